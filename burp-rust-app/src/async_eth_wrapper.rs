@@ -0,0 +1,34 @@
+use burp_rust_lib::traits::net_if::NetIf;
+use embedded_svc::ipv4::IpInfo;
+use esp_idf_svc::eth::{AsyncEth, EspEth};
+use esp_idf_sys::*;
+
+pub struct AsyncEthWrapper<'a, T>(pub AsyncEth<EspEth<'a, T>>);
+
+impl<T> NetIf for AsyncEthWrapper<'_, T> {
+    type Error = EspError;
+
+    async fn start(&mut self) -> Result<(), EspError> {
+        self.0.start().await
+    }
+
+    async fn stop(&mut self) -> Result<(), EspError> {
+        self.0.stop().await
+    }
+
+    async fn wait_netif_up(&self) -> Result<(), EspError> {
+        self.0.wait_netif_up().await
+    }
+
+    fn is_connected(&self) -> Result<bool, Self::Error> {
+        self.0.is_connected()
+    }
+
+    fn get_ip_info(&self) -> Result<IpInfo, Self::Error> {
+        self.0.eth().netif().get_ip_info()
+    }
+
+    fn set_default_route(&mut self) -> Result<(), Self::Error> {
+        esp!(unsafe { esp_netif_set_default_netif(self.0.eth().netif().handle()) })
+    }
+}
@@ -1,35 +1,154 @@
+use std::ffi::c_void;
+use std::ops::RangeInclusive;
+use std::ptr;
+use std::sync::atomic::{AtomicU16, Ordering};
+
+use burp_rust_lib::traits::net_if::NetIf;
 use burp_rust_lib::traits::wifi::Wifi;
-use embedded_svc::ipv4::IpInfo;
-use embedded_svc::wifi::{AccessPointInfo, Configuration};
+use embedded_svc::ipv4::{IpInfo, Ipv6Addr};
+use embedded_svc::wifi::{AccessPointInfo, AuthMethod, Configuration, SecondaryChannel};
 use esp_idf_svc::wifi::{AsyncWifi, EspWifi};
-use esp_idf_sys::EspError;
+use esp_idf_sys::*;
+
+// 0, which is not a wifi_err_reason_t, until the station first loses an access point
+static DISCONNECT_REASON: AtomicU16 = AtomicU16::new(0);
 
 pub struct AsyncWifiWrapper<'a>(pub AsyncWifi<EspWifi<'a>>);
 
-impl Wifi for AsyncWifiWrapper<'_> {
-    type Error = EspError;
+unsafe extern "C" fn on_sta_disconnected(_: *mut c_void, _: esp_event_base_t, _: i32, data: *mut c_void) {
+    let event = &*(data as *const wifi_event_sta_disconnected_t);
+    DISCONNECT_REASON.store(event.reason as u16, Ordering::Relaxed);
+}
 
-    fn set_configuration(&mut self, conf: &Configuration) -> Result<(), Self::Error> {
-        self.0.set_configuration(conf)
+impl AsyncWifiWrapper<'_> {
+    // Needed once for disconnect_reason to report anything
+    pub fn subscribe_disconnect_reasons(&self) -> Result<(), EspError> {
+        esp!(unsafe {
+            esp_event_handler_register(
+                WIFI_EVENT,
+                wifi_event_t_WIFI_EVENT_STA_DISCONNECTED as i32,
+                Some(on_sta_disconnected),
+                ptr::null_mut(),
+            )
+        })
     }
+}
+
+impl NetIf for AsyncWifiWrapper<'_> {
+    type Error = EspError;
 
     async fn start(&mut self) -> Result<(), EspError> {
         self.0.start().await
     }
 
+    async fn stop(&mut self) -> Result<(), EspError> {
+        self.0.stop().await
+    }
+
+    async fn wait_netif_up(&self) -> Result<(), EspError> {
+        self.0.wait_netif_up().await
+    }
+
+    fn is_connected(&self) -> Result<bool, Self::Error> {
+        self.0.is_connected()
+    }
+
+    fn get_ip_info(&self) -> Result<IpInfo, Self::Error> {
+        self.0.wifi().sta_netif().get_ip_info()
+    }
+
+    fn set_default_route(&mut self) -> Result<(), Self::Error> {
+        esp!(unsafe { esp_netif_set_default_netif(self.0.wifi().sta_netif().handle()) })
+    }
+}
+
+impl Wifi for AsyncWifiWrapper<'_> {
+    fn set_configuration(&mut self, conf: &Configuration) -> Result<(), Self::Error> {
+        self.0.set_configuration(conf)
+    }
+
     async fn scan(&mut self) -> Result<Vec<AccessPointInfo>, EspError> {
         self.0.scan().await
     }
 
+    // The link-local address lets mdns answer over IPv6, SLAAC adds the rest
     async fn connect(&mut self) -> Result<(), EspError> {
-        self.0.connect().await
+        // a failed attempt also reports a reason, which belongs to this attempt rather than the last drop
+        DISCONNECT_REASON.store(0, Ordering::Relaxed);
+        self.0.connect().await?;
+        esp!(unsafe { esp_netif_create_ip6_linklocal(self.0.wifi().sta_netif().handle()) })
     }
 
-    async fn wait_netif_up(&self) -> Result<(), EspError> {
-        self.0.wait_netif_up().await
+    async fn disconnect(&mut self) -> Result<(), EspError> {
+        self.0.disconnect().await
     }
 
-    fn get_ip_info(&self) -> Result<IpInfo, Self::Error> {
-        self.0.wifi().sta_netif().get_ip_info()
+    fn ap_info(&self) -> Result<AccessPointInfo, Self::Error> {
+        let mut record: wifi_ap_record_t = Default::default();
+        esp!(unsafe { esp_wifi_sta_get_ap_info(&mut record) })?;
+        Ok(access_point_info(&record))
+    }
+
+    fn rssi(&self) -> Result<i8, Self::Error> {
+        self.ap_info().map(|ap_info| ap_info.signal_strength)
+    }
+
+    // Taken so that a reason is only reported for the drop it came with
+    fn disconnect_reason(&self) -> Option<u16> {
+        match DISCONNECT_REASON.swap(0, Ordering::Relaxed) {
+            0 => None,
+            reason => Some(reason),
+        }
+    }
+
+    fn ipv6_addresses(&self) -> Result<Vec<Ipv6Addr>, Self::Error> {
+        // lwIP writes as many as it is configured to keep, the network keeps the first few of them
+        let mut addresses: [esp_ip6_addr_t; CONFIG_LWIP_IPV6_NUM_ADDRESSES as usize] = Default::default();
+        let count = unsafe { esp_netif_get_all_ip6(self.0.wifi().sta_netif().handle(), addresses.as_mut_ptr()) };
+        Ok(addresses[..(count.max(0) as usize).min(addresses.len())].iter().map(|address| {
+            let mut octets = [0_u8; 16];
+            for (chunk, word) in octets.chunks_mut(4).zip(address.addr) {
+                // already in network order
+                chunk.copy_from_slice(&word.to_ne_bytes());
+            }
+            Ipv6Addr::from(octets)
+        }).collect())
+    }
+
+    fn channels(&self) -> Result<RangeInclusive<u8>, Self::Error> {
+        let mut country: wifi_country_t = Default::default();
+        esp!(unsafe { esp_wifi_get_country(&mut country) })?;
+        Ok(country.schan..=country.schan + country.nchan.saturating_sub(1))
+    }
+}
+
+fn access_point_info(record: &wifi_ap_record_t) -> AccessPointInfo {
+    let ssid_length = record.ssid.iter().position(|byte| *byte == 0).unwrap_or(record.ssid.len());
+    #[allow(non_upper_case_globals)]
+    let auth_method = match record.authmode {
+        wifi_auth_mode_t_WIFI_AUTH_WEP => AuthMethod::WEP,
+        wifi_auth_mode_t_WIFI_AUTH_WPA_PSK => AuthMethod::WPA,
+        wifi_auth_mode_t_WIFI_AUTH_WPA2_PSK => AuthMethod::WPA2Personal,
+        wifi_auth_mode_t_WIFI_AUTH_WPA_WPA2_PSK => AuthMethod::WPAWPA2Personal,
+        wifi_auth_mode_t_WIFI_AUTH_WPA2_ENTERPRISE => AuthMethod::WPA2Enterprise,
+        wifi_auth_mode_t_WIFI_AUTH_WPA3_PSK => AuthMethod::WPA3Personal,
+        wifi_auth_mode_t_WIFI_AUTH_WPA2_WPA3_PSK => AuthMethod::WPA2WPA3Personal,
+        wifi_auth_mode_t_WIFI_AUTH_WAPI_PSK => AuthMethod::WAPIPersonal,
+        _ => AuthMethod::None,
+    };
+    #[allow(non_upper_case_globals)]
+    let secondary_channel = match record.second {
+        wifi_second_chan_t_WIFI_SECOND_CHAN_ABOVE => SecondaryChannel::Above,
+        wifi_second_chan_t_WIFI_SECOND_CHAN_BELOW => SecondaryChannel::Below,
+        _ => SecondaryChannel::None,
+    };
+    AccessPointInfo {
+        ssid: core::str::from_utf8(&record.ssid[..ssid_length]).unwrap_or_default().into(),
+        bssid: record.bssid,
+        channel: record.primary,
+        secondary_channel,
+        signal_strength: record.rssi,
+        protocols: Default::default(),
+        auth_method,
     }
 }
@@ -0,0 +1,16 @@
+use std::time::Duration;
+
+use burp_rust_lib::traits::timer::Timer;
+use embassy_time::Instant;
+
+pub struct EmbassyTimerWrapper;
+
+impl Timer for EmbassyTimerWrapper {
+    fn now(&self) -> Duration {
+        Duration::from_micros(Instant::now().as_micros())
+    }
+
+    async fn after(&mut self, duration: Duration) {
+        embassy_time::Timer::after(embassy_time::Duration::from_micros(duration.as_micros() as u64)).await
+    }
+}
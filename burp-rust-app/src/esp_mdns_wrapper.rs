@@ -2,9 +2,20 @@ use std::time::Duration;
 
 use burp_rust_lib::traits::mdns::{Interface, Mdns, Protocol, QueryResult};
 use esp_idf_svc::mdns::{EspMdns, Interface as EspInterface, Protocol as EspProtocol, QueryResult as EspQueryResult};
-use esp_idf_sys::EspError;
+use esp_idf_sys::{esp_err_t, EspError, ESP_ERR_INVALID_STATE};
 
-pub struct EspMdnsWrapper(pub EspMdns);
+// None once freed
+pub struct EspMdnsWrapper(pub Option<EspMdns>);
+
+impl EspMdnsWrapper {
+    fn mdns(&self) -> Result<&EspMdns, EspError> {
+        self.0.as_ref().ok_or_else(|| EspError::from(ESP_ERR_INVALID_STATE as esp_err_t).unwrap())
+    }
+
+    fn mdns_mut(&mut self) -> Result<&mut EspMdns, EspError> {
+        self.0.as_mut().ok_or_else(|| EspError::from(ESP_ERR_INVALID_STATE as esp_err_t).unwrap())
+    }
+}
 
 fn convert_query_result_interface(interface: &EspInterface) -> Interface {
     match interface {
@@ -26,23 +37,44 @@ impl Mdns for EspMdnsWrapper {
     type QueryResult = EspQueryResult;
 
     fn set_hostname(&mut self, hostname: &str) -> Result<(), Self::Error> {
-        self.0.set_hostname(hostname)
+        self.mdns_mut()?.set_hostname(hostname)
     }
 
     fn set_instance_name(&mut self, instance_name: &str) -> Result<(), Self::Error> {
-        self.0.set_instance_name(instance_name)
+        self.mdns_mut()?.set_instance_name(instance_name)
     }
 
     fn add_service(&mut self, instance_name: Option<&str>, service_type: &str, proto: &str, port: u16, txt: &[(&str, &str)]) -> Result<(), Self::Error> {
-        self.0.add_service(instance_name, service_type, proto, port, txt)
+        self.mdns_mut()?.add_service(instance_name, service_type, proto, port, txt)
+    }
+
+    fn remove_service(&mut self, service_type: &str, proto: &str) -> Result<(), Self::Error> {
+        self.mdns_mut()?.remove_service(service_type, proto)
+    }
+
+    fn remove_services(&mut self) -> Result<(), Self::Error> {
+        self.mdns_mut()?.remove_services()
+    }
+
+    // dropping EspMdns frees the responder
+    fn free(&mut self) {
+        self.0 = None;
     }
 
     fn query_ptr(&self, service_type: &str, proto: &str, timeout: Duration, max_results: usize, results: &mut [Self::QueryResult]) -> Result<usize, Self::Error> {
-        self.0.query_ptr(service_type, proto, timeout, max_results, results)
+        self.mdns()?.query_ptr(service_type, proto, timeout, max_results, results)
     }
 
     fn create_query_results<const N: usize>() -> heapless::Vec<Self::QueryResult, N> {
-        heapless::Vec::new()
+        (0..N).map(|_| EspQueryResult {
+            instance_name: None,
+            hostname: None,
+            port: 0,
+            txt: Vec::new(),
+            addr: Vec::new(),
+            interface: EspInterface::STA,
+            ip_protocol: EspProtocol::V4,
+        }).collect()
     }
 
     fn convert_query_result(query_result: &Self::QueryResult) -> QueryResult {
@@ -1,81 +1,176 @@
-use burp_rust_lib::traits::storage::Storage;
-use esp_idf_svc::nvs::EspNvs;
-use esp_idf_sys::EspError;
+use std::ffi::{CStr, CString};
+use std::ptr;
+
+use burp_rust_lib::traits::storage::{NamespaceStats, Storage, StorageStats, MAX_NAMESPACES};
+use esp_idf_svc::nvs::{EspNvs, EspNvsPartition, NvsPartitionId};
+use esp_idf_sys::{
+    esp, esp_err_t, nvs_close, nvs_entry_find, nvs_entry_info, nvs_entry_info_t, nvs_entry_next, nvs_get_stats,
+    nvs_get_used_entry_count, nvs_handle_t, nvs_iterator_t, nvs_open_from_partition, nvs_open_mode_t_NVS_READONLY,
+    nvs_release_iterator, nvs_stats_t, nvs_type_t_NVS_TYPE_ANY, EspError, ESP_ERR_NVS_NOT_FOUND, ESP_OK,
+};
+
+pub struct EspNvsWrapper<T: NvsPartitionId> {
+    nvs: EspNvs<T>,
+    partition_name: CString,
+    namespace: CString,
+}
 
-pub struct EspNvsWrapper<T: esp_idf_svc::nvs::NvsPartitionId>(pub EspNvs<T>);
+impl<T: NvsPartitionId> EspNvsWrapper<T> {
+    pub fn new(partition: EspNvsPartition<T>, namespace: &str, read_write: bool) -> Result<EspNvsWrapper<T>, EspError> {
+        let partition_name = CString::from(partition.name());
+        let nvs = EspNvs::new(partition, namespace, read_write)?;
+        Ok(EspNvsWrapper {
+            nvs,
+            partition_name,
+            namespace: CString::new(namespace).unwrap(),
+        })
+    }
+
+    fn namespace_used_entries(&self, namespace: &CStr) -> Result<usize, EspError> {
+        let mut handle: nvs_handle_t = 0;
+        esp!(unsafe {
+            nvs_open_from_partition(
+                self.partition_name.as_ptr(),
+                namespace.as_ptr(),
+                nvs_open_mode_t_NVS_READONLY,
+                &mut handle,
+            )
+        })?;
+        let mut used_entries: usize = 0;
+        let result = esp!(unsafe { nvs_get_used_entry_count(handle, &mut used_entries) });
+        unsafe { nvs_close(handle) };
+        result.map(|_| used_entries)
+    }
+
+    // Namespaces only exist in the partition while they hold keys, so they are found through them
+    fn namespace_names(&self) -> Result<heapless::Vec<CString, MAX_NAMESPACES>, EspError> {
+        let mut names = heapless::Vec::<CString, MAX_NAMESPACES>::new();
+        let mut iterator: nvs_iterator_t = ptr::null_mut();
+        let mut result = unsafe {
+            nvs_entry_find(self.partition_name.as_ptr(), ptr::null(), nvs_type_t_NVS_TYPE_ANY, &mut iterator)
+        };
+        while result == ESP_OK as esp_err_t {
+            let mut info: nvs_entry_info_t = Default::default();
+            result = unsafe { nvs_entry_info(iterator, &mut info) };
+            if result != ESP_OK as esp_err_t {
+                break;
+            }
+            let name = unsafe { CStr::from_ptr(info.namespace_name.as_ptr()) };
+            if !names.iter().any(|known| known.as_c_str() == name) {
+                // any further namespaces are only counted by nvs_get_stats
+                names.push(CString::from(name)).ok();
+            }
+            result = unsafe { nvs_entry_next(&mut iterator) };
+        }
+        unsafe { nvs_release_iterator(iterator) };
+        if result != ESP_ERR_NVS_NOT_FOUND as esp_err_t {
+            esp!(result)?;
+        }
+        Ok(names)
+    }
+
+    fn namespaces(&self) -> Result<heapless::Vec<NamespaceStats, MAX_NAMESPACES>, EspError> {
+        let mut namespaces = heapless::Vec::new();
+        for name in self.namespace_names()? {
+            let namespace = NamespaceStats {
+                name: heapless::String::from(name.to_str().unwrap_or("?")),
+                used_entries: self.namespace_used_entries(&name)?,
+            };
+            namespaces.push(namespace).ok();
+        }
+        Ok(namespaces)
+    }
+}
 
-impl<T: esp_idf_svc::nvs::NvsPartitionId> Storage for EspNvsWrapper<T> {
+impl<T: NvsPartitionId + Send + Sync + 'static> Storage for EspNvsWrapper<T> {
     type Error = EspError;
 
+    fn stats(&self) -> Result<StorageStats, Self::Error> {
+        let mut stats: nvs_stats_t = Default::default();
+        esp!(unsafe { nvs_get_stats(self.partition_name.as_ptr(), &mut stats) })?;
+        Ok(StorageStats {
+            used_entries: stats.used_entries,
+            free_entries: stats.free_entries,
+            total_entries: stats.total_entries,
+            namespace_count: stats.namespace_count,
+            namespace_used_entries: self.namespace_used_entries(&self.namespace)?,
+            namespaces: self.namespaces()?,
+        })
+    }
+
     fn get_blob<'a>(&self, name: &str, buf: &'a mut [u8]) -> Result<Option<&'a [u8]>, Self::Error> {
-        self.0.get_blob(name, buf)
+        self.nvs.get_blob(name, buf)
     }
 
     fn set_blob(&mut self, name: &str, val: &[u8]) -> Result<(), Self::Error> {
-        self.0.set_blob(name, val)
+        self.nvs.set_blob(name, val)
+    }
+
+    fn remove(&mut self, name: &str) -> Result<bool, Self::Error> {
+        self.nvs.remove(name)
     }
 
     fn get_u8(&self, name: &str) -> Result<Option<u8>, Self::Error> {
-        self.0.get_u8(name)
+        self.nvs.get_u8(name)
     }
 
     fn set_u8(&self, name: &str, val: u8) -> Result<(), Self::Error> {
-        self.0.set_u8(name, val)
+        self.nvs.set_u8(name, val)
     }
 
     fn get_i8(&self, name: &str) -> Result<Option<i8>, Self::Error> {
-        self.0.get_i8(name)
+        self.nvs.get_i8(name)
     }
 
     fn set_i8(&self, name: &str, val: i8) -> Result<(), Self::Error> {
-        self.0.set_i8(name, val)
+        self.nvs.set_i8(name, val)
     }
 
     fn get_u16(&self, name: &str) -> Result<Option<u16>, Self::Error> {
-        self.0.get_u16(name)
+        self.nvs.get_u16(name)
     }
 
     fn set_u16(&self, name: &str, val: u16) -> Result<(), Self::Error> {
-        self.0.set_u16(name, val)
+        self.nvs.set_u16(name, val)
     }
 
     fn get_i16(&self, name: &str) -> Result<Option<i16>, Self::Error> {
-        self.0.get_i16(name)
+        self.nvs.get_i16(name)
     }
 
     fn set_i16(&self, name: &str, val: i16) -> Result<(), Self::Error> {
-        self.0.set_i16(name, val)
+        self.nvs.set_i16(name, val)
     }
 
     fn get_u32(&self, name: &str) -> Result<Option<u32>, Self::Error> {
-        self.0.get_u32(name)
+        self.nvs.get_u32(name)
     }
 
     fn set_u32(&self, name: &str, val: u32) -> Result<(), Self::Error> {
-        self.0.set_u32(name, val)
+        self.nvs.set_u32(name, val)
     }
 
     fn get_i32(&self, name: &str) -> Result<Option<i32>, Self::Error> {
-        self.0.get_i32(name)
+        self.nvs.get_i32(name)
     }
 
     fn set_i32(&self, name: &str, val: i32) -> Result<(), Self::Error> {
-        self.0.set_i32(name, val)
+        self.nvs.set_i32(name, val)
     }
 
     fn get_u64(&self, name: &str) -> Result<Option<u64>, Self::Error> {
-        self.0.get_u64(name)
+        self.nvs.get_u64(name)
     }
 
     fn set_u64(&self, name: &str, val: u64) -> Result<(), Self::Error> {
-        self.0.set_u64(name, val)
+        self.nvs.set_u64(name, val)
     }
 
     fn get_i64(&self, name: &str) -> Result<Option<i64>, Self::Error> {
-        self.0.get_i64(name)
+        self.nvs.get_i64(name)
     }
 
     fn set_i64(&self, name: &str, val: i64) -> Result<(), Self::Error> {
-        self.0.set_i64(name, val)
+        self.nvs.set_i64(name, val)
     }
 }
@@ -0,0 +1,40 @@
+use std::mem;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use burp_rust_lib::traits::ping::{Ping, PingStats};
+use burp_rust_lib::workers;
+use embedded_svc::ipv4::Ipv4Addr;
+use embedded_svc::ping::{Configuration, Reply};
+use esp_idf_svc::ping::EspPing;
+use esp_idf_sys::*;
+
+pub struct EspPingWrapper(pub EspPing);
+
+impl Ping for EspPingWrapper {
+    type Error = EspError;
+
+    // ICMP echo through lwIP, which only pings IPv4 addresses. ping_details blocks until the last
+    // reply or timeout, so it is waited on from the sockets worker.
+    async fn ping(&mut self, address: IpAddr, count: u16, timeout: Duration) -> Result<PingStats, Self::Error> {
+        let IpAddr::V4(address) = address else {
+            return Err(EspError::from(ESP_ERR_NOT_SUPPORTED as esp_err_t).unwrap());
+        };
+        let configuration = Configuration {
+            count: count as u32,
+            timeout,
+            ..Default::default()
+        };
+        let mut ping = mem::take(&mut self.0);
+        let (ping, result) = workers::sockets().run(move || {
+            let mut stats = PingStats::default();
+            let result = ping.ping_details(Ipv4Addr::from(address.octets()), &configuration, |_, reply| match reply {
+                Reply::Success(info) => stats.add(Some(info.elapsed_time)),
+                Reply::Timeout => stats.add(None),
+            });
+            (ping, result.map(|_| stats))
+        }).await;
+        self.0 = ping;
+        result
+    }
+}
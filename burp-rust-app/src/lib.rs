@@ -2,4 +2,8 @@
 
 pub mod esp_nvs_wrapper;
 pub mod async_wifi_wrapper;
+pub mod async_eth_wrapper;
 pub mod esp_mdns_wrapper;
+pub mod esp_ping_wrapper;
+pub mod embassy_timer_wrapper;
+pub mod uart_byte_stream;
@@ -1,26 +1,51 @@
 use std::ffi::CStr;
 use std::future::Future;
+use std::net::SocketAddr;
 use std::str::Utf8Error;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use burp_rust_lib::config::Config;
-use burp_rust_lib::name::init_name;
+use burp_rust_lib::name::{get_name, init_name};
 use burp_rust_lib::network::{Network, NetworkError};
+use burp_rust_lib::network::soft_ap::SoftApPolicy;
+use burp_rust_lib::network::txt::BuildInfo;
+use burp_rust_lib::provisioning::captive_portal::CaptivePortal;
+use burp_rust_lib::provisioning::improv::{DeviceInfo, Improv};
+use burp_rust_lib::storage::cached_storage::CachedStorage;
+use burp_rust_lib::storage::log_storage_stats;
+use burp_rust_lib::time::{MonotonicClock, SharedClock, TimeKeeper};
+use burp_rust_lib::time::sntp::SntpClient;
+use burp_rust_lib::traits::byte_stream::SerialPort;
 use burp_rust_lib::traits::read_write::ReadWrite;
 use edge_executor::SpawnError;
-use esp_idf_hal::prelude::Peripherals;
+use embedded_svc::ipv4::Ipv4Addr;
+use esp_idf_hal::gpio::{AnyIOPin, Gpio1, Gpio3};
+use esp_idf_hal::modem::Modem;
+use esp_idf_hal::prelude::*;
 use esp_idf_hal::task::executor::EspExecutor;
+use esp_idf_hal::uart::{config, UartDriver, UART0};
 use esp_idf_svc::eventloop::EspSystemEventLoop;
 use esp_idf_svc::mdns::EspMdns;
-use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, NvsDefault};
 use esp_idf_svc::timer::EspTaskTimerService;
 use esp_idf_svc::wifi::{AsyncWifi, EspWifi};
 use esp_idf_sys::{esp, esp_base_mac_addr_get, esp_err_to_name, EspError};
 use log::*;
 
 use burp_rust_app::async_wifi_wrapper::AsyncWifiWrapper;
+use burp_rust_app::embassy_timer_wrapper::EmbassyTimerWrapper;
 use burp_rust_app::esp_mdns_wrapper::EspMdnsWrapper;
 use burp_rust_app::esp_nvs_wrapper::EspNvsWrapper;
+use burp_rust_app::uart_byte_stream::UartByteStream;
+
+// comfortably more than the config keys
+const STORAGE_CACHE_ENTRIES: usize = 16;
+
+type AppStorage = CachedStorage<EspNvsWrapper<NvsDefault>, STORAGE_CACHE_ENTRIES>;
+
+// the console port, which is where Improv clients such as ESP Web Tools talk to the device
+const IMPROV_BAUD_RATE: u32 = 115_200;
 
 #[toml_cfg::toml_config]
 pub struct WifiConfig {
@@ -28,6 +53,9 @@ pub struct WifiConfig {
     wifi_ssid: &'static str,
     #[default("")]
     wifi_psk: &'static str,
+    // protects the fallback access point, which stays closed while credentials are stored if empty
+    #[default("")]
+    soft_ap_passphrase: &'static str,
 }
 
 fn print_utf8_error(utf8_error: Utf8Error) {
@@ -48,10 +76,25 @@ async fn print_async_error(
             NetworkError::Utf8Error(utf8_error) => print_utf8_error(utf8_error),
             NetworkError::WifiError(esp_error) => print_esp_error(esp_error),
             NetworkError::MdnsError(esp_error) => print_esp_error(esp_error),
+            NetworkError::EthernetError(never) => match never {},
         }
     }
 }
 
+async fn run_network(
+    network: &mut Network<'_, AppStorage, AsyncWifiWrapper<'_>, EspMdnsWrapper>,
+    nvs: Arc<Mutex<AppStorage>>,
+) -> Result<(), NetworkError<EspError, EspError>> {
+    match network.start().await {
+        Err(NetworkError::Utf8Error(error)) => return Err(NetworkError::Utf8Error(error)),
+        // the supervisor keeps retrying and falls back to the soft access point
+        Err(error) => warn!("Network start failed: {:?}", error),
+        Ok(()) => {}
+    }
+    nvs.lock().unwrap().log_stats();
+    Err(network.supervise(&mut EmbassyTimerWrapper).await)
+}
+
 fn main() -> Result<(), SpawnError> {
     // It is necessary to call this function once. Otherwise some patches to the runtime
     // implemented by esp-idf-sys might not link properly. See https://github.com/esp-rs/esp-idf-template/issues/71
@@ -63,28 +106,83 @@ fn main() -> Result<(), SpawnError> {
     init_name(&base_mac_address);
 
     let nvs = init_nvs();
+    if let Err(esp_error) = log_storage_stats(&*nvs.lock().unwrap()) {
+        print_esp_error(esp_error);
+    }
     let config = init_config(nvs.clone());
-    let wifi = init_async_wifi();
+    nvs.lock().unwrap().log_stats();
+    let peripherals = Peripherals::take().unwrap();
+    let wifi = init_async_wifi(peripherals.modem);
     let mdns = init_mdns();
-    let mut network = Network::new(config.clone(), wifi, mdns);
+    let mut network = Network::new(config.clone(), wifi, mdns)
+        .with_soft_ap_policy(init_soft_ap_policy())
+        .with_captive_portal(init_captive_portal())
+        .with_timer(EmbassyTimerWrapper)
+        .with_improv(init_improv(peripherals.uart0, peripherals.pins.gpio1, peripherals.pins.gpio3))
+        .with_time_sync(init_time_keeper())
+        .with_build_info(BuildInfo {
+            firmware_version: env!("CARGO_PKG_VERSION"),
+            hardware_model: "esp32",
+            capabilities: vec!["captive_portal", "improv", "time_sync"],
+        });
 
     let executor = EspExecutor::new();
     let mut tasks = heapless::Vec::<_, 1>::new();
-    executor.spawn_local_collect(print_async_error(network.start()), &mut tasks)?;
+    executor.spawn_local_collect(print_async_error(run_network(&mut network, nvs.clone())), &mut tasks)?;
     executor.run_tasks(|| true, tasks);
     println!("Finished");
     Ok(())
 }
 
+fn init_soft_ap_policy() -> SoftApPolicy {
+    let passphrase = WIFI_CONFIG.soft_ap_passphrase;
+    SoftApPolicy {
+        passphrase: (8..=63).contains(&passphrase.len()).then(|| heapless::String::from(passphrase)),
+        ..Default::default()
+    }
+}
+
+fn init_captive_portal() -> CaptivePortal {
+    // bound only while the soft access point is up
+    CaptivePortal::new(
+        SocketAddr::from(([0, 0, 0, 0], 80)),
+        SocketAddr::from(([0, 0, 0, 0], 53)),
+        // the default address of the soft access point netif
+        Ipv4Addr::new(192, 168, 71, 1),
+    )
+}
+
+fn init_improv(uart: UART0, tx: Gpio1, rx: Gpio3) -> Improv<'static, SerialPort> {
+    let uart = UartDriver::new(
+        uart,
+        tx,
+        rx,
+        Option::<AnyIOPin>::None,
+        Option::<AnyIOPin>::None,
+        &config::Config::new().baudrate(Hertz(IMPROV_BAUD_RATE)),
+    ).unwrap();
+    let redirect_url = format!("http://{}.local/", get_name());
+    Improv::new(Box::new(UartByteStream(uart)), DeviceInfo {
+        firmware_name: "burp",
+        firmware_version: env!("CARGO_PKG_VERSION"),
+        chip: "ESP32",
+        device_name: get_name(),
+    }).with_redirect_url(Box::leak(redirect_url.into_boxed_str()))
+}
+
+fn init_time_keeper() -> TimeKeeper<SharedClock, SntpClient> {
+    let clock: SharedClock = Arc::new(Mutex::new(MonotonicClock::new()));
+    TimeKeeper::new(clock, SntpClient::new(Duration::from_secs(2)))
+}
+
 fn init_mdns() -> EspMdnsWrapper {
-    EspMdnsWrapper(EspMdns::take().unwrap())
+    EspMdnsWrapper(Some(EspMdns::take().unwrap()))
 }
 
-fn init_async_wifi() -> AsyncWifiWrapper<'static> {
-    let peripherals = Peripherals::take().unwrap();
+fn init_async_wifi(modem: Modem) -> AsyncWifiWrapper<'static> {
     let esp_system_event_loop = EspSystemEventLoop::take().unwrap();
-    AsyncWifiWrapper(AsyncWifi::wrap(
-        EspWifi::new(peripherals.modem, esp_system_event_loop.clone(), None).unwrap(),
+    let wifi = AsyncWifiWrapper(AsyncWifi::wrap(
+        EspWifi::new(modem, esp_system_event_loop.clone(), None).unwrap(),
         // EspWifi::wrap_all(
         //     WifiDriver::new(peripherals.modem, esp_system_event_loop.clone(), None).unwrap(),
         //     EspNetif::new_with_conf(&NetifConfiguration {
@@ -101,24 +199,26 @@ fn init_async_wifi() -> AsyncWifiWrapper<'static> {
         // ).unwrap(),
         esp_system_event_loop.clone(),
         EspTaskTimerService::new().unwrap(),
-    ).unwrap())
+    ).unwrap());
+    wifi.subscribe_disconnect_reasons().unwrap();
+    wifi
 }
 
-fn init_config(nvs: Arc<Mutex<EspNvsWrapper<NvsDefault>>>) -> Arc<Mutex<Config<'static, EspNvsWrapper<NvsDefault>>>> {
+fn init_config(nvs: Arc<Mutex<AppStorage>>) -> Arc<Mutex<Config<'static, AppStorage>>> {
     let mut config = Config::new(nvs, WIFI_CONFIG.wifi_ssid, WIFI_CONFIG.wifi_psk);
     config.read().unwrap();
     Arc::new(Mutex::new(config))
 }
 
-fn init_nvs() -> Arc<Mutex<EspNvsWrapper<NvsDefault>>> {
+fn init_nvs() -> Arc<Mutex<AppStorage>> {
     let esp_nvs_partition = EspDefaultNvsPartition::take()
         .unwrap();
-    let esp_nvs = EspNvs::new(
+    let esp_nvs = EspNvsWrapper::new(
         esp_nvs_partition,
         "burptech",
         true,
     ).unwrap();
-    Arc::new(Mutex::new(EspNvsWrapper(esp_nvs)))
+    Arc::new(Mutex::new(CachedStorage::new(esp_nvs)))
 }
 
 fn get_base_mac_address() -> Result<[u8; 6], EspError> {
@@ -0,0 +1,29 @@
+use std::io;
+
+use burp_rust_lib::traits::byte_stream::ByteStream;
+use esp_idf_hal::delay::NON_BLOCK;
+use esp_idf_hal::uart::UartDriver;
+use esp_idf_sys::EspError;
+
+pub struct UartByteStream(pub UartDriver<'static>);
+
+fn to_io_error(error: EspError) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, error)
+}
+
+impl ByteStream for UartByteStream {
+    type Error = io::Error;
+
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Self::Error> {
+        self.0.read(buffer, NON_BLOCK).map_err(to_io_error)
+    }
+
+    fn write_all(&mut self, mut data: &[u8]) -> Result<(), Self::Error> {
+        // the driver returns once the bytes fit in its transmit buffer, which may not be all of them
+        while !data.is_empty() {
+            let written = self.0.write(data).map_err(to_io_error)?;
+            data = &data[written..];
+        }
+        Ok(())
+    }
+}
@@ -1,10 +1,20 @@
 use std::sync::{Arc, Mutex};
 
+use embedded_svc::wifi::AuthMethod;
+use thiserror::Error;
+
 use crate::config::blob_config::BlobConfig;
+use crate::config::u8_config::U8Config;
+use crate::network::discovery::IpFamily;
+use crate::network::services::{is_storable_name, MdnsService};
+use crate::network::txt::{Role, FINGERPRINT_BYTES};
+use crate::time::timezone::{TimeZone, TimeZoneError, TZ_MAX_BYTES};
+use crate::traits::async_read_write::AsyncReadWrite;
 use crate::traits::read_write::ReadWrite;
 use crate::traits::storage::Storage;
 
 mod blob_config;
+mod u8_config;
 
 const SSID_FIELD: &str = "ssid";
 const SSID_MAX_BYTES: usize = 32;
@@ -12,9 +22,96 @@ const SSID_MAX_BYTES: usize = 32;
 const PSK_FIELD: &str = "psk";
 const PSK_MAX_BYTES: usize = 64;
 
+const HIDDEN_FIELD: &str = "hidden";
+
+const BSSID_FIELD: &str = "bssid";
+
+const BSSID_BYTES: usize = 6;
+
+const LAST_ACCESS_POINT_FIELD: &str = "last_ap";
+// channel, bssid and auth method, written as one blob so they are never mixed from different connections
+const LAST_ACCESS_POINT_BYTES: usize = 1 + BSSID_BYTES + 1;
+
+const IP_FAMILY_FIELD: &str = "ip_family";
+
+const NTP_SERVERS_FIELD: &str = "ntp_servers";
+const NTP_SERVERS_MAX_BYTES: usize = 96;
+const DEFAULT_NTP_SERVERS: &str = "pool.ntp.org,time.google.com";
+
+const TIMEZONE_FIELD: &str = "timezone";
+const DEFAULT_TIMEZONE: &str = "UTC0";
+
+const MDNS_SERVICES_FIELD: &str = "mdns_services";
+const MDNS_SERVICES_MAX_BYTES: usize = 256;
+const DEFAULT_MDNS_SERVICES: &str = "_burptech._tcp:1234";
+
+const MDNS_QUERY_FIELD: &str = "mdns_query";
+const MDNS_QUERY_MAX_BYTES: usize = 32;
+const DEFAULT_MDNS_QUERY: &str = "_burptech._tcp";
+
+const ROLE_FIELD: &str = "role";
+
+const FINGERPRINT_FIELD: &str = "fingerprint";
+
+const AUTH_METHOD_FIELD: &str = "auth_method";
+// stored as 0 to detect the auth method from the scan results, otherwise 1 + index in AUTH_METHODS
+pub const AUTH_METHOD_AUTO: u8 = 0;
+const AUTH_METHODS: [AuthMethod; 9] = [
+    AuthMethod::None,
+    AuthMethod::WEP,
+    AuthMethod::WPA,
+    AuthMethod::WPA2Personal,
+    AuthMethod::WPAWPA2Personal,
+    AuthMethod::WPA2Enterprise,
+    AuthMethod::WPA3Personal,
+    AuthMethod::WPA2WPA3Personal,
+    AuthMethod::WAPIPersonal,
+];
+
+// Where the last connection was made, to connect to without scanning first
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LastAccessPoint {
+    pub channel: u8,
+    pub bssid: [u8; BSSID_BYTES],
+    pub auth_method: AuthMethod,
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("Time servers must fit in {NTP_SERVERS_MAX_BYTES} bytes")]
+pub struct NtpServersTooLong;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum MdnsConfigError {
+    #[error("{0} must fit in {1} bytes")]
+    TooLong(&'static str, usize),
+    #[error("{0} can not be stored, names may not contain '.', ':', ';', '=' or line breaks and TXT values no ';' or line breaks")]
+    Unstorable(String),
+}
+
 pub struct Config<'a, S> {
     pub ssid: BlobConfig<'a, S, SSID_MAX_BYTES>,
     pub psk: BlobConfig<'a, S, PSK_MAX_BYTES>,
+    pub auth_method: U8Config<'a, S>,
+    // hidden networks do not show up in scans, stored as 0 or 1
+    pub hidden: U8Config<'a, S>,
+    // only this access point is connected to when set, empty otherwise
+    pub bssid: BlobConfig<'a, S, BSSID_BYTES>,
+    // where the last connection was made, empty when unknown
+    pub last_ap: BlobConfig<'a, S, LAST_ACCESS_POINT_BYTES>,
+    // peers are connected to over this family where they have an address in it, 0 for IPv4 and 1 for IPv6
+    pub ip_family: U8Config<'a, S>,
+    // comma separated, each a host name or address with an optional port
+    pub ntp_servers: BlobConfig<'a, S, NTP_SERVERS_MAX_BYTES>,
+    // a POSIX TZ string, eg. CET-1CEST,M3.5.0,M10.5.0/3
+    pub timezone: BlobConfig<'a, S, TZ_MAX_BYTES>,
+    // advertised under the device name, one per line, eg. _http._tcp:80;path=/
+    pub mdns_services: BlobConfig<'a, S, MDNS_SERVICES_MAX_BYTES>,
+    // the service browsed for peers, eg. _burptech._tcp
+    pub mdns_query: BlobConfig<'a, S, MDNS_QUERY_MAX_BYTES>,
+    // index in Role::ALL
+    pub role: U8Config<'a, S>,
+    // of the device identity, empty until provisioned
+    pub fingerprint: BlobConfig<'a, S, FINGERPRINT_BYTES>,
 }
 
 impl<S: Storage> Config<'_, S> {
@@ -22,28 +119,449 @@ impl<S: Storage> Config<'_, S> {
         Config {
             ssid: BlobConfig::new(storage.clone(), SSID_FIELD, default_ssid.as_bytes()),
             psk: BlobConfig::new(storage.clone(), PSK_FIELD, default_psk.as_bytes()),
+            auth_method: U8Config::new(storage.clone(), AUTH_METHOD_FIELD, AUTH_METHOD_AUTO),
+            hidden: U8Config::new(storage.clone(), HIDDEN_FIELD, 0),
+            bssid: BlobConfig::new(storage.clone(), BSSID_FIELD, &[]),
+            last_ap: BlobConfig::new(storage.clone(), LAST_ACCESS_POINT_FIELD, &[]),
+            ip_family: U8Config::new(storage.clone(), IP_FAMILY_FIELD, 0),
+            ntp_servers: BlobConfig::new(storage.clone(), NTP_SERVERS_FIELD, DEFAULT_NTP_SERVERS.as_bytes()),
+            timezone: BlobConfig::new(storage.clone(), TIMEZONE_FIELD, DEFAULT_TIMEZONE.as_bytes()),
+            mdns_services: BlobConfig::new(storage.clone(), MDNS_SERVICES_FIELD, DEFAULT_MDNS_SERVICES.as_bytes()),
+            mdns_query: BlobConfig::new(storage.clone(), MDNS_QUERY_FIELD, DEFAULT_MDNS_QUERY.as_bytes()),
+            role: U8Config::new(storage.clone(), ROLE_FIELD, 0),
+            fingerprint: BlobConfig::new(storage.clone(), FINGERPRINT_FIELD, &[]),
         }
     }
+
+    // None when the auth method should be detected from the scan results
+    pub fn auth_method_override(&self) -> Option<AuthMethod> {
+        match self.auth_method.get() {
+            AUTH_METHOD_AUTO => None,
+            value => AUTH_METHODS.get(value as usize - 1).copied(),
+        }
+    }
+
+    pub fn set_auth_method_override(&mut self, auth_method: Option<AuthMethod>) {
+        let value = auth_method
+            .and_then(|auth_method| AUTH_METHODS.iter().position(|known| *known == auth_method))
+            .map_or(AUTH_METHOD_AUTO, |index| index as u8 + 1);
+        self.auth_method.set(value);
+    }
+
+    pub fn is_hidden(&self) -> bool {
+        self.hidden.get() != 0
+    }
+
+    pub fn set_hidden(&mut self, hidden: bool) {
+        self.hidden.set(hidden as u8);
+    }
+
+    pub fn pinned_bssid(&self) -> Option<[u8; BSSID_BYTES]> {
+        self.bssid.get().try_into().ok()
+    }
+
+    // The last access point is forgotten as it may not be the pinned one
+    pub fn set_pinned_bssid(&mut self, bssid: Option<[u8; BSSID_BYTES]>) {
+        self.bssid.set(bssid.as_ref().map_or(&[], |bssid| &bssid[..]));
+        self.set_last_access_point(None);
+    }
+
+    // None when unknown or when the stored record is not one this version wrote
+    pub fn last_access_point(&self) -> Option<LastAccessPoint> {
+        let record: &[u8; LAST_ACCESS_POINT_BYTES] = self.last_ap.get().try_into().ok()?;
+        let (channel, rest) = record.split_first()?;
+        let (auth_method, bssid) = rest.split_last()?;
+        if *channel == 0 {
+            return None;
+        }
+        Some(LastAccessPoint {
+            channel: *channel,
+            bssid: bssid.try_into().ok()?,
+            auth_method: *AUTH_METHODS.get(*auth_method as usize)?,
+        })
+    }
+
+    pub fn set_last_access_point(&mut self, access_point: Option<LastAccessPoint>) {
+        let record = access_point.and_then(|access_point| {
+            let auth_method = AUTH_METHODS.iter().position(|known| *known == access_point.auth_method)?;
+            let mut record = [0_u8; LAST_ACCESS_POINT_BYTES];
+            record[0] = access_point.channel;
+            record[1..=BSSID_BYTES].copy_from_slice(&access_point.bssid);
+            record[BSSID_BYTES + 1] = auth_method as u8;
+            Some(record)
+        });
+        self.last_ap.set(record.as_ref().map_or(&[], |record| &record[..]));
+    }
+
+    pub fn preferred_family(&self) -> IpFamily {
+        match self.ip_family.get() {
+            1 => IpFamily::V6,
+            _ => IpFamily::V4,
+        }
+    }
+
+    pub fn set_preferred_family(&mut self, family: IpFamily) {
+        self.ip_family.set(match family {
+            IpFamily::V4 => 0,
+            IpFamily::V6 => 1,
+        });
+    }
+
+    // Invalid UTF-8 leaves no servers
+    pub fn ntp_servers(&self) -> Vec<&str> {
+        std::str::from_utf8(self.ntp_servers.get())
+            .map_or(Vec::new(), |servers| servers.split(',').map(str::trim).filter(|server| !server.is_empty()).collect())
+    }
+
+    pub fn set_ntp_servers(&mut self, servers: &[&str]) -> Result<(), NtpServersTooLong> {
+        let servers = servers.join(",");
+        if servers.len() > NTP_SERVERS_MAX_BYTES {
+            return Err(NtpServersTooLong);
+        }
+        self.ntp_servers.set(servers.as_bytes());
+        Ok(())
+    }
+
+    // UTC when what was stored does not parse
+    pub fn timezone(&self) -> TimeZone {
+        std::str::from_utf8(self.timezone.get())
+            .ok()
+            .and_then(|tz| TimeZone::parse(tz).ok())
+            .unwrap_or_else(TimeZone::utc)
+    }
+
+    pub fn set_timezone(&mut self, tz: &str) -> Result<TimeZone, TimeZoneError> {
+        let timezone = TimeZone::parse(tz)?;
+        self.timezone.set(tz.as_bytes());
+        Ok(timezone)
+    }
+
+    // Records that do not parse are left out
+    pub fn mdns_services(&self) -> Vec<MdnsService> {
+        std::str::from_utf8(self.mdns_services.get())
+            .map_or(Vec::new(), |records| records.lines().filter_map(MdnsService::from_record).collect())
+    }
+
+    pub fn set_mdns_services(&mut self, services: &[MdnsService]) -> Result<(), MdnsConfigError> {
+        let records = services.iter()
+            .map(|service| service.to_record().ok_or_else(|| MdnsConfigError::Unstorable(format!("{}.{}", service.service_type, service.proto))))
+            .collect::<Result<Vec<_>, _>>()?
+            .join("\n");
+        if records.len() > MDNS_SERVICES_MAX_BYTES {
+            return Err(MdnsConfigError::TooLong("mDNS services", MDNS_SERVICES_MAX_BYTES));
+        }
+        self.mdns_services.set(records.as_bytes());
+        Ok(())
+    }
+
+    // Service type and proto, the default when what was stored does not parse
+    pub fn mdns_query(&self) -> (&str, &str) {
+        std::str::from_utf8(self.mdns_query.get())
+            .ok()
+            .and_then(|query| query.split_once('.'))
+            .unwrap_or_else(|| DEFAULT_MDNS_QUERY.split_once('.').unwrap())
+    }
+
+    pub fn set_mdns_query(&mut self, service_type: &str, proto: &str) -> Result<(), MdnsConfigError> {
+        if !is_storable_name(service_type) || !is_storable_name(proto) {
+            return Err(MdnsConfigError::Unstorable(format!("{}.{}", service_type, proto)));
+        }
+        let query = format!("{}.{}", service_type, proto);
+        if query.len() > MDNS_QUERY_MAX_BYTES {
+            return Err(MdnsConfigError::TooLong("mDNS query", MDNS_QUERY_MAX_BYTES));
+        }
+        self.mdns_query.set(query.as_bytes());
+        Ok(())
+    }
+
+    pub fn role(&self) -> Role {
+        Role::ALL.get(self.role.get() as usize).copied().unwrap_or_default()
+    }
+
+    pub fn set_role(&mut self, role: Role) {
+        self.role.set(Role::ALL.iter().position(|known| *known == role).unwrap() as u8);
+    }
+
+    pub fn fingerprint(&self) -> Option<[u8; FINGERPRINT_BYTES]> {
+        self.fingerprint.get().try_into().ok()
+    }
+
+    pub fn set_fingerprint(&mut self, fingerprint: Option<[u8; FINGERPRINT_BYTES]>) {
+        self.fingerprint.set(fingerprint.as_ref().map_or(&[], |fingerprint| &fingerprint[..]));
+    }
 }
 
 impl<S: Storage> ReadWrite for Config<'_, S> {
     type Error = S::Error;
 
     fn read(&mut self) -> Result<(), Self::Error> {
-        let fields: [&mut dyn ReadWrite<Error=Self::Error>; 2] = [
+        let fields: [&mut dyn ReadWrite<Error=Self::Error>; 13] = [
             &mut self.ssid,
             &mut self.psk,
+            &mut self.auth_method,
+            &mut self.hidden,
+            &mut self.bssid,
+            &mut self.last_ap,
+            &mut self.ip_family,
+            &mut self.ntp_servers,
+            &mut self.timezone,
+            &mut self.mdns_services,
+            &mut self.mdns_query,
+            &mut self.role,
+            &mut self.fingerprint,
         ];
         let iter = fields.map(|field| field.read());
         Result::from_iter(iter).map(|_: ()| ())
     }
 
     fn write(&mut self) -> Result<(), Self::Error> {
-        let fields: [&mut dyn ReadWrite<Error=Self::Error>; 2] = [
+        let fields: [&mut dyn ReadWrite<Error=Self::Error>; 13] = [
             &mut self.ssid,
             &mut self.psk,
+            &mut self.auth_method,
+            &mut self.hidden,
+            &mut self.bssid,
+            &mut self.last_ap,
+            &mut self.ip_family,
+            &mut self.ntp_servers,
+            &mut self.timezone,
+            &mut self.mdns_services,
+            &mut self.mdns_query,
+            &mut self.role,
+            &mut self.fingerprint,
         ];
         let iter = fields.map(|field| field.write());
         Result::from_iter(iter).map(|_: ()| ())
     }
 }
+
+impl<S: Storage> AsyncReadWrite for Config<'_, S> {
+    type Error = S::Error;
+
+    async fn read(&mut self) -> Result<(), Self::Error> {
+        let results = [
+            AsyncReadWrite::read(&mut self.ssid).await,
+            AsyncReadWrite::read(&mut self.psk).await,
+            AsyncReadWrite::read(&mut self.auth_method).await,
+            AsyncReadWrite::read(&mut self.hidden).await,
+            AsyncReadWrite::read(&mut self.bssid).await,
+            AsyncReadWrite::read(&mut self.last_ap).await,
+            AsyncReadWrite::read(&mut self.ip_family).await,
+            AsyncReadWrite::read(&mut self.ntp_servers).await,
+            AsyncReadWrite::read(&mut self.timezone).await,
+            AsyncReadWrite::read(&mut self.mdns_services).await,
+            AsyncReadWrite::read(&mut self.mdns_query).await,
+            AsyncReadWrite::read(&mut self.role).await,
+            AsyncReadWrite::read(&mut self.fingerprint).await,
+        ];
+        Result::from_iter(results).map(|_: ()| ())
+    }
+
+    async fn write(&mut self) -> Result<(), Self::Error> {
+        let results = [
+            AsyncReadWrite::write(&mut self.ssid).await,
+            AsyncReadWrite::write(&mut self.psk).await,
+            AsyncReadWrite::write(&mut self.auth_method).await,
+            AsyncReadWrite::write(&mut self.hidden).await,
+            AsyncReadWrite::write(&mut self.bssid).await,
+            AsyncReadWrite::write(&mut self.last_ap).await,
+            AsyncReadWrite::write(&mut self.ip_family).await,
+            AsyncReadWrite::write(&mut self.ntp_servers).await,
+            AsyncReadWrite::write(&mut self.timezone).await,
+            AsyncReadWrite::write(&mut self.mdns_services).await,
+            AsyncReadWrite::write(&mut self.mdns_query).await,
+            AsyncReadWrite::write(&mut self.role).await,
+            AsyncReadWrite::write(&mut self.fingerprint).await,
+        ];
+        Result::from_iter(results).map(|_: ()| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::from_utf8;
+    use std::sync::{Arc, Mutex};
+
+    use embedded_svc::wifi::AuthMethod;
+
+    use crate::config::{Config, LastAccessPoint, MdnsConfigError, NtpServersTooLong};
+    use crate::mocks::block_on::block_on;
+    use crate::mocks::faulty_storage::{FaultyStorage, FaultyStorageError};
+    use crate::mocks::mock_esp_nvs::{MockEspNvs, MockEspNvsValue};
+    use crate::network::services::MdnsService;
+    use crate::time::timezone::{TimeZone, TimeZoneError};
+    use crate::traits::read_write::ReadWrite;
+    use crate::traits::storage::Storage;
+
+    fn stored_storage() -> Arc<Mutex<FaultyStorage<MockEspNvs>>> {
+        Arc::new(Mutex::new(FaultyStorage::new(MockEspNvs::from([
+            (String::from("ssid"), MockEspNvsValue::BlobValue(Vec::from("stored_ssid"))),
+            (String::from("psk"), MockEspNvsValue::BlobValue(Vec::from("stored_psk"))),
+        ]))))
+    }
+
+    fn stored_blob(storage: &Arc<Mutex<FaultyStorage<MockEspNvs>>>, name: &str) -> Option<Vec<u8>> {
+        let mut buffer = [0_u8; 64];
+        storage.lock().unwrap().inner().get_blob(name, &mut buffer).unwrap().map(Vec::from)
+    }
+
+    #[test]
+    fn read_reports_failure_but_still_reads_remaining_fields() {
+        let storage = stored_storage();
+        storage.lock().unwrap().fail_call(1);
+        let mut config = Config::new(storage.clone(), "default_ssid", "default_psk");
+        let result = config.read();
+        assert!(matches!(result, Err(FaultyStorageError::InjectedCall(1))));
+        assert_eq!(from_utf8(config.ssid.get()).unwrap(), "");
+        assert_eq!(from_utf8(config.psk.get()).unwrap(), "stored_psk");
+    }
+
+    #[test]
+    fn failed_read_keeps_previous_value() {
+        let storage = stored_storage();
+        let mut config = Config::new(storage.clone(), "default_ssid", "default_psk");
+        config.read().unwrap();
+        storage.lock().unwrap().fail_key("ssid");
+        config.ssid.set("changed".as_bytes());
+        assert!(matches!(config.read(), Err(FaultyStorageError::InjectedKey(_))));
+        assert_eq!(from_utf8(config.ssid.get()).unwrap(), "changed");
+        assert_eq!(from_utf8(config.psk.get()).unwrap(), "stored_psk");
+    }
+
+    #[test]
+    fn truncated_blobs_are_accepted_as_stored_values() {
+        let storage = stored_storage();
+        storage.lock().unwrap().truncate_blobs_to(Some(3));
+        let mut config = Config::new(storage.clone(), "default_ssid", "default_psk");
+        config.read().unwrap();
+        assert_eq!(from_utf8(config.ssid.get()).unwrap(), "sto");
+        assert_eq!(from_utf8(config.psk.get()).unwrap(), "sto");
+    }
+
+    #[test]
+    fn write_reports_full_storage_and_leaves_stored_values() {
+        let storage = stored_storage();
+        let mut config = Config::new(storage.clone(), "default_ssid", "default_psk");
+        config.ssid.set("new_ssid".as_bytes());
+        storage.lock().unwrap().set_full(true);
+        assert!(matches!(config.write(), Err(FaultyStorageError::Full)));
+        assert_eq!(stored_blob(&storage, "ssid").unwrap(), "stored_ssid".as_bytes());
+    }
+
+    #[test]
+    fn power_loss_mid_write_persists_partial_blob() {
+        let storage = stored_storage();
+        let mut config = Config::new(storage.clone(), "default_ssid", "default_psk");
+        config.ssid.set("new_ssid".as_bytes());
+        config.psk.set("new_psk".as_bytes());
+        storage.lock().unwrap().power_loss_after_bytes(4);
+        assert!(matches!(config.write(), Err(FaultyStorageError::PowerLoss)));
+        assert_eq!(stored_blob(&storage, "ssid").unwrap(), "new_".as_bytes());
+        assert_eq!(stored_blob(&storage, "psk").unwrap(), "stored_psk".as_bytes());
+
+        storage.lock().unwrap().power_on();
+        let mut rebooted = Config::new(storage.clone(), "default_ssid", "default_psk");
+        rebooted.read().unwrap();
+        assert_eq!(from_utf8(rebooted.ssid.get()).unwrap(), "new_");
+        assert_eq!(from_utf8(rebooted.psk.get()).unwrap(), "stored_psk");
+    }
+
+    #[test]
+    fn intermittent_failure_succeeds_on_retry() {
+        let storage = stored_storage();
+        storage.lock().unwrap().fail_call(2);
+        let mut config = Config::new(storage.clone(), "default_ssid", "default_psk");
+        config.ssid.set("new_ssid".as_bytes());
+        assert!(config.write().is_err());
+        let calls = storage.lock().unwrap().calls();
+        config.write().unwrap();
+        assert_eq!(storage.lock().unwrap().calls(), 2 * calls);
+        assert_eq!(stored_blob(&storage, "psk").unwrap(), "".as_bytes());
+        assert_eq!(stored_blob(&storage, "ssid").unwrap(), "new_ssid".as_bytes());
+    }
+
+    #[test]
+    fn lists_ntp_servers() {
+        let storage = stored_storage();
+        let mut config = Config::new(storage.clone(), "default_ssid", "default_psk");
+        config.read().unwrap();
+        assert_eq!(config.ntp_servers(), ["pool.ntp.org", "time.google.com"]);
+        config.set_ntp_servers(&["10.0.0.1", "time.local:1123"]).unwrap();
+        assert_eq!(config.ntp_servers(), ["10.0.0.1", "time.local:1123"]);
+        assert_eq!(config.set_ntp_servers(&[&"a".repeat(97)]), Err(NtpServersTooLong));
+    }
+
+    #[test]
+    fn ignores_last_access_point_records_it_did_not_write() {
+        let storage = stored_storage();
+        let mut config = Config::new(storage.clone(), "default_ssid", "default_psk");
+        config.read().unwrap();
+        let last = LastAccessPoint { channel: 6, bssid: [2, 0, 0, 0, 0, 6], auth_method: AuthMethod::WPA3Personal };
+        config.set_last_access_point(Some(last));
+        config.write().unwrap();
+        let mut rebooted = Config::new(storage.clone(), "default_ssid", "default_psk");
+        rebooted.read().unwrap();
+        assert_eq!(rebooted.last_access_point(), Some(last));
+        for record in [&[6, 2, 0, 0, 0, 0, 6][..], &[6, 2, 0, 0, 0, 0, 6, 0xff], &[0, 2, 0, 0, 0, 0, 6, 3]] {
+            rebooted.last_ap.set(record);
+            assert_eq!(rebooted.last_access_point(), None);
+        }
+    }
+
+    #[test]
+    fn stores_a_valid_timezone() {
+        let storage = stored_storage();
+        let mut config = Config::new(storage.clone(), "default_ssid", "default_psk");
+        config.read().unwrap();
+        assert_eq!(config.timezone(), TimeZone::parse("UTC0").unwrap());
+        config.set_timezone("CET-1CEST,M3.5.0,M10.5.0/3").unwrap();
+        assert_eq!(config.set_timezone("CET-1CEST,M3"), Err(TimeZoneError::InvalidRule));
+        config.write().unwrap();
+        let mut rebooted = Config::new(storage.clone(), "default_ssid", "default_psk");
+        rebooted.read().unwrap();
+        assert_eq!(rebooted.timezone(), TimeZone::parse("CET-1CEST,M3.5.0,M10.5.0/3").unwrap());
+    }
+
+    #[test]
+    fn stores_mdns_services_and_query() {
+        let storage = stored_storage();
+        let mut config = Config::new(storage.clone(), "default_ssid", "default_psk");
+        config.read().unwrap();
+        assert_eq!(config.mdns_services(), [MdnsService::new("_burptech", "_tcp", 1234)]);
+        assert_eq!(config.mdns_query(), ("_burptech", "_tcp"));
+        let services = [
+            MdnsService::new("_http", "_tcp", 80).with_txt("path", "/a=b").with_txt("empty", ""),
+            MdnsService::new("_peer", "_udp", 4000),
+        ];
+        config.set_mdns_services(&services).unwrap();
+        config.set_mdns_query("_peer", "_udp").unwrap();
+        config.write().unwrap();
+        let mut rebooted = Config::new(storage.clone(), "default_ssid", "default_psk");
+        rebooted.read().unwrap();
+        assert_eq!(rebooted.mdns_services(), services);
+        assert_eq!(rebooted.mdns_query(), ("_peer", "_udp"));
+
+        let unstorable = MdnsService::new("_http", "_tcp", 80).with_txt("path", "a;b");
+        assert_eq!(rebooted.set_mdns_services(&[unstorable]), Err(MdnsConfigError::Unstorable(String::from("_http._tcp"))));
+        let many = vec![MdnsService::new("_http", "_tcp", 80).with_txt("path", &"a".repeat(32)); 8];
+        assert_eq!(rebooted.set_mdns_services(&many), Err(MdnsConfigError::TooLong("mDNS services", 256)));
+        assert!(matches!(rebooted.set_mdns_query("_peer.x", "_udp"), Err(MdnsConfigError::Unstorable(_))));
+        assert_eq!(rebooted.mdns_services(), services);
+        rebooted.mdns_query.set(b"_peer");
+        assert_eq!(rebooted.mdns_query(), ("_burptech", "_tcp"));
+    }
+
+    #[test]
+    fn async_read_and_write_match_blocking_behaviour() {
+        use crate::traits::async_read_write::AsyncReadWrite;
+
+        let storage = stored_storage();
+        let mut config = Config::new(storage.clone(), "default_ssid", "default_psk");
+        block_on(AsyncReadWrite::read(&mut config)).unwrap();
+        assert_eq!(from_utf8(config.ssid.get()).unwrap(), "stored_ssid");
+        config.psk.set("new_psk".as_bytes());
+        storage.lock().unwrap().fail_key("ssid");
+        assert!(matches!(block_on(AsyncReadWrite::write(&mut config)), Err(FaultyStorageError::InjectedKey(_))));
+        assert_eq!(stored_blob(&storage, "psk").unwrap(), "new_psk".as_bytes());
+    }
+}
@@ -1,6 +1,9 @@
 use std::sync::{Arc, Mutex};
 
 use crate::debug::debug_blob::DebugBlob;
+use crate::storage::async_storage_adaptor::AsyncStorageAdaptor;
+use crate::traits::async_read_write::AsyncReadWrite;
+use crate::traits::async_storage::AsyncStorage;
 use crate::traits::read_write::ReadWrite;
 use crate::traits::storage::Storage;
 
@@ -63,6 +66,16 @@ impl<'a, S: Storage, const N: usize> BlobConfig<'a, S, N> {
     }
 }
 
+// Copies of a field can be written without holding the config lock
+impl<S, const N: usize> Clone for BlobConfig<'_, S, N> {
+    fn clone(&self) -> Self {
+        BlobConfig {
+            storage: self.storage.clone(),
+            ..*self
+        }
+    }
+}
+
 impl<S: Storage, const N: usize> ReadWrite for BlobConfig<'_, S, N> {
     type Error = S::Error;
 
@@ -80,6 +93,26 @@ impl<S: Storage, const N: usize> ReadWrite for BlobConfig<'_, S, N> {
     }
 }
 
+impl<S: Storage, const N: usize> AsyncReadWrite for BlobConfig<'_, S, N> {
+    type Error = S::Error;
+
+    async fn read(&mut self) -> Result<(), Self::Error> {
+        let mut buffer = [0_u8; N];
+        let storage = AsyncStorageAdaptor::new(self.storage.clone());
+        let blob_result = storage.get_blob(self.name, &mut buffer).await;
+        blob_result.map(|blob_option| match blob_option {
+            None => self.reset(),
+            Some(blob) => self.set(blob),
+        })
+    }
+
+    async fn write(&mut self) -> Result<(), Self::Error> {
+        let mut storage = AsyncStorageAdaptor::new(self.storage.clone());
+        storage.set_blob(self.name, &self.buffer[..self.len]).await?;
+        storage.commit().await
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::str::from_utf8;
@@ -0,0 +1,86 @@
+use std::sync::{Arc, Mutex};
+
+use crate::storage::async_storage_adaptor::AsyncStorageAdaptor;
+use crate::traits::async_read_write::AsyncReadWrite;
+use crate::traits::async_storage::AsyncStorage;
+use crate::traits::read_write::ReadWrite;
+use crate::traits::storage::Storage;
+
+pub struct U8Config<'a, S> {
+    storage: Arc<Mutex<S>>,
+    name: &'a str,
+    default: u8,
+    value: u8,
+}
+
+impl<'a, S: Storage> U8Config<'a, S> {
+    pub fn new(storage: Arc<Mutex<S>>, name: &'a str, default: u8) -> U8Config<'a, S> {
+        U8Config {
+            storage,
+            name,
+            default,
+            value: default,
+        }
+    }
+
+    pub fn get(&self) -> u8 {
+        self.value
+    }
+
+    pub fn set(&mut self, value: u8) {
+        self.value = value;
+    }
+
+    pub fn reset(&mut self) {
+        self.value = self.default;
+    }
+
+    pub fn name(&self) -> &str {
+        self.name
+    }
+}
+
+// Copies of a field can be written without holding the config lock
+impl<S> Clone for U8Config<'_, S> {
+    fn clone(&self) -> Self {
+        U8Config {
+            storage: self.storage.clone(),
+            ..*self
+        }
+    }
+}
+
+impl<S: Storage> ReadWrite for U8Config<'_, S> {
+    type Error = S::Error;
+
+    fn read(&mut self) -> Result<(), Self::Error> {
+        let value_result = self.storage.lock().unwrap().get_u8(self.name);
+        value_result.map(|value_option| match value_option {
+            None => self.reset(),
+            Some(value) => self.set(value),
+        })
+    }
+
+    fn write(&mut self) -> Result<(), Self::Error> {
+        self.storage.lock().unwrap().set_u8(self.name, self.value)
+    }
+}
+
+impl<S: Storage> AsyncReadWrite for U8Config<'_, S> {
+    type Error = S::Error;
+
+    async fn read(&mut self) -> Result<(), Self::Error> {
+        let storage = AsyncStorageAdaptor::new(self.storage.clone());
+        let value_result = storage.get_u8(self.name).await;
+        value_result.map(|value_option| match value_option {
+            None => self.reset(),
+            Some(value) => self.set(value),
+        })
+    }
+
+    async fn write(&mut self) -> Result<(), Self::Error> {
+        let mut storage = AsyncStorageAdaptor::new(self.storage.clone());
+        storage.set_u8(self.name, self.value).await?;
+        storage.commit().await
+    }
+}
@@ -0,0 +1,306 @@
+use std::io;
+use std::net::{IpAddr, SocketAddr, TcpStream};
+use std::time::{Duration, Instant};
+
+use log::*;
+use thiserror::Error;
+
+use crate::resolver::{resolve, split_host_port};
+use crate::traits::ping::{Ping, PingStats};
+use crate::workers;
+
+pub mod udp_echo;
+
+const DEFAULT_INTERNET_TARGETS: [&str; 2] = ["connectivitycheck.gstatic.com:80", "1.1.1.1:53"];
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ProbeError {
+    #[error("Host did not resolve")]
+    NotResolved,
+    #[error("Timed out")]
+    TimedOut,
+    #[error("Connection refused")]
+    Refused,
+    #[error("Ping failed: {0}")]
+    Ping(String),
+    #[error("{0}")]
+    Io(io::ErrorKind),
+}
+
+impl From<io::Error> for ProbeError {
+    fn from(error: io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => ProbeError::TimedOut,
+            io::ErrorKind::ConnectionRefused => ProbeError::Refused,
+            kind => ProbeError::Io(kind),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DnsLookup {
+    pub host: String,
+    pub elapsed: Duration,
+    pub result: Result<Vec<IpAddr>, ProbeError>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectProbe {
+    // host or address with a port
+    pub target: String,
+    // None when the target did not resolve
+    pub address: Option<SocketAddr>,
+    // connect latency
+    pub result: Result<Duration, ProbeError>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PingProbe {
+    pub address: IpAddr,
+    pub result: Result<PingStats, ProbeError>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reachability {
+    Online,
+    // addresses can be reached but names do not resolve
+    DnsFailing,
+    Offline,
+}
+
+impl Reachability {
+    pub fn of(probes: &[ConnectProbe]) -> Reachability {
+        let named: Vec<_> = probes.iter().filter(|probe| host(&probe.target).parse::<IpAddr>().is_err()).collect();
+        if !probes.iter().any(|probe| probe.result.is_ok()) {
+            Reachability::Offline
+        } else if !named.is_empty() && named.iter().all(|probe| probe.result == Err(ProbeError::NotResolved)) {
+            Reachability::DnsFailing
+        } else {
+            Reachability::Online
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DiagnosticsPolicy {
+    // for each lookup, connect and echo
+    pub timeout: Duration,
+    pub ping_count: u16,
+    // the gateway's web interface on most routers
+    pub gateway_port: u16,
+    // each a host name or address with a port
+    pub targets: Vec<String>,
+    // connected to in turn to decide whether the internet is reachable
+    pub internet_targets: Vec<String>,
+}
+
+impl Default for DiagnosticsPolicy {
+    fn default() -> Self {
+        DiagnosticsPolicy {
+            timeout: Duration::from_secs(2),
+            ping_count: 4,
+            gateway_port: 80,
+            targets: Vec::new(),
+            internet_targets: DEFAULT_INTERNET_TARGETS.map(String::from).to_vec(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiagnosticsReport {
+    pub gateway: Option<IpAddr>,
+    pub gateway_ping: Option<PingProbe>,
+    pub gateway_connect: Option<ConnectProbe>,
+    // the host names among the targets
+    pub lookups: Vec<DnsLookup>,
+    pub targets: Vec<ConnectProbe>,
+    pub internet: Vec<ConnectProbe>,
+    pub reachability: Reachability,
+}
+
+pub async fn ping<P: Ping>(pinger: &mut P, address: IpAddr, count: u16, timeout: Duration) -> PingProbe {
+    PingProbe {
+        address,
+        result: pinger.ping(address, count, timeout).await.map_err(|error| ProbeError::Ping(error.to_string())),
+    }
+}
+
+// Goes through the system resolver, which is lwIP's DNS client on the device
+pub async fn lookup(host: &str, timeout: Duration) -> DnsLookup {
+    let started = Instant::now();
+    let result = match resolve(host, 0, timeout).await {
+        Ok(addresses) if !addresses.is_empty() => Ok(addresses.into_iter().map(|address| address.ip()).collect()),
+        Err(error) if error.kind() == io::ErrorKind::TimedOut => Err(ProbeError::TimedOut),
+        _ => Err(ProbeError::NotResolved),
+    };
+    DnsLookup {
+        host: host.to_string(),
+        elapsed: started.elapsed(),
+        result,
+    }
+}
+
+pub async fn connect(target: &str, timeout: Duration) -> ConnectProbe {
+    let address = match split_host_port(target) {
+        Some((host, Some(port))) => resolve(host, port, timeout).await.ok().and_then(|addresses| addresses.into_iter().next()),
+        _ => None,
+    };
+    let result = match address {
+        Some(address) => connect_timeout(address, timeout).await,
+        None => Err(ProbeError::NotResolved),
+    };
+    ConnectProbe {
+        target: target.to_string(),
+        address,
+        result,
+    }
+}
+
+// std has no non-blocking connect, so it waits on the sockets worker
+async fn connect_timeout(address: SocketAddr, timeout: Duration) -> Result<Duration, ProbeError> {
+    let connect = workers::sockets().run(move || {
+        let started = Instant::now();
+        TcpStream::connect_timeout(&address, timeout).map(|_| started.elapsed())
+    });
+    Ok(connect.await?)
+}
+
+pub async fn run<P: Ping>(pinger: &mut P, gateway: Option<IpAddr>, policy: &DiagnosticsPolicy) -> DiagnosticsReport {
+    let (gateway_ping, gateway_connect) = match gateway {
+        Some(gateway) => (
+            Some(ping(pinger, gateway, policy.ping_count, policy.timeout).await),
+            Some(connect(&SocketAddr::new(gateway, policy.gateway_port).to_string(), policy.timeout).await),
+        ),
+        None => (None, None),
+    };
+    let mut lookups = Vec::new();
+    for host in host_names(policy.targets.iter().chain(&policy.internet_targets)) {
+        lookups.push(lookup(host, policy.timeout).await);
+    }
+    let mut targets = Vec::new();
+    for target in &policy.targets {
+        targets.push(connect(target, policy.timeout).await);
+    }
+    let mut internet = Vec::new();
+    for target in &policy.internet_targets {
+        internet.push(connect(target, policy.timeout).await);
+    }
+    let reachability = Reachability::of(&internet);
+    info!("Diagnostics finished, internet {:?}", reachability);
+    DiagnosticsReport {
+        gateway,
+        gateway_ping,
+        gateway_connect,
+        lookups,
+        targets,
+        internet,
+        reachability,
+    }
+}
+
+// Without the port or the brackets around an IPv6 address
+fn host(target: &str) -> &str {
+    split_host_port(target).map_or(target, |(host, _)| host)
+}
+
+// Each name once, in the order the targets list them
+fn host_names<'t>(targets: impl Iterator<Item = &'t String>) -> Vec<&'t str> {
+    let mut hosts = Vec::new();
+    for host in targets.map(|target| host(target)) {
+        if host.parse::<IpAddr>().is_err() && !hosts.contains(&host) {
+            hosts.push(host);
+        }
+    }
+    hosts
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::time::Duration;
+
+    use crate::diagnostics::{connect, host, host_names, lookup, run, ConnectProbe, DiagnosticsPolicy, ProbeError, Reachability};
+    use crate::diagnostics::udp_echo::UdpEcho;
+    use crate::mocks::block_on::block_on;
+    use crate::mocks::mock_echo_server::{stand_in_echo, LOCALHOST};
+
+    fn closed_port() -> u16 {
+        TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+    }
+
+    fn probe(target: &str, result: Result<Duration, ProbeError>) -> ConnectProbe {
+        ConnectProbe {
+            target: target.to_string(),
+            address: None,
+            result,
+        }
+    }
+
+    #[test]
+    fn measures_connect_latency() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let open = block_on(connect(&listener.local_addr().unwrap().to_string(), Duration::from_secs(1)));
+        assert!(open.result.unwrap() < Duration::from_secs(1));
+        let closed = block_on(connect(&format!("127.0.0.1:{}", closed_port()), Duration::from_secs(1)));
+        assert_eq!(closed.result, Err(ProbeError::Refused));
+        let portless = block_on(connect("127.0.0.1", Duration::from_secs(1)));
+        assert_eq!((portless.address, portless.result), (None, Err(ProbeError::NotResolved)));
+    }
+
+    #[test]
+    fn resolves_localhost() {
+        let lookup = block_on(lookup("localhost", Duration::from_secs(5)));
+        assert!(lookup.result.unwrap().iter().any(|address| address.is_loopback()));
+    }
+
+    #[test]
+    fn finds_each_host_name_once() {
+        assert_eq!(host("fe80::1"), "fe80::1");
+        assert_eq!(host("[::1]:53"), "::1");
+        assert_eq!(host("example.com:80"), "example.com");
+        let targets = ["a.example:80", "1.1.1.1:53", "b.example:80", "fe80::1", "a.example:443"].map(String::from);
+        assert_eq!(host_names(targets.iter()), ["a.example", "b.example"]);
+    }
+
+    #[test]
+    fn decides_reachability() {
+        let ok = Ok(Duration::from_millis(10));
+        let unresolved = Err(ProbeError::NotResolved);
+        assert_eq!(Reachability::of(&[probe("example.com:80", ok.clone())]), Reachability::Online);
+        assert_eq!(Reachability::of(&[probe("1.1.1.1:53", ok.clone())]), Reachability::Online);
+        assert_eq!(
+            Reachability::of(&[probe("example.com:80", unresolved.clone()), probe("[::1]:53", ok.clone())]),
+            Reachability::DnsFailing
+        );
+        assert_eq!(Reachability::of(&[probe("example.com:80", unresolved.clone()), probe("::1", ok)]), Reachability::DnsFailing);
+        assert_eq!(Reachability::of(&[probe("example.com:80", unresolved)]), Reachability::Offline);
+    }
+
+    #[test]
+    fn reports_on_the_gateway_and_targets() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let policy = DiagnosticsPolicy {
+            timeout: Duration::from_millis(200),
+            ping_count: 2,
+            gateway_port: port,
+            targets: vec![format!("localhost:{}", port), format!("127.0.0.1:{}", closed_port())],
+            internet_targets: vec![format!("127.0.0.1:{}", port)],
+        };
+        let mut pinger = UdpEcho::new(stand_in_echo(2, 0));
+        let report = block_on(run(&mut pinger, Some(LOCALHOST), &policy));
+        assert_eq!(report.gateway_ping.unwrap().result.unwrap().received, 2);
+        assert!(report.gateway_connect.unwrap().result.is_ok());
+        assert_eq!(report.lookups.len(), 1);
+        assert_eq!(report.lookups[0].host, "localhost");
+        assert!(report.targets[0].result.is_ok());
+        assert_eq!(report.targets[1].result, Err(ProbeError::Refused));
+        assert_eq!(report.reachability, Reachability::Online);
+
+        let report = block_on(run(&mut UdpEcho::new(closed_port()), None, &DiagnosticsPolicy {
+            internet_targets: vec![format!("127.0.0.1:{}", closed_port())],
+            ..policy
+        }));
+        assert_eq!((report.gateway_ping, report.reachability), (None, Reachability::Offline));
+    }
+}
@@ -0,0 +1,105 @@
+use std::io;
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+use log::*;
+
+use crate::traits::ping::{Ping, PingStats};
+use crate::workers;
+
+// RFC 862
+pub const ECHO_PORT: u16 = 7;
+
+// Stands in for ICMP where raw sockets are unavailable, needs an echo service on the target
+pub struct UdpEcho {
+    port: u16,
+    // distinguishes replies to this ping from stale ones
+    nonce: u64,
+}
+
+impl UdpEcho {
+    pub fn new(port: u16) -> UdpEcho {
+        UdpEcho {
+            port,
+            nonce: 0x2545_f491_4f6c_dd1d,
+        }
+    }
+
+}
+
+// Blocks until the payload comes back or the socket times out, None when it did not
+fn echo(socket: &UdpSocket, payload: &[u8; 8], timeout: Duration) -> io::Result<Option<Duration>> {
+    let sent = Instant::now();
+    socket.send(payload)?;
+    let mut reply = [0_u8; 8];
+    loop {
+        let remaining = timeout.saturating_sub(sent.elapsed());
+        if remaining.is_zero() {
+            return Ok(None);
+        }
+        socket.set_read_timeout(Some(remaining))?;
+        match socket.recv(&mut reply) {
+            Ok(8) if reply == *payload => return Ok(Some(sent.elapsed())),
+            Ok(_) => debug!("Ignoring unexpected echo"),
+            // which of the two depends on the platform
+            Err(error) if matches!(error.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {}
+            // when the port unreachable for an earlier packet comes back
+            Err(error) if error.kind() == io::ErrorKind::ConnectionRefused => return Ok(None),
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+impl Default for UdpEcho {
+    fn default() -> Self {
+        UdpEcho::new(ECHO_PORT)
+    }
+}
+
+impl Ping for UdpEcho {
+    type Error = io::Error;
+
+    // The echoes run on the sockets worker, so waiting for replies leaves the executor free
+    async fn ping(&mut self, address: IpAddr, count: u16, timeout: Duration) -> Result<PingStats, Self::Error> {
+        let payloads: Vec<[u8; 8]> = (0..count).map(|_| {
+            self.nonce = self.nonce.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            self.nonce.to_be_bytes()
+        }).collect();
+        let target = SocketAddr::new(address, self.port);
+        workers::sockets().run(move || {
+            let socket = UdpSocket::bind(if address.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" })?;
+            socket.connect(target)?;
+            let mut stats = PingStats::default();
+            for payload in &payloads {
+                stats.add(echo(&socket, payload, timeout)?);
+            }
+            Ok(stats)
+        }).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::UdpSocket;
+    use std::time::Duration;
+
+    use crate::diagnostics::udp_echo::UdpEcho;
+    use crate::mocks::block_on::block_on;
+    use crate::mocks::mock_echo_server::{stand_in_echo, LOCALHOST};
+    use crate::traits::ping::Ping;
+
+    #[test]
+    fn counts_echoed_packets() {
+        let mut echo = UdpEcho::new(stand_in_echo(3, 1));
+        let stats = block_on(echo.ping(LOCALHOST, 3, Duration::from_millis(200))).unwrap();
+        assert_eq!((stats.sent, stats.received, stats.loss_percent()), (3, 2, 34));
+        assert!(stats.min.unwrap() <= stats.mean().unwrap() && stats.mean().unwrap() <= stats.max.unwrap());
+    }
+
+    #[test]
+    fn loses_packets_without_an_echo_service() {
+        let port = UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let stats = block_on(UdpEcho::new(port).ping(LOCALHOST, 2, Duration::from_millis(50))).unwrap();
+        assert_eq!((stats.sent, stats.received, stats.mean()), (2, 0, None));
+    }
+}
@@ -0,0 +1,169 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use heapless::Deque;
+
+struct Inner<T, const N: usize> {
+    events: Deque<(u32, T), N>,
+    next_sequence: u32,
+    latest: Option<T>,
+    wakers: Vec<Waker>,
+}
+
+// Broadcasts events to any number of subscribers, keeping the last N events for subscribers that
+// fall behind. Clones publish to the same subscribers.
+pub struct EventChannel<T, const N: usize> {
+    inner: Arc<Mutex<Inner<T, N>>>,
+}
+
+impl<T, const N: usize> Clone for EventChannel<T, N> {
+    fn clone(&self) -> Self {
+        EventChannel {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T: Clone, const N: usize> Default for EventChannel<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone, const N: usize> EventChannel<T, N> {
+    pub fn new() -> EventChannel<T, N> {
+        EventChannel {
+            inner: Arc::new(Mutex::new(Inner {
+                events: Deque::new(),
+                next_sequence: 0,
+                latest: None,
+                wakers: Vec::new(),
+            })),
+        }
+    }
+
+    pub fn publish(&self, event: T) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.events.is_full() {
+            inner.events.pop_front();
+        }
+        let sequence = inner.next_sequence;
+        inner.next_sequence = sequence.wrapping_add(1);
+        inner.latest = Some(event.clone());
+        inner.events.push_back((sequence, event)).ok();
+        for waker in inner.wakers.drain(..) {
+            waker.wake();
+        }
+    }
+
+    pub fn latest(&self) -> Option<T> {
+        self.inner.lock().unwrap().latest.clone()
+    }
+
+    // Only receives events published after subscribing
+    pub fn subscribe(&self) -> Subscriber<T, N> {
+        Subscriber {
+            inner: self.inner.clone(),
+            next_sequence: self.inner.lock().unwrap().next_sequence,
+            missed: 0,
+        }
+    }
+}
+
+pub struct Subscriber<T, const N: usize> {
+    inner: Arc<Mutex<Inner<T, N>>>,
+    next_sequence: u32,
+    missed: u32,
+}
+
+impl<T: Clone, const N: usize> Subscriber<T, N> {
+    pub fn try_recv(&mut self) -> Option<T> {
+        let inner = self.inner.lock().unwrap();
+        let (sequence, event) = inner.events.iter()
+            .find(|(sequence, _)| sequence.wrapping_sub(self.next_sequence) < u32::MAX / 2)?;
+        self.missed = self.missed.wrapping_add(sequence.wrapping_sub(self.next_sequence));
+        self.next_sequence = sequence.wrapping_add(1);
+        Some(event.clone())
+    }
+
+    pub fn recv(&mut self) -> NextEvent<'_, T, N> {
+        NextEvent {
+            subscriber: self,
+        }
+    }
+
+    // events dropped because this subscriber fell more than N events behind
+    pub fn missed(&self) -> u32 {
+        self.missed
+    }
+}
+
+pub struct NextEvent<'a, T, const N: usize> {
+    subscriber: &'a mut Subscriber<T, N>,
+}
+
+impl<T: Clone, const N: usize> Future for NextEvent<'_, T, N> {
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if let Some(event) = self.subscriber.try_recv() {
+            return Poll::Ready(event);
+        }
+        let mut inner = self.subscriber.inner.lock().unwrap();
+        if !inner.wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+            inner.wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+    use std::pin::pin;
+    use std::task::{Context, Poll};
+
+    use crate::events::EventChannel;
+    use crate::mocks::block_on::noop_waker;
+
+    #[test]
+    fn subscribers_receive_events_published_after_subscribing() {
+        let channel: EventChannel<u8, 4> = EventChannel::new();
+        channel.publish(1);
+        let mut first = channel.subscribe();
+        channel.publish(2);
+        let mut second = channel.clone().subscribe();
+        channel.publish(3);
+        assert_eq!(first.try_recv(), Some(2));
+        assert_eq!(first.try_recv(), Some(3));
+        assert_eq!(first.try_recv(), None);
+        assert_eq!(second.try_recv(), Some(3));
+        assert_eq!(channel.latest(), Some(3));
+    }
+
+    #[test]
+    fn lagging_subscribers_skip_to_oldest_kept_event() {
+        let channel: EventChannel<u8, 2> = EventChannel::new();
+        let mut subscriber = channel.subscribe();
+        for event in 0..5 {
+            channel.publish(event);
+        }
+        assert_eq!(subscriber.try_recv(), Some(3));
+        assert_eq!(subscriber.try_recv(), Some(4));
+        assert_eq!(subscriber.missed(), 3);
+    }
+
+    #[test]
+    fn awaiting_subscribers_are_woken_by_publish() {
+        let channel: EventChannel<u8, 2> = EventChannel::new();
+        let mut subscriber = channel.subscribe();
+        let waker = noop_waker();
+        let mut context = Context::from_waker(&waker);
+        let mut next = pin!(subscriber.recv());
+        assert!(next.as_mut().poll(&mut context).is_pending());
+        channel.publish(7);
+        assert_eq!(next.as_mut().poll(&mut context), Poll::Ready(7));
+    }
+}
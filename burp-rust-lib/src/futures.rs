@@ -0,0 +1,301 @@
+use std::future::{poll_fn, Future};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::pin::{pin, Pin};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::task::{Context, Poll, Waker};
+use std::thread::Builder;
+use std::time::{Duration, Instant};
+
+// the timer thread only keeps a list of deadlines
+const TIMER_THREAD_STACK_BYTES: usize = 2048;
+
+pub struct YieldNow {
+    yielded: bool,
+}
+
+// Gives the executor a chance to run other tasks before continuing
+pub fn yield_now() -> YieldNow {
+    YieldNow { yielded: false }
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.yielded {
+            Poll::Ready(())
+        } else {
+            self.yielded = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+struct Slot<T> {
+    value: Option<T>,
+    waker: Option<Waker>,
+    // the completer went away without a value, or the completion did
+    abandoned: bool,
+}
+
+// The other thread's end of a Completion
+pub struct Completer<T> {
+    slot: Arc<Mutex<Slot<T>>>,
+}
+
+// Resolved by another thread, which wakes the waiting task rather than it polling
+pub struct Completion<T> {
+    slot: Arc<Mutex<Slot<T>>>,
+}
+
+pub fn completion<T>() -> (Completer<T>, Completion<T>) {
+    let slot = Arc::new(Mutex::new(Slot { value: None, waker: None, abandoned: false }));
+    (Completer { slot: slot.clone() }, Completion { slot })
+}
+
+impl<T> Completer<T> {
+    pub fn complete(self, value: T) {
+        let waker = {
+            let mut slot = self.slot.lock().unwrap();
+            slot.value = Some(value);
+            slot.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    // Nobody waits for the value any more, so the work can be skipped
+    pub fn is_abandoned(&self) -> bool {
+        self.slot.lock().unwrap().abandoned
+    }
+}
+
+impl<T> Drop for Completer<T> {
+    fn drop(&mut self) {
+        let waker = {
+            let mut slot = self.slot.lock().unwrap();
+            if slot.value.is_some() {
+                return;
+            }
+            slot.abandoned = true;
+            slot.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Future for Completion<T> {
+    type Output = T;
+
+    // Panics when the completer was dropped without a value, as when the work panicked
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut slot = self.slot.lock().unwrap();
+        if let Some(value) = slot.value.take() {
+            return Poll::Ready(value);
+        }
+        if slot.abandoned {
+            drop(slot);
+            panic!("Work was dropped without completing");
+        }
+        slot.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for Completion<T> {
+    fn drop(&mut self) {
+        self.slot.lock().unwrap().abandoned = true;
+    }
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+// A long-lived thread that runs blocking work in the order it was handed over
+pub struct Worker {
+    jobs: Mutex<Sender<Job>>,
+}
+
+impl Worker {
+    pub fn new(name: &str, stack_bytes: usize) -> Worker {
+        let (jobs, received) = channel::<Job>();
+        Builder::new()
+            .name(String::from(name))
+            .stack_size(stack_bytes)
+            .spawn(move || {
+                // a job that panics drops its completer, which passes the panic on to the waiting task
+                for job in received {
+                    catch_unwind(AssertUnwindSafe(job)).ok();
+                }
+            })
+            .expect("Failed to start worker thread");
+        Worker { jobs: Mutex::new(jobs) }
+    }
+
+    pub fn submit(&self, job: impl FnOnce() + Send + 'static) {
+        self.jobs.lock().unwrap().send(Box::new(job)).expect("Worker thread stopped");
+    }
+
+    pub fn run<T: Send + 'static>(&self, job: impl FnOnce() -> T + Send + 'static) -> Completion<T> {
+        let (completer, completion) = completion();
+        self.submit(move || completer.complete(job()));
+        completion
+    }
+}
+
+type SleepWaker = Arc<Mutex<Option<Waker>>>;
+
+struct Timers {
+    sleeping: Mutex<Vec<(Instant, SleepWaker)>>,
+    changed: Condvar,
+}
+
+// One thread wakes every sleep, at the earliest deadline or when one is added
+fn timers() -> &'static Timers {
+    static TIMERS: OnceLock<&'static Timers> = OnceLock::new();
+    TIMERS.get_or_init(|| {
+        let timers: &'static Timers = Box::leak(Box::new(Timers { sleeping: Mutex::new(Vec::new()), changed: Condvar::new() }));
+        Builder::new()
+            .name(String::from("timer"))
+            .stack_size(TIMER_THREAD_STACK_BYTES)
+            .spawn(move || {
+                let mut sleeping = timers.sleeping.lock().unwrap();
+                loop {
+                    let now = Instant::now();
+                    sleeping.retain(|(deadline, waker)| {
+                        if *deadline > now {
+                            return true;
+                        }
+                        if let Some(waker) = waker.lock().unwrap().take() {
+                            waker.wake();
+                        }
+                        false
+                    });
+                    sleeping = match sleeping.iter().map(|(deadline, _)| *deadline).min() {
+                        Some(next) => timers.changed.wait_timeout(sleeping, next - now).unwrap().0,
+                        None => timers.changed.wait(sleeping).unwrap(),
+                    };
+                }
+            })
+            .expect("Failed to start timer thread");
+        timers
+    })
+}
+
+pub struct Sleep {
+    deadline: Instant,
+    waker: Option<SleepWaker>,
+}
+
+// For code without a Timer to hand, the timer thread wakes the task once the duration has passed
+pub fn sleep(duration: Duration) -> Sleep {
+    Sleep { deadline: Instant::now() + duration, waker: None }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if Instant::now() >= self.deadline {
+            return Poll::Ready(());
+        }
+        match &self.waker {
+            Some(waker) => *waker.lock().unwrap() = Some(cx.waker().clone()),
+            None => {
+                let waker = Arc::new(Mutex::new(Some(cx.waker().clone())));
+                let timers = timers();
+                timers.sleeping.lock().unwrap().push((self.deadline, waker.clone()));
+                timers.changed.notify_one();
+                self.waker = Some(waker);
+            }
+        }
+        Poll::Pending
+    }
+}
+
+// None when expiry, eg. Timer::after or sleep, finishes first
+pub async fn timeout<F: Future>(future: F, expiry: impl Future) -> Option<F::Output> {
+    let mut future = pin!(future);
+    let mut expiry = pin!(expiry);
+    poll_fn(|cx| {
+        if let Poll::Ready(output) = future.as_mut().poll(cx) {
+            return Poll::Ready(Some(output));
+        }
+        match expiry.as_mut().poll(cx) {
+            Poll::Ready(_) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+    use std::pin::pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc::channel;
+    use std::sync::Arc;
+    use std::task::{Context, Wake};
+    use std::time::{Duration, Instant};
+
+    use crate::futures::{sleep, timeout, Worker};
+    use crate::mocks::block_on::block_on;
+
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn wakes_once_the_work_is_done() {
+        let worker = Worker::new("test", 64 * 1024);
+        let (release, released) = channel::<()>();
+        let counter = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = counter.clone().into();
+        let mut context = Context::from_waker(&waker);
+        let mut work = pin!(worker.run(move || released.recv().map(|_| 7)));
+        assert!(work.as_mut().poll(&mut context).is_pending());
+        assert!(work.as_mut().poll(&mut context).is_pending());
+        assert_eq!(counter.0.load(Ordering::SeqCst), 0);
+        release.send(()).unwrap();
+        while counter.0.load(Ordering::SeqCst) == 0 {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(block_on(work), Ok(7));
+    }
+
+    #[test]
+    #[should_panic(expected = "dropped without completing")]
+    fn passes_on_panics() {
+        let worker = Worker::new("test", 64 * 1024);
+        block_on(worker.run(|| panic!("broken")));
+    }
+
+    #[test]
+    fn sleeps_without_being_polled() {
+        let counter = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = counter.clone().into();
+        let mut context = Context::from_waker(&waker);
+        let started = Instant::now();
+        let mut sleeping = pin!(sleep(Duration::from_millis(50)));
+        assert!(sleeping.as_mut().poll(&mut context).is_pending());
+        while counter.0.load(Ordering::SeqCst) == 0 {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        assert!(sleeping.as_mut().poll(&mut context).is_ready());
+    }
+
+    #[test]
+    fn gives_up_when_expiry_comes_first() {
+        assert_eq!(block_on(timeout(async { 1 }, sleep(Duration::ZERO))), Some(1));
+        assert_eq!(block_on(timeout(sleep(Duration::from_secs(5)), sleep(Duration::from_millis(10)))), None);
+    }
+}
@@ -2,8 +2,16 @@
 
 pub mod name;
 pub mod config;
+pub mod diagnostics;
+pub mod events;
+pub mod futures;
 pub mod network;
+pub mod provisioning;
+pub mod resolver;
+pub mod storage;
+pub mod time;
 pub mod traits;
+pub mod workers;
 mod debug;
 #[cfg(test)]
 mod mocks;
@@ -1 +1,12 @@
 pub(crate) mod mock_esp_nvs;
+pub(crate) mod faulty_storage;
+pub(crate) mod block_on;
+pub(crate) mod mock_wifi;
+pub(crate) mod mock_ethernet;
+pub(crate) mod mock_mdns;
+pub(crate) mod mock_timer;
+pub(crate) mod mock_duplex;
+pub(crate) mod mock_improv;
+pub(crate) mod mock_sntp_server;
+pub(crate) mod mock_time_sync;
+pub(crate) mod mock_echo_server;
@@ -0,0 +1,220 @@
+use std::cell::{Cell, RefCell};
+use std::error::Error;
+
+use thiserror::Error;
+
+use crate::traits::storage::{Storage, StorageStats};
+
+pub struct FaultyStorage<S> {
+    storage: S,
+    calls: Cell<usize>,
+    failing_calls: RefCell<Vec<usize>>,
+    failing_keys: RefCell<Vec<String>>,
+    truncate_blobs_to: Cell<Option<usize>>,
+    power_loss_after_bytes: Cell<Option<usize>>,
+    powered: Cell<bool>,
+    full: Cell<bool>,
+}
+
+#[derive(Error, Debug)]
+pub enum FaultyStorageError<E: Error> {
+    #[error("Injected failure on call {0}")]
+    InjectedCall(usize),
+    #[error("Injected failure for key: {0}")]
+    InjectedKey(String),
+    #[error("Storage is full")]
+    Full,
+    #[error("Power lost")]
+    PowerLoss,
+    #[error("Storage error: {0}")]
+    StorageError(E),
+}
+
+impl<S: Storage> FaultyStorage<S> {
+    pub fn new(storage: S) -> FaultyStorage<S> {
+        FaultyStorage {
+            storage,
+            calls: Cell::new(0),
+            failing_calls: RefCell::new(Vec::new()),
+            failing_keys: RefCell::new(Vec::new()),
+            truncate_blobs_to: Cell::new(None),
+            power_loss_after_bytes: Cell::new(None),
+            powered: Cell::new(true),
+            full: Cell::new(false),
+        }
+    }
+
+    // calls are counted from 1 across all methods
+    pub fn fail_call(&self, call: usize) {
+        self.failing_calls.borrow_mut().push(call);
+    }
+
+    pub fn fail_key(&self, name: &str) {
+        self.failing_keys.borrow_mut().push(String::from(name));
+    }
+
+    pub fn truncate_blobs_to(&self, len: Option<usize>) {
+        self.truncate_blobs_to.set(len);
+    }
+
+    // the next blob write only persists its first `bytes` bytes, after which every call fails until power_on
+    pub fn power_loss_after_bytes(&self, bytes: usize) {
+        self.power_loss_after_bytes.set(Some(bytes));
+    }
+
+    pub fn power_on(&self) {
+        self.powered.set(true);
+    }
+
+    pub fn set_full(&self, full: bool) {
+        self.full.set(full);
+    }
+
+    pub fn calls(&self) -> usize {
+        self.calls.get()
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.storage
+    }
+
+    fn check(&self, name: &str, write: bool) -> Result<(), FaultyStorageError<S::Error>> {
+        let call = self.calls.get() + 1;
+        self.calls.set(call);
+        if !self.powered.get() {
+            return Err(FaultyStorageError::PowerLoss);
+        }
+        if self.failing_calls.borrow().contains(&call) {
+            return Err(FaultyStorageError::InjectedCall(call));
+        }
+        if self.failing_keys.borrow().iter().any(|key| key == name) {
+            return Err(FaultyStorageError::InjectedKey(String::from(name)));
+        }
+        if write && self.full.get() {
+            return Err(FaultyStorageError::Full);
+        }
+        Ok(())
+    }
+
+    fn get<T>(
+        &self,
+        name: &str,
+        get: impl FnOnce(&S, &str) -> Result<Option<T>, S::Error>,
+    ) -> Result<Option<T>, FaultyStorageError<S::Error>> {
+        self.check(name, false)?;
+        get(&self.storage, name).map_err(FaultyStorageError::StorageError)
+    }
+
+    fn set<T>(
+        &self,
+        name: &str,
+        val: T,
+        set: impl FnOnce(&S, &str, T) -> Result<(), S::Error>,
+    ) -> Result<(), FaultyStorageError<S::Error>> {
+        self.check(name, true)?;
+        // values other than blobs are written atomically so power loss drops the whole write
+        if self.power_loss_after_bytes.take().is_some() {
+            self.powered.set(false);
+            return Err(FaultyStorageError::PowerLoss);
+        }
+        set(&self.storage, name, val).map_err(FaultyStorageError::StorageError)
+    }
+}
+
+impl<S: Storage> Storage for FaultyStorage<S> {
+    type Error = FaultyStorageError<S::Error>;
+
+    fn stats(&self) -> Result<StorageStats, Self::Error> {
+        self.check("", false)?;
+        self.storage.stats().map_err(FaultyStorageError::StorageError)
+    }
+
+    fn get_blob<'a>(&self, name: &str, buf: &'a mut [u8]) -> Result<Option<&'a [u8]>, Self::Error> {
+        self.check(name, false)?;
+        let blob = self.storage.get_blob(name, buf).map_err(FaultyStorageError::StorageError)?;
+        Ok(blob.map(|blob| match self.truncate_blobs_to.get() {
+            Some(len) if len < blob.len() => &blob[..len],
+            _ => blob,
+        }))
+    }
+
+    fn set_blob(&mut self, name: &str, val: &[u8]) -> Result<(), Self::Error> {
+        self.check(name, true)?;
+        if let Some(bytes) = self.power_loss_after_bytes.take() {
+            let partial = &val[..bytes.min(val.len())];
+            self.storage.set_blob(name, partial).map_err(FaultyStorageError::StorageError)?;
+            self.powered.set(false);
+            return Err(FaultyStorageError::PowerLoss);
+        }
+        self.storage.set_blob(name, val).map_err(FaultyStorageError::StorageError)
+    }
+
+    fn remove(&mut self, name: &str) -> Result<bool, Self::Error> {
+        self.check(name, false)?;
+        self.storage.remove(name).map_err(FaultyStorageError::StorageError)
+    }
+
+    fn get_u8(&self, name: &str) -> Result<Option<u8>, Self::Error> {
+        self.get(name, S::get_u8)
+    }
+
+    fn set_u8(&self, name: &str, val: u8) -> Result<(), Self::Error> {
+        self.set(name, val, S::set_u8)
+    }
+
+    fn get_i8(&self, name: &str) -> Result<Option<i8>, Self::Error> {
+        self.get(name, S::get_i8)
+    }
+
+    fn set_i8(&self, name: &str, val: i8) -> Result<(), Self::Error> {
+        self.set(name, val, S::set_i8)
+    }
+
+    fn get_u16(&self, name: &str) -> Result<Option<u16>, Self::Error> {
+        self.get(name, S::get_u16)
+    }
+
+    fn set_u16(&self, name: &str, val: u16) -> Result<(), Self::Error> {
+        self.set(name, val, S::set_u16)
+    }
+
+    fn get_i16(&self, name: &str) -> Result<Option<i16>, Self::Error> {
+        self.get(name, S::get_i16)
+    }
+
+    fn set_i16(&self, name: &str, val: i16) -> Result<(), Self::Error> {
+        self.set(name, val, S::set_i16)
+    }
+
+    fn get_u32(&self, name: &str) -> Result<Option<u32>, Self::Error> {
+        self.get(name, S::get_u32)
+    }
+
+    fn set_u32(&self, name: &str, val: u32) -> Result<(), Self::Error> {
+        self.set(name, val, S::set_u32)
+    }
+
+    fn get_i32(&self, name: &str) -> Result<Option<i32>, Self::Error> {
+        self.get(name, S::get_i32)
+    }
+
+    fn set_i32(&self, name: &str, val: i32) -> Result<(), Self::Error> {
+        self.set(name, val, S::set_i32)
+    }
+
+    fn get_u64(&self, name: &str) -> Result<Option<u64>, Self::Error> {
+        self.get(name, S::get_u64)
+    }
+
+    fn set_u64(&self, name: &str, val: u64) -> Result<(), Self::Error> {
+        self.set(name, val, S::set_u64)
+    }
+
+    fn get_i64(&self, name: &str) -> Result<Option<i64>, Self::Error> {
+        self.get(name, S::get_i64)
+    }
+
+    fn set_i64(&self, name: &str, val: i64) -> Result<(), Self::Error> {
+        self.set(name, val, S::set_i64)
+    }
+}
@@ -0,0 +1,45 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io;
+use std::rc::Rc;
+
+use crate::traits::byte_stream::ByteStream;
+
+// One end of an in-memory pipe, what one end writes the other reads
+pub struct MockDuplex {
+    incoming: Rc<RefCell<VecDeque<u8>>>,
+    outgoing: Rc<RefCell<VecDeque<u8>>>,
+}
+
+impl MockDuplex {
+    pub fn pair() -> (MockDuplex, MockDuplex) {
+        let a = Rc::new(RefCell::new(VecDeque::new()));
+        let b = Rc::new(RefCell::new(VecDeque::new()));
+        (
+            MockDuplex { incoming: a.clone(), outgoing: b.clone() },
+            MockDuplex { incoming: b, outgoing: a },
+        )
+    }
+
+    pub fn read_all(&mut self) -> Vec<u8> {
+        self.incoming.borrow_mut().drain(..).collect()
+    }
+}
+
+impl ByteStream for MockDuplex {
+    type Error = io::Error;
+
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Self::Error> {
+        let mut incoming = self.incoming.borrow_mut();
+        let len = buffer.len().min(incoming.len());
+        for (byte, received) in buffer.iter_mut().zip(incoming.drain(..len)) {
+            *byte = received;
+        }
+        Ok(len)
+    }
+
+    fn write_all(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        self.outgoing.borrow_mut().extend(data);
+        Ok(())
+    }
+}
@@ -0,0 +1,20 @@
+use std::net::{IpAddr, Ipv4Addr, UdpSocket};
+use std::thread;
+
+pub(crate) const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+// Echoes the given number of datagrams, dropping the first ones as told
+pub(crate) fn stand_in_echo(count: usize, dropped: usize) -> u16 {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let port = socket.local_addr().unwrap().port();
+    thread::spawn(move || {
+        for index in 0..count {
+            let mut buffer = [0_u8; 64];
+            let (len, from) = socket.recv_from(&mut buffer).unwrap();
+            if index >= dropped {
+                socket.send_to(&buffer[..len], from).unwrap();
+            }
+        }
+    });
+    port
+}
@@ -4,7 +4,12 @@ use std::fmt::Debug;
 
 use thiserror::Error;
 
-use crate::traits::storage::Storage;
+use crate::traits::storage::{NamespaceStats, Storage, StorageStats};
+
+// a single 0x6000 byte NVS partition has 3 pages of 126 entries
+const MOCK_TOTAL_ENTRIES: usize = 378;
+const ENTRY_BYTES: usize = 32;
+const MOCK_NAMESPACE: &str = "mock";
 
 pub enum MockEspNvsValue {
     BlobValue(Vec<u8>),
@@ -39,6 +44,25 @@ impl MockEspNvs {
 impl Storage for MockEspNvs {
     type Error = MockEspNvsStorageError;
 
+    fn stats(&self) -> Result<StorageStats, Self::Error> {
+        // blobs take a header entry plus one entry per 32 bytes of data
+        let used_entries = self.key_values.borrow().values().map(|value| match value {
+            MockEspNvsValue::BlobValue(value) => 1 + (value.len() + ENTRY_BYTES - 1) / ENTRY_BYTES,
+            _ => 1,
+        }).sum();
+        Ok(StorageStats {
+            used_entries,
+            free_entries: MOCK_TOTAL_ENTRIES.saturating_sub(used_entries),
+            total_entries: MOCK_TOTAL_ENTRIES,
+            namespace_count: 1,
+            namespace_used_entries: used_entries,
+            namespaces: heapless::Vec::from_slice(&[NamespaceStats {
+                name: heapless::String::from(MOCK_NAMESPACE),
+                used_entries,
+            }]).unwrap(),
+        })
+    }
+
     fn get_blob<'a>(&self, name: &str, buf: &'a mut [u8]) -> Result<Option<&'a [u8]>, Self::Error> {
         self.key_values.borrow().get(name).map(|value| match value {
             MockEspNvsValue::BlobValue(value) => {
@@ -54,6 +78,10 @@ impl Storage for MockEspNvs {
         Ok(())
     }
 
+    fn remove(&mut self, name: &str) -> Result<bool, Self::Error> {
+        Ok(self.key_values.borrow_mut().remove(name).is_some())
+    }
+
     fn get_u8(&self, name: &str) -> Result<Option<u8>, Self::Error> {
         self.key_values.borrow().get(name).map(|value| match value {
             MockEspNvsValue::U8Value(value) => Ok(*value),
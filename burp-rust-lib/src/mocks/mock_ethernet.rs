@@ -0,0 +1,117 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use embedded_svc::ipv4::{IpInfo, Ipv4Addr, Mask, Subnet};
+use thiserror::Error;
+
+use crate::traits::net_if::NetIf;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MockEthernetCall {
+    Start,
+    Stop,
+    WaitNetifUp,
+    GetIpInfo,
+    SetDefaultRoute,
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum MockEthernetError {
+    #[error("Scripted failure: {0}")]
+    Scripted(&'static str),
+}
+
+pub struct MockEthernetState {
+    pub fail_start: bool,
+    pub started: bool,
+    // the cable, only reported while started
+    pub plugged_in: bool,
+    // wait_netif_up never completes, as when no DHCP server answers
+    pub dhcp_stalled: bool,
+    pub ip_info: IpInfo,
+    pub calls: Vec<MockEthernetCall>,
+}
+
+// Clones share state so tests can keep a handle after moving the mock into a Network
+#[derive(Clone)]
+pub struct MockEthernet {
+    state: Rc<RefCell<MockEthernetState>>,
+}
+
+impl MockEthernet {
+    pub fn new() -> MockEthernet {
+        MockEthernet {
+            state: Rc::new(RefCell::new(MockEthernetState {
+                fail_start: false,
+                started: false,
+                plugged_in: true,
+                dhcp_stalled: false,
+                ip_info: IpInfo {
+                    ip: Ipv4Addr::new(192, 168, 2, 100),
+                    subnet: Subnet {
+                        gateway: Ipv4Addr::new(192, 168, 2, 1),
+                        mask: Mask(24),
+                    },
+                    dns: Some(Ipv4Addr::new(192, 168, 2, 1)),
+                    secondary_dns: None,
+                },
+                calls: Vec::new(),
+            })),
+        }
+    }
+
+    pub fn state(&self) -> std::cell::RefMut<'_, MockEthernetState> {
+        self.state.borrow_mut()
+    }
+
+    pub fn calls(&self) -> Vec<MockEthernetCall> {
+        self.state.borrow().calls.clone()
+    }
+
+    fn record(&self, call: MockEthernetCall) {
+        self.state.borrow_mut().calls.push(call);
+    }
+}
+
+impl NetIf for MockEthernet {
+    type Error = MockEthernetError;
+
+    async fn start(&mut self) -> Result<(), Self::Error> {
+        self.record(MockEthernetCall::Start);
+        let mut state = self.state.borrow_mut();
+        if state.fail_start {
+            return Err(MockEthernetError::Scripted("start"));
+        }
+        state.started = true;
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<(), Self::Error> {
+        self.record(MockEthernetCall::Stop);
+        self.state.borrow_mut().started = false;
+        Ok(())
+    }
+
+    async fn wait_netif_up(&self) -> Result<(), Self::Error> {
+        self.record(MockEthernetCall::WaitNetifUp);
+        if self.state.borrow().dhcp_stalled {
+            std::future::pending::<()>().await;
+        }
+        Ok(())
+    }
+
+    fn is_connected(&self) -> Result<bool, Self::Error> {
+        let state = self.state.borrow();
+        Ok(state.started && state.plugged_in)
+    }
+
+    fn get_ip_info(&self) -> Result<IpInfo, Self::Error> {
+        self.record(MockEthernetCall::GetIpInfo);
+        Ok(self.state.borrow().ip_info)
+    }
+
+    fn set_default_route(&mut self) -> Result<(), Self::Error> {
+        self.record(MockEthernetCall::SetDefaultRoute);
+        Ok(())
+    }
+}
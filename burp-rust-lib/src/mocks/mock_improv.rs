@@ -0,0 +1,29 @@
+use crate::mocks::mock_duplex::MockDuplex;
+use crate::provisioning::improv::{DeviceInfo, Improv, ImprovPacket, ImprovParser};
+use crate::traits::byte_stream::ByteStream;
+
+// An Improv RPC command packet as sent by the host
+pub fn rpc(command: u8, strings: &[&str]) -> Vec<u8> {
+    let mut data = vec![command, 0];
+    for string in strings {
+        data.push(string.len() as u8);
+        data.extend_from_slice(string.as_bytes());
+    }
+    data[1] = (data.len() - 2) as u8;
+    ImprovPacket::new(0x03, &data).encode().to_vec()
+}
+
+// The packets the device sent to the host since the last call
+pub fn received(host: &mut MockDuplex) -> Vec<ImprovPacket> {
+    let mut parser = ImprovParser::new();
+    host.read_all().into_iter().filter_map(|byte| parser.push(byte)).map(Result::unwrap).collect()
+}
+
+pub fn improv<T: ByteStream>(device: T) -> Improv<'static, T> {
+    Improv::new(device, DeviceInfo {
+        firmware_name: "burp",
+        firmware_version: "0.1.0",
+        chip: "ESP32",
+        device_name: "burp-test",
+    }).with_redirect_url("http://burp-test.local/")
+}
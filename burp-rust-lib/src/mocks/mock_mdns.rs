@@ -0,0 +1,184 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use embedded_svc::ipv4::IpAddr;
+use thiserror::Error;
+
+use crate::traits::mdns::{Interface, Mdns, Protocol, QueryResult};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MockMdnsCall {
+    SetHostname(String),
+    SetInstanceName(String),
+    AddService {
+        instance_name: Option<String>,
+        service_type: String,
+        proto: String,
+        port: u16,
+        txt: Vec<(String, String)>,
+    },
+    RemoveService {
+        service_type: String,
+        proto: String,
+    },
+    RemoveServices,
+    Free,
+    QueryPtr {
+        service_type: String,
+        proto: String,
+        timeout: Duration,
+        max_results: usize,
+    },
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum MockMdnsError {
+    #[error("Scripted failure: {0}")]
+    Scripted(&'static str),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MockQueryResult {
+    pub instance_name: Option<String>,
+    pub hostname: Option<String>,
+    pub port: u16,
+    pub txt: Vec<(String, String)>,
+    pub addr: Vec<IpAddr>,
+    pub ip_protocol: Protocol,
+}
+
+pub struct MockMdnsState {
+    pub fail_set_hostname: bool,
+    pub fail_add_service: bool,
+    pub fail_query: bool,
+    pub query_results: Vec<MockQueryResult>,
+    pub calls: Vec<MockMdnsCall>,
+}
+
+// Clones share state so tests can keep a handle after moving the mock into a Network
+#[derive(Clone)]
+pub struct MockMdns {
+    state: Rc<RefCell<MockMdnsState>>,
+}
+
+impl MockMdns {
+    pub fn new() -> MockMdns {
+        MockMdns {
+            state: Rc::new(RefCell::new(MockMdnsState {
+                fail_set_hostname: false,
+                fail_add_service: false,
+                fail_query: false,
+                query_results: Vec::new(),
+                calls: Vec::new(),
+            })),
+        }
+    }
+
+    pub fn state(&self) -> std::cell::RefMut<'_, MockMdnsState> {
+        self.state.borrow_mut()
+    }
+
+    pub fn calls(&self) -> Vec<MockMdnsCall> {
+        self.state.borrow().calls.clone()
+    }
+
+    fn record(&self, call: MockMdnsCall) {
+        self.state.borrow_mut().calls.push(call);
+    }
+}
+
+impl Mdns for MockMdns {
+    type Error = MockMdnsError;
+    type QueryResult = MockQueryResult;
+
+    fn set_hostname(&mut self, hostname: &str) -> Result<(), Self::Error> {
+        self.record(MockMdnsCall::SetHostname(String::from(hostname)));
+        if self.state.borrow().fail_set_hostname {
+            return Err(MockMdnsError::Scripted("set_hostname"));
+        }
+        Ok(())
+    }
+
+    fn set_instance_name(&mut self, instance_name: &str) -> Result<(), Self::Error> {
+        self.record(MockMdnsCall::SetInstanceName(String::from(instance_name)));
+        Ok(())
+    }
+
+    fn add_service(
+        &mut self,
+        instance_name: Option<&str>,
+        service_type: &str,
+        proto: &str,
+        port: u16,
+        txt: &[(&str, &str)],
+    ) -> Result<(), Self::Error> {
+        self.record(MockMdnsCall::AddService {
+            instance_name: instance_name.map(String::from),
+            service_type: String::from(service_type),
+            proto: String::from(proto),
+            port,
+            txt: txt.iter().map(|(key, value)| (String::from(*key), String::from(*value))).collect(),
+        });
+        if self.state.borrow().fail_add_service {
+            return Err(MockMdnsError::Scripted("add_service"));
+        }
+        Ok(())
+    }
+
+    fn remove_service(&mut self, service_type: &str, proto: &str) -> Result<(), Self::Error> {
+        self.record(MockMdnsCall::RemoveService {
+            service_type: String::from(service_type),
+            proto: String::from(proto),
+        });
+        Ok(())
+    }
+
+    fn remove_services(&mut self) -> Result<(), Self::Error> {
+        self.record(MockMdnsCall::RemoveServices);
+        Ok(())
+    }
+
+    fn free(&mut self) {
+        self.record(MockMdnsCall::Free);
+    }
+
+    fn query_ptr(
+        &self,
+        service_type: &str,
+        proto: &str,
+        timeout: Duration,
+        max_results: usize,
+        results: &mut [Self::QueryResult],
+    ) -> Result<usize, Self::Error> {
+        self.record(MockMdnsCall::QueryPtr {
+            service_type: String::from(service_type),
+            proto: String::from(proto),
+            timeout,
+            max_results,
+        });
+        let state = self.state.borrow();
+        if state.fail_query {
+            return Err(MockMdnsError::Scripted("query_ptr"));
+        }
+        let size = state.query_results.len().min(max_results).min(results.len());
+        results[..size].clone_from_slice(&state.query_results[..size]);
+        Ok(size)
+    }
+
+    fn create_query_results<const N: usize>() -> heapless::Vec<Self::QueryResult, N> {
+        (0..N).map(|_| MockQueryResult::default()).collect()
+    }
+
+    fn convert_query_result(query_result: &Self::QueryResult) -> QueryResult {
+        QueryResult {
+            instance_name: &query_result.instance_name,
+            hostname: &query_result.hostname,
+            port: query_result.port,
+            txt: &query_result.txt,
+            addr: &query_result.addr,
+            interface: Interface::STA,
+            ip_protocol: query_result.ip_protocol,
+        }
+    }
+}
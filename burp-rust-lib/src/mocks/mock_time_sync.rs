@@ -0,0 +1,61 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::time::Duration;
+
+use thiserror::Error;
+
+use crate::traits::time_sync::{TimeSample, TimeSync};
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum MockTimeSyncError {
+    #[error("No answers left")]
+    Exhausted,
+}
+
+pub struct MockTimeSyncState {
+    // answered in order, one per query
+    pub answers: VecDeque<Result<TimeSample, MockTimeSyncError>>,
+    pub queried: Vec<Vec<String>>,
+}
+
+// Clones share state so tests can keep a handle after moving the mock into a TimeKeeper
+#[derive(Clone)]
+pub struct MockTimeSync {
+    state: Rc<RefCell<MockTimeSyncState>>,
+}
+
+impl MockTimeSync {
+    pub fn new() -> MockTimeSync {
+        MockTimeSync {
+            state: Rc::new(RefCell::new(MockTimeSyncState {
+                answers: VecDeque::new(),
+                queried: Vec::new(),
+            })),
+        }
+    }
+
+    pub fn answer(self, server: &str, utc: Duration) -> MockTimeSync {
+        self.state.borrow_mut().answers.push_back(Ok(TimeSample {
+            server: server.to_string(),
+            utc,
+            round_trip: Duration::from_millis(20),
+            stratum: 1,
+        }));
+        self
+    }
+
+    pub fn state(&self) -> std::cell::RefMut<'_, MockTimeSyncState> {
+        self.state.borrow_mut()
+    }
+}
+
+impl TimeSync for MockTimeSync {
+    type Error = MockTimeSyncError;
+
+    async fn query(&mut self, servers: &[&str]) -> Result<TimeSample, Self::Error> {
+        let mut state = self.state.borrow_mut();
+        state.queried.push(servers.iter().map(|server| server.to_string()).collect());
+        state.answers.pop_front().unwrap_or(Err(MockTimeSyncError::Exhausted))
+    }
+}
@@ -0,0 +1,43 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::traits::timer::Timer;
+
+struct MockTimerState {
+    now: Duration,
+    sleeps: Vec<Duration>,
+}
+
+// Simulated clock, sleeping advances time immediately
+#[derive(Clone)]
+pub struct MockTimer {
+    state: Rc<RefCell<MockTimerState>>,
+}
+
+impl MockTimer {
+    pub fn new() -> MockTimer {
+        MockTimer {
+            state: Rc::new(RefCell::new(MockTimerState {
+                now: Duration::ZERO,
+                sleeps: Vec::new(),
+            })),
+        }
+    }
+
+    pub fn sleeps(&self) -> Vec<Duration> {
+        self.state.borrow().sleeps.clone()
+    }
+}
+
+impl Timer for MockTimer {
+    fn now(&self) -> Duration {
+        self.state.borrow().now
+    }
+
+    async fn after(&mut self, duration: Duration) {
+        let mut state = self.state.borrow_mut();
+        state.now += duration;
+        state.sleeps.push(duration);
+    }
+}
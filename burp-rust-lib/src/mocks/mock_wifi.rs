@@ -0,0 +1,227 @@
+use std::cell::RefCell;
+use std::ops::RangeInclusive;
+use std::rc::Rc;
+
+use embedded_svc::ipv4::{IpInfo, Ipv4Addr, Ipv6Addr, Mask, Subnet};
+use embedded_svc::wifi::{AccessPointInfo, AuthMethod, Configuration, SecondaryChannel};
+use thiserror::Error;
+
+use crate::futures::yield_now;
+use crate::traits::net_if::NetIf;
+use crate::traits::wifi::Wifi;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MockWifiCall {
+    SetConfiguration(Box<Configuration>),
+    Start,
+    Scan,
+    Connect,
+    Disconnect,
+    Stop,
+    WaitNetifUp,
+    GetIpInfo,
+    SetDefaultRoute,
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum MockWifiError {
+    #[error("Scripted failure: {0}")]
+    Scripted(&'static str),
+}
+
+pub struct MockWifiState {
+    pub scan_results: Vec<AccessPointInfo>,
+    pub fail_start: bool,
+    pub fail_scan: bool,
+    // number of upcoming connect calls that fail
+    pub fail_connects: usize,
+    // number of polls before wait_netif_up completes
+    pub netif_up_delay_polls: usize,
+    pub ip_info: IpInfo,
+    pub connected: bool,
+    pub rssi: i8,
+    pub disconnect_reason: Option<u16>,
+    pub ipv6_addresses: Vec<Ipv6Addr>,
+    pub channels: RangeInclusive<u8>,
+    pub calls: Vec<MockWifiCall>,
+}
+
+// Clones share state so tests can keep a handle after moving the mock into a Network
+#[derive(Clone)]
+pub struct MockWifi {
+    state: Rc<RefCell<MockWifiState>>,
+}
+
+pub fn access_point(ssid: &str, channel: u8) -> AccessPointInfo {
+    AccessPointInfo {
+        ssid: ssid.into(),
+        bssid: [0x02, 0x00, 0x00, 0x00, 0x00, channel],
+        channel,
+        secondary_channel: SecondaryChannel::None,
+        signal_strength: -60,
+        protocols: Default::default(),
+        auth_method: AuthMethod::WPA2Personal,
+    }
+}
+
+impl MockWifi {
+    pub fn new() -> MockWifi {
+        MockWifi {
+            state: Rc::new(RefCell::new(MockWifiState {
+                scan_results: Vec::new(),
+                fail_start: false,
+                fail_scan: false,
+                fail_connects: 0,
+                netif_up_delay_polls: 0,
+                ip_info: IpInfo {
+                    ip: Ipv4Addr::new(192, 168, 1, 100),
+                    subnet: Subnet {
+                        gateway: Ipv4Addr::new(192, 168, 1, 1),
+                        mask: Mask(24),
+                    },
+                    dns: Some(Ipv4Addr::new(192, 168, 1, 1)),
+                    secondary_dns: None,
+                },
+                connected: false,
+                rssi: -60,
+                disconnect_reason: None,
+                ipv6_addresses: Vec::new(),
+                channels: 1..=13,
+                calls: Vec::new(),
+            })),
+        }
+    }
+
+    pub fn with_scan_results(self, scan_results: Vec<AccessPointInfo>) -> MockWifi {
+        self.state.borrow_mut().scan_results = scan_results;
+        self
+    }
+
+    pub fn state(&self) -> std::cell::RefMut<'_, MockWifiState> {
+        self.state.borrow_mut()
+    }
+
+    pub fn calls(&self) -> Vec<MockWifiCall> {
+        self.state.borrow().calls.clone()
+    }
+
+    pub fn configurations(&self) -> Vec<Configuration> {
+        self.state.borrow().calls.iter().filter_map(|call| match call {
+            MockWifiCall::SetConfiguration(configuration) => Some(*configuration.clone()),
+            _ => None,
+        }).collect()
+    }
+
+    fn record(&self, call: MockWifiCall) {
+        self.state.borrow_mut().calls.push(call);
+    }
+}
+
+impl NetIf for MockWifi {
+    type Error = MockWifiError;
+
+    async fn start(&mut self) -> Result<(), Self::Error> {
+        self.record(MockWifiCall::Start);
+        if self.state.borrow().fail_start {
+            return Err(MockWifiError::Scripted("start"));
+        }
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<(), Self::Error> {
+        self.record(MockWifiCall::Stop);
+        self.state.borrow_mut().connected = false;
+        Ok(())
+    }
+
+    async fn wait_netif_up(&self) -> Result<(), Self::Error> {
+        self.record(MockWifiCall::WaitNetifUp);
+        let polls = self.state.borrow().netif_up_delay_polls;
+        for _ in 0..polls {
+            yield_now().await;
+        }
+        Ok(())
+    }
+
+    fn is_connected(&self) -> Result<bool, Self::Error> {
+        Ok(self.state.borrow().connected)
+    }
+
+    fn get_ip_info(&self) -> Result<IpInfo, Self::Error> {
+        self.record(MockWifiCall::GetIpInfo);
+        Ok(self.state.borrow().ip_info)
+    }
+
+    fn set_default_route(&mut self) -> Result<(), Self::Error> {
+        self.record(MockWifiCall::SetDefaultRoute);
+        Ok(())
+    }
+}
+
+impl Wifi for MockWifi {
+    fn set_configuration(&mut self, conf: &Configuration) -> Result<(), Self::Error> {
+        self.record(MockWifiCall::SetConfiguration(Box::new(conf.clone())));
+        Ok(())
+    }
+
+    async fn scan(&mut self) -> Result<Vec<AccessPointInfo>, Self::Error> {
+        self.record(MockWifiCall::Scan);
+        let state = self.state.borrow();
+        if state.fail_scan {
+            return Err(MockWifiError::Scripted("scan"));
+        }
+        Ok(state.scan_results.clone())
+    }
+
+    async fn connect(&mut self) -> Result<(), Self::Error> {
+        self.record(MockWifiCall::Connect);
+        let mut state = self.state.borrow_mut();
+        if state.fail_connects > 0 {
+            state.fail_connects -= 1;
+            return Err(MockWifiError::Scripted("connect"));
+        }
+        state.connected = true;
+        Ok(())
+    }
+
+    async fn disconnect(&mut self) -> Result<(), Self::Error> {
+        self.record(MockWifiCall::Disconnect);
+        self.state.borrow_mut().connected = false;
+        Ok(())
+    }
+
+    // Built from the last client configuration, calls are not recorded
+    fn ap_info(&self) -> Result<AccessPointInfo, Self::Error> {
+        let state = self.state.borrow();
+        if !state.connected {
+            return Err(MockWifiError::Scripted("ap_info"));
+        }
+        let client = state.calls.iter().rev().find_map(|call| match call {
+            MockWifiCall::SetConfiguration(configuration) => match configuration.as_ref() {
+                Configuration::Client(client) | Configuration::Mixed(client, _) => Some(client.clone()),
+                _ => None,
+            },
+            _ => None,
+        }).unwrap_or_default();
+        let mut ap_info = access_point(&client.ssid, client.channel.unwrap_or(1));
+        ap_info.signal_strength = state.rssi;
+        Ok(ap_info)
+    }
+
+    fn rssi(&self) -> Result<i8, Self::Error> {
+        self.ap_info().map(|ap_info| ap_info.signal_strength)
+    }
+
+    fn disconnect_reason(&self) -> Option<u16> {
+        self.state.borrow_mut().disconnect_reason.take()
+    }
+
+    fn ipv6_addresses(&self) -> Result<Vec<Ipv6Addr>, Self::Error> {
+        let state = self.state.borrow();
+        Ok(if state.connected { state.ipv6_addresses.clone() } else { Vec::new() })
+    }
+
+    fn channels(&self) -> Result<RangeInclusive<u8>, Self::Error> {
+        Ok(self.state.borrow().channels.clone())
+    }
+}
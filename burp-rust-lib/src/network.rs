@@ -1,7 +1,9 @@
+use std::convert::Infallible;
 use std::error::Error;
+use std::fmt::Display;
 use std::str::{from_utf8, Utf8Error};
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use embedded_svc::wifi::{AuthMethod, ClientConfiguration, Configuration};
 use heapless::String;
@@ -9,55 +11,239 @@ use log::*;
 use thiserror::Error;
 
 use crate::config::Config;
+use crate::events::{EventChannel, Subscriber};
 use crate::name::get_name;
+use crate::network::ethernet::{LinkPolicy, Links, NoEthernet};
+use crate::network::fast_connect::ConnectMetrics;
+use crate::network::ipv6::Ipv6Addresses;
+use crate::network::metrics::LinkMetrics;
+use crate::network::reconnect::{Reconnect, ReconnectPolicy};
+use crate::network::peers::{PeerRegistry, PeerRegistryPolicy};
+use crate::network::services::{MdnsPolicy, MdnsServices};
+use crate::network::soft_ap::{SoftAp, SoftApPolicy};
+use crate::network::state::{FailureReason, NetworkEvent, NetworkState};
+use crate::network::survey::Surveys;
+use crate::provisioning::captive_portal::CaptivePortal;
+use crate::provisioning::improv::Improv;
+use crate::time::{SharedClock, TimeKeeper};
+use crate::time::sntp::SntpClient;
+use crate::traits::byte_stream::SerialPort;
 use crate::traits::mdns::Mdns;
+use crate::traits::net_if::NetIf;
 use crate::traits::storage::Storage;
+use crate::traits::time_sync::TimeSync;
+use crate::traits::timer::Timer;
 use crate::traits::wifi::Wifi;
 
-pub struct Network<'a, S, W, M> {
+mod backoff;
+mod captive_portal;
+mod diagnostics;
+pub mod discovery;
+pub mod ethernet;
+pub mod fast_connect;
+mod hidden;
+mod improv;
+pub mod ipv6;
+pub mod metrics;
+pub mod peers;
+pub mod reconnect;
+pub mod services;
+pub mod soft_ap;
+pub mod state;
+mod stop;
+pub mod survey;
+mod time_sync;
+pub mod txt;
+
+pub const NETWORK_EVENTS_CAPACITY: usize = 8;
+
+// ssid and password
+type WifiCredentials = (String<32>, String<64>);
+
+pub struct Network<'a, S, W, M, E = NoEthernet, N = SntpClient> {
     config: Arc<Mutex<Config<'a, S>>>,
     wifi: W,
     mdns: M,
+    ssid: String<32>,
+    channel: Option<u8>,
+    // pinned when connecting straight to the last access point
+    bssid: Option<[u8; 6]>,
+    found_bssid: Option<[u8; 6]>,
+    scanned_auth_method: Option<AuthMethod>,
+    connect_metrics: ConnectMetrics,
+    link_metrics: LinkMetrics,
+    ipv6: Ipv6Addresses,
+    peers: PeerRegistry,
+    services: MdnsServices,
+    reconnect: Reconnect,
+    soft_ap: SoftAp,
+    portal: Option<CaptivePortal>,
+    improv: Option<Improv<'a, SerialPort>>,
+    stopped: bool,
+    surveys: Surveys,
+    time_keeper: Option<TimeKeeper<SharedClock, N>>,
+    ethernet: Option<E>,
+    links: Links,
+    events: EventChannel<NetworkEvent, NETWORK_EVENTS_CAPACITY>,
+    // time since boot, or since creation without with_timer
+    uptime: Box<dyn Fn() -> Duration>,
 }
 
 #[derive(Error, Debug)]
-pub enum NetworkError<W: Error, M: Error> {
+pub enum NetworkError<W: Error, M: Error, E: Error = Infallible> {
     Utf8Error(Utf8Error),
     WifiError(W),
     MdnsError(M),
+    EthernetError(E),
 }
 
-const MDNS_SERVICE_TYPE: &'static str = "_burptech";
-
-const MDNS_SERVICE_PROTOCOL: &'static str = "_tcp";
-
-const MDNS_SERVICE_PORT: u16 = 1234;
-
-const MDNS_QUERY_MAX_RESULTS: usize = 20;
-
-const MDNS_QUERY_TIMEOUT_SECONDS: u64 = 5;
-
 impl<S: Storage, W: Wifi, M: Mdns> Network<'_, S, W, M> {
     pub fn new(
         config: Arc<Mutex<Config<S>>>,
         wifi: W,
         mdns: M,
     ) -> Network<S, W, M> {
+        let created = Instant::now();
         Network {
             config,
             wifi,
             mdns,
+            ssid: String::new(),
+            channel: None,
+            bssid: None,
+            found_bssid: None,
+            scanned_auth_method: None,
+            connect_metrics: ConnectMetrics::default(),
+            link_metrics: LinkMetrics::new(),
+            ipv6: Ipv6Addresses::default(),
+            peers: PeerRegistry::new(PeerRegistryPolicy::default()),
+            services: MdnsServices::new(MdnsPolicy::default()),
+            reconnect: Reconnect::new(ReconnectPolicy::default(), name_seed()),
+            soft_ap: SoftAp::new(SoftApPolicy::default()),
+            portal: None,
+            improv: None,
+            stopped: false,
+            surveys: Surveys::new(),
+            time_keeper: None,
+            ethernet: None,
+            links: Links::new(LinkPolicy::default()),
+            events: EventChannel::new(),
+            uptime: Box::new(move || created.elapsed()),
+        }
+    }
+}
+
+impl<'a, S: Storage, W: Wifi, M: Mdns, E: NetIf, N: TimeSync> Network<'a, S, W, M, E, N> {
+    // Moves everything over for the builders that change the ethernet or time sync type
+    fn rebuild<E2, N2>(self, ethernet: Option<E2>, time_keeper: Option<TimeKeeper<SharedClock, N2>>) -> Network<'a, S, W, M, E2, N2> {
+        Network {
+            config: self.config,
+            wifi: self.wifi,
+            mdns: self.mdns,
+            ssid: self.ssid,
+            channel: self.channel,
+            bssid: self.bssid,
+            found_bssid: self.found_bssid,
+            scanned_auth_method: self.scanned_auth_method,
+            connect_metrics: self.connect_metrics,
+            link_metrics: self.link_metrics,
+            ipv6: self.ipv6,
+            peers: self.peers,
+            services: self.services,
+            reconnect: self.reconnect,
+            soft_ap: self.soft_ap,
+            portal: self.portal,
+            improv: self.improv,
+            stopped: self.stopped,
+            surveys: self.surveys,
+            time_keeper,
+            ethernet,
+            links: self.links,
+            events: self.events,
+            uptime: self.uptime,
+        }
+    }
+
+    // Measures connections, which happen outside supervising too, by the same clock supervising sleeps on
+    pub fn with_timer<T: Timer + 'static>(mut self, timer: T) -> Self {
+        self.uptime = Box::new(move || timer.now());
+        self
+    }
+
+    fn uptime(&self) -> Duration {
+        (self.uptime)()
+    }
+
+    pub fn with_reconnect_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect = Reconnect::new(policy, name_seed());
+        self
+    }
+
+    pub fn state(&self) -> NetworkState {
+        self.links.state()
+    }
+
+    // A handle other tasks can subscribe through while the network is busy
+    pub fn events(&self) -> EventChannel<NetworkEvent, NETWORK_EVENTS_CAPACITY> {
+        self.events.clone()
+    }
+
+    pub fn subscribe(&self) -> Subscriber<NetworkEvent, NETWORK_EVENTS_CAPACITY> {
+        self.events.subscribe()
+    }
+
+    pub async fn start(&mut self) -> Result<(), NetworkError<W::Error, M::Error, E::Error>> {
+        self.stopped = false;
+        self.start_ethernet().await?;
+        if self.links.policy.wifi {
+            // wifi problems are left to supervising while ethernet can carry traffic
+            if let Err(error) = self.start_wifi_link().await {
+                if !self.links.ethernet_up {
+                    return Err(error);
+                }
+                warn!("Wifi unavailable, continuing on ethernet: {:?}", error);
+            }
         }
+        self.update_default_route();
+        self.start_mdns().map_err(|error| NetworkError::MdnsError(self.fail(FailureReason::Mdns, error)))?;
+        Ok(())
     }
 
-    pub async fn start(&mut self) -> Result<(), NetworkError<W::Error, M::Error>> {
-        let ssid = self.get_ssid().map_err(NetworkError::Utf8Error)?;
-        let password = self.get_password().map_err(NetworkError::Utf8Error)?;
-        self.start_wifi(ssid, password).await.map_err(NetworkError::WifiError)?;
-        self.start_mdns().map_err(NetworkError::MdnsError)?;
+    async fn start_wifi_link(&mut self) -> Result<(), NetworkError<W::Error, M::Error, E::Error>> {
+        let (ssid, password) = self.get_credentials().map_err(NetworkError::Utf8Error)?;
+        if ssid.is_empty() {
+            info!("No wifi credentials configured");
+            self.enter_soft_ap(false).map_err(NetworkError::WifiError)?;
+            self.wifi.start().await.map_err(|error| self.fail_wifi(error))?;
+        } else {
+            self.start_wifi(ssid, password).await.map_err(|error| self.fail_wifi(error))?;
+            self.reconnect.connected = true;
+        }
         Ok(())
     }
 
+    fn get_credentials(&mut self) -> Result<WifiCredentials, Utf8Error> {
+        let ssid = self.get_ssid().map_err(|error| self.fail(FailureReason::InvalidConfig, error))?;
+        let password = self.get_password().map_err(|error| self.fail(FailureReason::InvalidConfig, error))?;
+        self.ssid = ssid.clone();
+        Ok((ssid, password))
+    }
+
+    fn publish(&self, event: NetworkEvent) {
+        info!("Network state: {:?}", event.state());
+        self.links.record(&event);
+        self.events.publish(event);
+    }
+
+    fn fail<D: Display>(&self, reason: FailureReason, error: D) -> D {
+        self.publish(NetworkEvent::failed(self.ssid.clone(), reason, &error));
+        error
+    }
+
+    fn fail_wifi(&self, error: W::Error) -> NetworkError<W::Error, M::Error, E::Error> {
+        NetworkError::WifiError(self.fail(self.links.wifi_state.get().failure_reason(), error))
+    }
+
     fn get_ssid(&self) -> Result<String<32>, Utf8Error> {
         Ok(String::from(
             from_utf8(self.config.lock().unwrap().ssid.get())?
@@ -73,61 +259,332 @@ impl<S: Storage, W: Wifi, M: Mdns> Network<'_, S, W, M> {
     async fn start_wifi(&mut self, ssid: String<32>, password: String<64>) -> Result<(), W::Error> {
         self.wifi.set_configuration(&Configuration::Client(ClientConfiguration::default()))?;
         self.wifi.start().await?;
+        let started = self.uptime();
+        let fast = match self.last_access_point() {
+            Some(last) => {
+                info!("Connecting to last access point {:02x?} on channel {} using {:?}", last.bssid, last.channel, last.auth_method);
+                self.connect_metrics.fast_attempts += 1;
+                (self.channel, self.bssid, self.scanned_auth_method) = (Some(last.channel), Some(last.bssid), Some(last.auth_method));
+                let result = self.connect_wifi(ssid.clone(), password.clone()).await;
+                if let Err(error) = &result {
+                    warn!("Connecting to last access point failed: {}, scanning instead", error);
+                    self.connect_metrics.fast_failures += 1;
+                }
+                result.is_ok()
+            }
+            None => false,
+        };
+        if !fast {
+            self.scan_and_connect_wifi(ssid, password).await?;
+        }
+        let elapsed = self.uptime().saturating_sub(started);
+        info!("Wifi connected in {:?}{}", elapsed, if fast { " without scanning" } else { "" });
+        self.connect_metrics.last_connect_time = Some(elapsed);
+        self.connect_metrics.last_connect_fast = fast;
+        Ok(())
+    }
+
+    async fn scan_and_connect_wifi(&mut self, ssid: String<32>, password: String<64>) -> Result<(), W::Error> {
+        if self.config.lock().unwrap().is_hidden() {
+            return self.connect_hidden_wifi(ssid, password).await;
+        }
         info!("Wifi scanning for ssid: {}", ssid);
+        self.publish(NetworkEvent::Scanning { ssid: ssid.clone() });
+        self.connect_metrics.scans += 1;
         let ap_infos = self.wifi.scan().await?;
-        let ours = ap_infos.into_iter().find(|a| a.ssid == ssid);
-        let channel = if let Some(ours) = ours {
-            info!("Found configured access point {} on channel {}", ssid, ours.channel);
-            Some(ours.channel)
+        let pinned_bssid = self.config.lock().unwrap().pinned_bssid();
+        let ours = ap_infos.into_iter()
+            .find(|a| a.ssid == ssid && pinned_bssid.map_or(true, |bssid| a.bssid == bssid));
+        self.bssid = None;
+        (self.channel, self.found_bssid, self.scanned_auth_method) = if let Some(ours) = ours {
+            info!("Found configured access point {} on channel {} using {:?}", ssid, ours.channel, ours.auth_method);
+            (Some(ours.channel), Some(ours.bssid), Some(ours.auth_method))
         } else {
             info!("Configured access point {} not found during scanning, will go with unknown channel", ssid);
-            None
+            (None, None, None)
         };
+        self.connect_wifi(ssid, password).await
+    }
 
-        self.wifi.set_configuration(&Configuration::Client(ClientConfiguration {
-            ssid,
-            password,
-            channel,
-            auth_method: AuthMethod::WPA2Personal,
-            ..Default::default()
-        }))?;
+    async fn connect_wifi(&mut self, ssid: String<32>, password: String<64>) -> Result<(), W::Error> {
+        self.publish(NetworkEvent::Connecting { ssid: ssid.clone(), channel: self.channel });
+        let auth_method = self.get_auth_method(&password);
+        let pinned_bssid = self.config.lock().unwrap().pinned_bssid();
+        info!("Using auth method {:?}", auth_method);
+        if auth_method != AuthMethod::None && password.is_empty() {
+            warn!("No password configured for {:?} access point {}", auth_method, ssid);
+        }
+        let client = ClientConfiguration {
+            ssid: ssid.clone(),
+            // open networks must not be given a password
+            password: if auth_method == AuthMethod::None { String::new() } else { password },
+            channel: self.channel,
+            bssid: pinned_bssid.or(self.bssid),
+            auth_method,
+        };
+        self.wifi.set_configuration(&self.wifi_configuration(client.clone()))?;
 
         info!("Connecting wifi...");
+        let connecting = self.uptime();
         self.wifi.connect().await?;
+        let connect_time = self.uptime().saturating_sub(connecting);
         info!("Waiting for DHCP lease...");
+        self.publish(NetworkEvent::ObtainingIp { ssid: ssid.clone(), channel: self.channel });
         self.wifi.wait_netif_up().await?;
+        let time_to_ip = self.uptime().saturating_sub(connecting) - connect_time;
         let ip_info = self.wifi.get_ip_info()?;
         info!("Wifi DHCP info: {:?}", ip_info);
+        self.remember_access_point().await;
+        self.start_session(ssid.clone(), connect_time, time_to_ip);
+        self.refresh_ipv6();
+        if self.soft_ap.active {
+            self.leave_soft_ap(client)?;
+        }
+        self.publish(NetworkEvent::Online { ssid, channel: self.channel, ip_info });
         Ok(())
     }
 
+    // A configured override wins, then whatever the access point advertised when scanned.
+    // Unscanned access points are assumed open without a password and WPA2 otherwise.
+    fn get_auth_method(&self, password: &str) -> AuthMethod {
+        if let Some(auth_method) = self.config.lock().unwrap().auth_method_override() {
+            return auth_method;
+        }
+        match self.scanned_auth_method {
+            Some(auth_method) => auth_method,
+            None if password.is_empty() => AuthMethod::None,
+            None => AuthMethod::WPA2Personal,
+        }
+    }
+
     fn start_mdns(&mut self) -> Result<(), M::Error> {
         let name = get_name();
         info!("Setting MDNS hostname");
         self.mdns.set_hostname(name)?;
         info!("Setting MDNS instance name");
         self.mdns.set_instance_name(name)?;
-        info!("Adding _burptech service");
-        self.mdns.add_service(
-            Some(name),
-            MDNS_SERVICE_TYPE,
-            MDNS_SERVICE_PROTOCOL,
-            MDNS_SERVICE_PORT,
-            &[],
-        )?;
-        info!("Query _burptech services");
-        let mut results: heapless::Vec<M::QueryResult, MDNS_QUERY_MAX_RESULTS> = M::create_query_results();
-        let size = self.mdns.query_ptr(
-            MDNS_SERVICE_TYPE,
-            MDNS_SERVICE_PROTOCOL,
-            Duration::from_secs(MDNS_QUERY_TIMEOUT_SECONDS),
-            MDNS_QUERY_MAX_RESULTS,
-            &mut results,
-        )?;
-        info!("Query returned {} results", size);
-        for i in 0..size {
-            info!("Query result {}: {:?}", i, M::convert_query_result(&results[i]));
-        }
+        self.advertise_services()?;
+        let now = self.uptime();
+        self.query_peers(now);
         Ok(())
     }
 }
+
+fn name_seed() -> u32 {
+    get_name().bytes().fold(0x811c9dc5_u32, |hash, byte| (hash ^ byte as u32).wrapping_mul(0x01000193))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use embedded_svc::wifi::{AuthMethod, ClientConfiguration, Configuration};
+
+    use crate::config::Config;
+    use crate::mocks::block_on::block_on;
+    use crate::mocks::mock_esp_nvs::{MockEspNvs, MockEspNvsValue};
+    use crate::mocks::mock_mdns::{MockMdns, MockMdnsCall, MockMdnsError};
+    use crate::mocks::mock_wifi::{access_point, MockWifi, MockWifiCall, MockWifiError};
+    use crate::network::{Network, NetworkError};
+    use crate::network::state::{FailureReason, NetworkEvent, NetworkState};
+    use crate::traits::read_write::ReadWrite;
+
+    fn config(storage: MockEspNvs) -> Arc<Mutex<Config<'static, MockEspNvs>>> {
+        config_with_psk(storage, "test_psk")
+    }
+
+    fn config_with_psk(storage: MockEspNvs, psk: &'static str) -> Arc<Mutex<Config<'static, MockEspNvs>>> {
+        let mut config = Config::new(Arc::new(Mutex::new(storage)), "test_ssid", psk);
+        config.read().unwrap();
+        Arc::new(Mutex::new(config))
+    }
+
+    fn client_configuration(wifi: &MockWifi) -> ClientConfiguration {
+        match wifi.configurations().last().unwrap() {
+            Configuration::Client(client) => client.clone(),
+            configuration => panic!("Unexpected configuration: {:?}", configuration),
+        }
+    }
+
+    fn client_channel(wifi: &MockWifi) -> Option<u8> {
+        client_configuration(wifi).channel
+    }
+
+    fn connect_to(auth_method: AuthMethod, psk: &'static str) -> ClientConfiguration {
+        let mut ours = access_point("test_ssid", 6);
+        ours.auth_method = auth_method;
+        let wifi = MockWifi::new().with_scan_results(vec![ours]);
+        let mut network = Network::new(config_with_psk(MockEspNvs::from([]), psk), wifi.clone(), MockMdns::new());
+        block_on(network.start()).unwrap();
+        client_configuration(&wifi)
+    }
+
+    #[test]
+    fn connects_with_channel_of_scanned_ssid() {
+        let wifi = MockWifi::new().with_scan_results(vec![
+            access_point("other_ssid", 1),
+            access_point("test_ssid", 6),
+        ]);
+        let mdns = MockMdns::new();
+        let mut network = Network::new(config(MockEspNvs::from([])), wifi.clone(), mdns.clone());
+        block_on(network.start()).unwrap();
+        assert_eq!(client_channel(&wifi), Some(6));
+        match wifi.configurations().last().unwrap() {
+            Configuration::Client(client) => {
+                assert_eq!(client.ssid, "test_ssid");
+                assert_eq!(client.password, "test_psk");
+            }
+            configuration => panic!("Unexpected configuration: {:?}", configuration),
+        }
+        assert!(wifi.calls().ends_with(&[MockWifiCall::Connect, MockWifiCall::WaitNetifUp, MockWifiCall::GetIpInfo]));
+        assert!(mdns.calls().iter().any(|call| matches!(call, MockMdnsCall::AddService { port: 1234, .. })));
+    }
+
+    #[test]
+    fn connects_without_channel_when_ssid_not_scanned() {
+        let wifi = MockWifi::new().with_scan_results(vec![access_point("other_ssid", 1)]);
+        let mut network = Network::new(config(MockEspNvs::from([])), wifi.clone(), MockMdns::new());
+        block_on(network.start()).unwrap();
+        assert_eq!(client_channel(&wifi), None);
+        assert!(wifi.calls().contains(&MockWifiCall::Connect));
+    }
+
+    #[test]
+    fn waits_for_netif_up() {
+        let wifi = MockWifi::new();
+        wifi.state().netif_up_delay_polls = 3;
+        let mut network = Network::new(config(MockEspNvs::from([])), wifi.clone(), MockMdns::new());
+        block_on(network.start()).unwrap();
+        assert!(wifi.calls().ends_with(&[MockWifiCall::WaitNetifUp, MockWifiCall::GetIpInfo]));
+    }
+
+    #[test]
+    fn fails_on_invalid_utf8_ssid() {
+        let storage = MockEspNvs::from([
+            (String::from("ssid"), MockEspNvsValue::BlobValue(vec![0xff, 0xfe])),
+        ]);
+        let wifi = MockWifi::new();
+        let mut network = Network::new(config(storage), wifi.clone(), MockMdns::new());
+        assert!(matches!(block_on(network.start()), Err(NetworkError::Utf8Error(_))));
+        assert!(wifi.calls().is_empty());
+    }
+
+    #[test]
+    fn fails_when_connect_fails() {
+        let wifi = MockWifi::new();
+        wifi.state().fail_connects = 1;
+        let mdns = MockMdns::new();
+        let mut network = Network::new(config(MockEspNvs::from([])), wifi.clone(), mdns.clone());
+        assert!(matches!(block_on(network.start()), Err(NetworkError::WifiError(MockWifiError::Scripted("connect")))));
+        assert!(mdns.calls().is_empty());
+    }
+
+    #[test]
+    fn fails_when_mdns_registration_fails() {
+        let wifi = MockWifi::new();
+        let mdns = MockMdns::new();
+        mdns.state().fail_add_service = true;
+        let mut network = Network::new(config(MockEspNvs::from([])), wifi.clone(), mdns.clone());
+        assert!(matches!(block_on(network.start()), Err(NetworkError::MdnsError(MockMdnsError::Scripted("add_service")))));
+        assert!(wifi.calls().contains(&MockWifiCall::GetIpInfo));
+        assert!(!mdns.calls().iter().any(|call| matches!(call, MockMdnsCall::QueryPtr { .. })));
+    }
+
+    #[test]
+    fn publishes_state_events_while_starting() {
+        let wifi = MockWifi::new().with_scan_results(vec![access_point("test_ssid", 6)]);
+        let mut network = Network::new(config(MockEspNvs::from([])), wifi.clone(), MockMdns::new());
+        let mut subscriber = network.subscribe();
+        assert_eq!(network.state(), NetworkState::Idle);
+        block_on(network.start()).unwrap();
+        let states: Vec<NetworkState> = std::iter::from_fn(|| subscriber.try_recv()).map(|event| event.state()).collect();
+        assert_eq!(states, [
+            NetworkState::Scanning,
+            NetworkState::Connecting,
+            NetworkState::ObtainingIp,
+            NetworkState::Online,
+        ]);
+        assert_eq!(network.events().latest(), Some(NetworkEvent::Online {
+            ssid: "test_ssid".into(),
+            channel: Some(6),
+            ip_info: wifi.state().ip_info,
+        }));
+    }
+
+    #[test]
+    fn publishes_failure_reason() {
+        let wifi = MockWifi::new();
+        wifi.state().fail_connects = 1;
+        let mut network = Network::new(config(MockEspNvs::from([])), wifi.clone(), MockMdns::new());
+        assert!(block_on(network.start()).is_err());
+        assert_eq!(network.state(), NetworkState::Failed);
+        match network.events().latest().unwrap() {
+            NetworkEvent::Failed { ssid, reason, message } => {
+                assert_eq!(ssid, "test_ssid");
+                assert_eq!(reason, FailureReason::Connect);
+                assert_eq!(message, "Scripted failure: connect");
+            }
+            event => panic!("Unexpected event: {:?}", event),
+        }
+    }
+
+    #[test]
+    fn uses_auth_method_of_scanned_access_point() {
+        for auth_method in [
+            AuthMethod::WPA2Personal,
+            AuthMethod::WPA3Personal,
+            AuthMethod::WPAWPA2Personal,
+            AuthMethod::WPA2WPA3Personal,
+        ] {
+            let client = connect_to(auth_method, "test_psk");
+            assert_eq!(client.auth_method, auth_method);
+            assert_eq!(client.password, "test_psk");
+        }
+    }
+
+    #[test]
+    fn connects_to_open_network_without_password() {
+        let client = connect_to(AuthMethod::None, "");
+        assert_eq!(client.auth_method, AuthMethod::None);
+        assert_eq!(client.password, "");
+        let client = connect_to(AuthMethod::None, "ignored_psk");
+        assert_eq!(client.auth_method, AuthMethod::None);
+        assert_eq!(client.password, "");
+    }
+
+    #[test]
+    fn guesses_auth_method_when_ssid_not_scanned() {
+        let wifi = MockWifi::new();
+        let mut network = Network::new(config_with_psk(MockEspNvs::from([]), ""), wifi.clone(), MockMdns::new());
+        block_on(network.start()).unwrap();
+        assert_eq!(client_configuration(&wifi).auth_method, AuthMethod::None);
+        let wifi = MockWifi::new();
+        let mut network = Network::new(config(MockEspNvs::from([])), wifi.clone(), MockMdns::new());
+        block_on(network.start()).unwrap();
+        assert_eq!(client_configuration(&wifi).auth_method, AuthMethod::WPA2Personal);
+    }
+
+    #[test]
+    fn connects_to_hidden_network_without_scanning() {
+        let wifi = MockWifi::new().with_scan_results(vec![access_point("test_ssid", 6)]);
+        let config = config(MockEspNvs::from([]));
+        config.lock().unwrap().set_hidden(true);
+        let mut network = Network::new(config, wifi.clone(), MockMdns::new());
+        block_on(network.start()).unwrap();
+        assert!(!wifi.calls().contains(&MockWifiCall::Scan));
+        assert_eq!(client_channel(&wifi), None);
+    }
+
+    #[test]
+    fn configured_auth_method_overrides_scan() {
+        let wifi = MockWifi::new().with_scan_results(vec![access_point("test_ssid", 6)]);
+        let config = config(MockEspNvs::from([]));
+        config.lock().unwrap().set_auth_method_override(Some(AuthMethod::WPA3Personal));
+        config.lock().unwrap().write().unwrap();
+        config.lock().unwrap().read().unwrap();
+        assert_eq!(config.lock().unwrap().auth_method_override(), Some(AuthMethod::WPA3Personal));
+        let mut network = Network::new(config, wifi.clone(), MockMdns::new());
+        block_on(network.start()).unwrap();
+        assert_eq!(client_configuration(&wifi).auth_method, AuthMethod::WPA3Personal);
+    }
+}
@@ -0,0 +1,69 @@
+use std::time::Duration;
+
+// Capped exponential backoff with +/- jitter_percent of random jitter
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    jitter_percent: u8,
+    current: Duration,
+    rng: u32,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration, jitter_percent: u8, seed: u32) -> Backoff {
+        Backoff {
+            initial,
+            max,
+            jitter_percent: jitter_percent.min(100),
+            current: initial,
+            // xorshift gets stuck on zero
+            rng: seed | 1,
+        }
+    }
+
+    pub fn next_delay(&mut self) -> Duration {
+        let base = self.current;
+        self.current = (self.current * 2).min(self.max);
+        let jitter = base * self.jitter_percent as u32 / 100;
+        if jitter.is_zero() {
+            return base;
+        }
+        let random = self.next_random() as u64 % (2 * jitter.as_millis() as u64 + 1);
+        (base + Duration::from_millis(random)).saturating_sub(jitter)
+    }
+
+    pub fn reset(&mut self) {
+        self.current = self.initial;
+    }
+
+    fn next_random(&mut self) -> u32 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        self.rng
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::network::backoff::Backoff;
+
+    #[test]
+    fn doubles_up_to_max() {
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(5), 0, 1);
+        let delays: Vec<u64> = (0..5).map(|_| backoff.next_delay().as_secs()).collect();
+        assert_eq!(delays, [1, 2, 4, 5, 5]);
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn jitter_stays_within_bounds() {
+        let mut backoff = Backoff::new(Duration::from_secs(10), Duration::from_secs(10), 20, 1234);
+        let delays: Vec<Duration> = (0..100).map(|_| backoff.next_delay()).collect();
+        assert!(delays.iter().all(|delay| *delay >= Duration::from_secs(8) && *delay <= Duration::from_secs(12)));
+        assert!(delays.iter().any(|delay| *delay != delays[0]));
+    }
+}
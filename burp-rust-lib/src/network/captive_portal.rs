@@ -0,0 +1,153 @@
+use std::time::Duration;
+
+use log::*;
+
+use crate::network::Network;
+use crate::provisioning::captive_portal::CaptivePortal;
+use crate::traits::mdns::Mdns;
+use crate::traits::net_if::NetIf;
+use crate::traits::storage::Storage;
+use crate::traits::time_sync::TimeSync;
+use crate::traits::timer::Timer;
+use crate::traits::wifi::Wifi;
+
+const PORTAL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+impl<S: Storage, W: Wifi, M: Mdns, E: NetIf, N: TimeSync> Network<'_, S, W, M, E, N> {
+    // Served whenever the soft access point is up
+    pub fn with_captive_portal(mut self, portal: CaptivePortal) -> Self {
+        self.portal = Some(portal);
+        self
+    }
+
+    // Waits out the station retry interval, serving the portal in the meantime and
+    // ending the wait early when it or Improv provisions new credentials
+    pub(crate) async fn wait_in_soft_ap<T: Timer>(&mut self, timer: &mut T) {
+        let interval = self.soft_ap.policy.station_retry_interval;
+        let bound = match self.portal.as_mut().map(|portal| portal.bind()) {
+            Some(Ok(())) => true,
+            Some(Err(error)) => {
+                warn!("Could not start portal: {}", error);
+                false
+            }
+            None => false,
+        };
+        if !bound {
+            self.wait(timer, interval).await;
+            return;
+        }
+        let networks = self.wifi.scan().await.unwrap_or_else(|error| {
+            warn!("Portal scan failed: {}", error);
+            Vec::new()
+        });
+        let started = timer.now();
+        while timer.now().saturating_sub(started) < interval {
+            let polled = self.portal.as_mut().map_or(Ok(None), |portal| portal.poll(&networks));
+            match polled {
+                Ok(Some(credentials)) => {
+                    if let Err(error) = credentials.apply(&mut self.config.lock().unwrap()) {
+                        warn!("Could not save provisioned credentials, using them until restart: {}", error);
+                    }
+                    self.reconnect.stats.consecutive_failures = 0;
+                    self.reconnect.backoff.reset();
+                    self.reconnect.rescan_pending = true;
+                    return;
+                }
+                Ok(None) => {}
+                Err(error) => warn!("Portal failed: {}", error),
+            }
+            if self.wait(timer, PORTAL_POLL_INTERVAL).await {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream, UdpSocket};
+    use std::str::from_utf8;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use embedded_svc::ipv4::Ipv4Addr;
+    use embedded_svc::wifi::Configuration;
+
+    use crate::config::Config;
+    use crate::mocks::block_on::block_on;
+    use crate::mocks::mock_esp_nvs::MockEspNvs;
+    use crate::mocks::mock_mdns::MockMdns;
+    use crate::mocks::mock_timer::MockTimer;
+    use crate::mocks::mock_wifi::{access_point, MockWifi, MockWifiCall};
+    use crate::network::Network;
+    use crate::network::state::NetworkState;
+    use crate::provisioning::captive_portal::CaptivePortal;
+    use crate::traits::read_write::ReadWrite;
+
+    fn exchange(addr: std::net::SocketAddr, request: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn provisions_credentials_over_localhost_and_connects() {
+        // the portal only binds once the access point is up, so the ports are picked beforehand
+        let http_addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let dns_addr = UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let portal = CaptivePortal::new(http_addr, dns_addr, Ipv4Addr::new(192, 168, 71, 1));
+        let wifi = MockWifi::new().with_scan_results(vec![access_point("home", 6)]);
+        let mut config = Config::new(Arc::new(Mutex::new(MockEspNvs::from([]))), "", "");
+        config.read().unwrap();
+        let config = Arc::new(Mutex::new(config));
+        let mut network = Network::new(config.clone(), wifi.clone(), MockMdns::new())
+            .with_captive_portal(portal);
+        block_on(network.start()).unwrap();
+        assert!(matches!(wifi.configurations().last(), Some(Configuration::Mixed(..))));
+        assert!(!network.portal.as_ref().unwrap().is_bound());
+
+        let client = thread::spawn(move || {
+            let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+            socket.set_read_timeout(Some(Duration::from_millis(200))).unwrap();
+            let query = [0, 1, 1, 0, 0, 1, 0, 0, 0, 0, 0, 0, 4, b't', b'e', b's', b't', 0, 0, 1, 0, 1];
+            let mut answer = [0_u8; 512];
+            // asks again until the portal has bound
+            let len = (0..25).find_map(|_| {
+                socket.send_to(&query, dns_addr).unwrap();
+                socket.recv(&mut answer).ok()
+            }).unwrap();
+            assert_eq!(&answer[len - 4..len], &[192, 168, 71, 1]);
+
+            let form = exchange(http_addr, "GET / HTTP/1.1\r\nHost: 192.168.71.1\r\n\r\n");
+            assert!(form.contains("<option value=\"home\">"));
+            let body = "ssid=home&psk=home_password";
+            exchange(http_addr, &format!("POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}", body.len(), body))
+        });
+        let mut timer = MockTimer::new();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while config.lock().unwrap().ssid.get().is_empty() {
+            assert!(Instant::now() < deadline, "portal was not provisioned");
+            block_on(network.supervise_once(&mut timer)).unwrap();
+        }
+        assert!(client.join().unwrap().contains("connecting to home"));
+        assert_eq!(from_utf8(config.lock().unwrap().psk.get()).unwrap(), "home_password");
+
+        wifi.state().calls.clear();
+        block_on(network.supervise_once(&mut timer)).unwrap();
+        assert_eq!(network.state(), NetworkState::Online);
+        assert!(!network.soft_ap_active());
+        assert!(!network.portal.as_ref().unwrap().is_bound());
+        assert!(wifi.calls().contains(&MockWifiCall::Scan));
+        match wifi.configurations().last().unwrap() {
+            Configuration::Client(client) => {
+                assert_eq!(client.ssid, "home");
+                assert_eq!(client.channel, Some(6));
+            }
+            configuration => panic!("Unexpected configuration: {:?}", configuration),
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use std::net::IpAddr;
+
+use log::*;
+
+use crate::diagnostics::{run, DiagnosticsPolicy, DiagnosticsReport};
+use crate::network::Network;
+use crate::network::ethernet::Uplink;
+use crate::traits::mdns::Mdns;
+use crate::traits::net_if::NetIf;
+use crate::traits::ping::Ping;
+use crate::traits::storage::Storage;
+use crate::traits::time_sync::TimeSync;
+use crate::traits::wifi::Wifi;
+
+impl<S: Storage, W: Wifi, M: Mdns, E: NetIf, N: TimeSync> Network<'_, S, W, M, E, N> {
+    // Probes through the gateway of whichever uplink has the default route
+    pub async fn diagnose<P: Ping>(&mut self, pinger: &mut P, policy: &DiagnosticsPolicy) -> DiagnosticsReport {
+        let ip_info = match &self.ethernet {
+            Some(ethernet) if self.links.default_route == Some(Uplink::Ethernet) => {
+                ethernet.get_ip_info().map_err(|error| error.to_string())
+            }
+            _ if self.reconnect.connected => self.wifi.get_ip_info().map_err(|error| error.to_string()),
+            _ => Err(String::from("not connected")),
+        };
+        let gateway = ip_info
+            .map(|ip_info| IpAddr::from(ip_info.subnet.gateway.octets()))
+            .map_err(|error| warn!("Diagnosing without a gateway: {}", error))
+            .ok();
+        run(pinger, gateway, policy).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, TcpListener};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use embedded_svc::ipv4::Ipv4Addr;
+
+    use crate::config::Config;
+    use crate::diagnostics::DiagnosticsPolicy;
+    use crate::diagnostics::udp_echo::UdpEcho;
+    use crate::mocks::block_on::block_on;
+    use crate::mocks::mock_echo_server::stand_in_echo;
+    use crate::mocks::mock_esp_nvs::MockEspNvs;
+    use crate::mocks::mock_mdns::MockMdns;
+    use crate::mocks::mock_wifi::{access_point, MockWifi};
+    use crate::network::Network;
+    use crate::traits::read_write::ReadWrite;
+
+    #[test]
+    fn diagnoses_through_the_wifi_gateway() {
+        let mut config = Config::new(Arc::new(Mutex::new(MockEspNvs::from([]))), "test_ssid", "test_psk");
+        config.read().unwrap();
+        let wifi = MockWifi::new().with_scan_results(vec![access_point("test_ssid", 6)]);
+        wifi.state().ip_info.subnet.gateway = Ipv4Addr::new(127, 0, 0, 1);
+        let mut network = Network::new(Arc::new(Mutex::new(config)), wifi, MockMdns::new());
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let policy = DiagnosticsPolicy {
+            timeout: Duration::from_millis(200),
+            ping_count: 1,
+            gateway_port: listener.local_addr().unwrap().port(),
+            targets: Vec::new(),
+            internet_targets: Vec::new(),
+        };
+        let mut pinger = UdpEcho::new(stand_in_echo(1, 0));
+        assert_eq!(block_on(network.diagnose(&mut pinger, &policy)).gateway, None);
+
+        block_on(network.start()).unwrap();
+        let report = block_on(network.diagnose(&mut pinger, &policy));
+        assert_eq!(report.gateway, Some(IpAddr::from([127, 0, 0, 1])));
+        assert_eq!(report.gateway_ping.unwrap().result.unwrap().received, 1);
+        assert!(report.gateway_connect.unwrap().result.is_ok());
+    }
+}
@@ -0,0 +1,168 @@
+use embedded_svc::ipv4::IpAddr;
+
+use crate::network::Network;
+use crate::network::ipv6::is_link_local;
+use crate::traits::mdns::{Mdns, Protocol, QueryResult};
+use crate::traits::net_if::NetIf;
+use crate::traits::storage::Storage;
+use crate::traits::time_sync::TimeSync;
+use crate::traits::wifi::Wifi;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IpFamily {
+    #[default]
+    V4,
+    V6,
+}
+
+impl IpFamily {
+    pub fn of(address: &IpAddr) -> IpFamily {
+        match address {
+            IpAddr::V4(_) => IpFamily::V4,
+            IpAddr::V6(_) => IpFamily::V6,
+        }
+    }
+}
+
+// A service instance as answered over every family it was found on
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DiscoveredPeer {
+    pub instance_name: Option<String>,
+    pub hostname: Option<String>,
+    pub port: u16,
+    pub txt: Vec<(String, String)>,
+    pub addresses: Vec<IpAddr>,
+    pub families: Vec<IpFamily>,
+}
+
+impl DiscoveredPeer {
+    // The other family is used when the peer has no address in the preferred one.
+    // Link-local IPv6 addresses need a zone to connect to, so they come last.
+    pub fn address(&self, preferred: IpFamily) -> Option<IpAddr> {
+        self.addresses.iter()
+            .min_by_key(|address| {
+                let link_local = matches!(address, IpAddr::V6(address) if is_link_local(address));
+                (IpFamily::of(address) != preferred, link_local)
+            })
+            .copied()
+    }
+
+    fn merge(&mut self, result: &QueryResult) {
+        for address in result.addr {
+            if !self.addresses.contains(address) {
+                self.addresses.push(*address);
+            }
+        }
+        let family = match result.ip_protocol {
+            Protocol::V4 => IpFamily::V4,
+            Protocol::V6 => IpFamily::V6,
+        };
+        if !self.families.contains(&family) {
+            self.families.push(family);
+        }
+        if self.hostname.is_none() {
+            self.hostname = result.hostname.clone();
+        }
+        if self.txt.is_empty() {
+            self.txt = result.txt.clone();
+        }
+    }
+}
+
+// Answers for the same instance arrive separately for IPv4 and IPv6
+pub fn merge_query_results<'a>(results: impl IntoIterator<Item = QueryResult<'a>>) -> Vec<DiscoveredPeer> {
+    let mut peers: Vec<DiscoveredPeer> = Vec::new();
+    for result in results {
+        let existing = peers.iter_mut().find(|peer| {
+            peer.instance_name == *result.instance_name && peer.port == result.port
+        });
+        match existing {
+            Some(peer) => peer.merge(&result),
+            None => {
+                let mut peer = DiscoveredPeer {
+                    instance_name: result.instance_name.clone(),
+                    port: result.port,
+                    ..Default::default()
+                };
+                peer.merge(&result);
+                peers.push(peer);
+            }
+        }
+    }
+    peers
+}
+
+impl<S: Storage, W: Wifi, M: Mdns, E: NetIf, N: TimeSync> Network<'_, S, W, M, E, N> {
+    // As last answered, see peer_registry for when each was seen
+    pub fn discovered_peers(&self) -> impl Iterator<Item = &DiscoveredPeer> {
+        self.peers.peers().map(|tracked| &tracked.peer)
+    }
+
+    // Over the family preferred in the config
+    pub fn peer_address(&self, peer: &DiscoveredPeer) -> Option<IpAddr> {
+        peer.address(self.config.lock().unwrap().preferred_family())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use embedded_svc::ipv4::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    use crate::config::Config;
+    use crate::mocks::block_on::block_on;
+    use crate::mocks::mock_esp_nvs::MockEspNvs;
+    use crate::mocks::mock_mdns::{MockMdns, MockQueryResult};
+    use crate::mocks::mock_wifi::MockWifi;
+    use crate::network::Network;
+    use crate::network::discovery::{merge_query_results, IpFamily};
+    use crate::traits::mdns::{Mdns, Protocol};
+    use crate::traits::read_write::ReadWrite;
+
+    const V4: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 20));
+    const LINK_LOCAL: IpAddr = IpAddr::V6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0x0200, 0, 0, 20));
+    const GLOBAL: IpAddr = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 1, 0x0200, 0, 0, 20));
+
+    fn result(instance_name: &str, ip_protocol: Protocol, addr: Vec<IpAddr>) -> MockQueryResult {
+        MockQueryResult {
+            instance_name: Some(String::from(instance_name)),
+            port: 1234,
+            addr,
+            ip_protocol,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn merges_both_families_and_prefers_one() {
+        let results = [
+            result("peer", Protocol::V4, vec![V4]),
+            result("other", Protocol::V4, vec![V4]),
+            result("peer", Protocol::V6, vec![LINK_LOCAL, GLOBAL]),
+        ];
+        let peers = merge_query_results(results.iter().map(MockMdns::convert_query_result));
+        assert_eq!(peers.len(), 2);
+        assert_eq!(peers[0].addresses, [V4, LINK_LOCAL, GLOBAL]);
+        assert_eq!(peers[0].families, [IpFamily::V4, IpFamily::V6]);
+        assert_eq!(peers[0].address(IpFamily::V4), Some(V4));
+        assert_eq!(peers[0].address(IpFamily::V6), Some(GLOBAL));
+        assert_eq!(peers[1].address(IpFamily::V6), Some(V4));
+    }
+
+    #[test]
+    fn resolves_discovered_peers_over_preferred_family() {
+        let mut config = Config::new(Arc::new(Mutex::new(MockEspNvs::from([]))), "test_ssid", "test_psk");
+        config.read().unwrap();
+        config.set_preferred_family(IpFamily::V6);
+        let mdns = MockMdns::new();
+        mdns.state().query_results = vec![
+            result("peer", Protocol::V4, vec![V4]),
+            result("peer", Protocol::V6, vec![GLOBAL]),
+        ];
+        let mut network = Network::new(Arc::new(Mutex::new(config)), MockWifi::new(), mdns);
+        block_on(network.start()).unwrap();
+        let peer = network.discovered_peers().next().unwrap();
+        assert_eq!(network.peer_address(peer), Some(GLOBAL));
+    }
+}
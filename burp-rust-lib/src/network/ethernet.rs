@@ -0,0 +1,354 @@
+use std::cell::Cell;
+use std::convert::Infallible;
+use std::future::Future;
+use std::time::Duration;
+
+use embedded_svc::ipv4::IpInfo;
+use log::*;
+
+use crate::network::{Network, NetworkError};
+use crate::futures::{sleep, timeout};
+use crate::network::state::{FailureReason, NetworkEvent, NetworkState};
+use crate::traits::mdns::Mdns;
+use crate::traits::net_if::NetIf;
+use crate::traits::storage::Storage;
+use crate::traits::time_sync::TimeSync;
+use crate::traits::wifi::Wifi;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Uplink {
+    Wifi,
+    Ethernet,
+}
+
+#[derive(Debug, Clone)]
+pub struct LinkPolicy {
+    // false on wired boards that should leave the radio off
+    pub wifi: bool,
+    // given the default route whenever it is up
+    pub preferred: Uplink,
+    // for a lease once the cable is plugged in
+    pub dhcp_timeout: Duration,
+}
+
+impl Default for LinkPolicy {
+    fn default() -> Self {
+        LinkPolicy {
+            wifi: true,
+            preferred: Uplink::Ethernet,
+            dhcp_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+pub(crate) struct Links {
+    pub(crate) policy: LinkPolicy,
+    pub(crate) ethernet_up: bool,
+    pub(crate) default_route: Option<Uplink>,
+    // from the events published about each link
+    pub(crate) wifi_state: Cell<NetworkState>,
+    ethernet_state: Cell<NetworkState>,
+}
+
+impl Links {
+    pub(crate) fn new(policy: LinkPolicy) -> Links {
+        Links {
+            policy,
+            ethernet_up: false,
+            default_route: None,
+            wifi_state: Cell::new(NetworkState::Idle),
+            ethernet_state: Cell::new(NetworkState::Idle),
+        }
+    }
+
+    pub(crate) fn record(&self, event: &NetworkEvent) {
+        match event {
+            NetworkEvent::EthernetUp { .. } | NetworkEvent::EthernetDown
+            | NetworkEvent::Failed { reason: FailureReason::Ethernet, .. } => self.ethernet_state.set(event.state()),
+            NetworkEvent::Stopped { .. }
+            | NetworkEvent::Failed { reason: FailureReason::Mdns | FailureReason::Stop | FailureReason::InvalidConfig, .. } => {
+                self.wifi_state.set(event.state());
+                self.ethernet_state.set(event.state());
+            }
+            _ => self.wifi_state.set(event.state()),
+        }
+    }
+
+    // That of the link carrying the default route, or the only link before either has it
+    pub(crate) fn state(&self) -> NetworkState {
+        let uplink = self.default_route.unwrap_or(if self.policy.wifi { Uplink::Wifi } else { Uplink::Ethernet });
+        match uplink {
+            Uplink::Wifi => self.wifi_state.get(),
+            Uplink::Ethernet => self.ethernet_state.get(),
+        }
+    }
+}
+
+// Stands in for the ethernet interface of a network that only has wifi
+pub enum NoEthernet {}
+
+impl NetIf for NoEthernet {
+    type Error = Infallible;
+
+    async fn start(&mut self) -> Result<(), Self::Error> {
+        match *self {}
+    }
+
+    async fn stop(&mut self) -> Result<(), Self::Error> {
+        match *self {}
+    }
+
+    async fn wait_netif_up(&self) -> Result<(), Self::Error> {
+        match *self {}
+    }
+
+    fn is_connected(&self) -> Result<bool, Self::Error> {
+        match *self {}
+    }
+
+    fn get_ip_info(&self) -> Result<IpInfo, Self::Error> {
+        match *self {}
+    }
+
+    fn set_default_route(&mut self) -> Result<(), Self::Error> {
+        match *self {}
+    }
+}
+
+impl<'a, S: Storage, W: Wifi, M: Mdns, N: TimeSync> Network<'a, S, W, M, NoEthernet, N> {
+    pub fn with_ethernet<E: NetIf>(mut self, ethernet: E, policy: LinkPolicy) -> Network<'a, S, W, M, E, N> {
+        let time_keeper = self.time_keeper.take();
+        let mut network = self.rebuild(Some(ethernet), time_keeper);
+        network.links = Links::new(policy);
+        network
+    }
+}
+
+impl<S: Storage, W: Wifi, M: Mdns, E: NetIf, N: TimeSync> Network<'_, S, W, M, E, N> {
+    pub fn ethernet_up(&self) -> bool {
+        self.links.ethernet_up
+    }
+
+    pub fn default_route(&self) -> Option<Uplink> {
+        self.links.default_route
+    }
+
+    // Only fatal when there is no wifi to fall back on
+    pub(crate) async fn start_ethernet(&mut self) -> Result<(), NetworkError<W::Error, M::Error, E::Error>> {
+        let Some(ethernet) = &mut self.ethernet else {
+            return Ok(());
+        };
+        info!("Starting ethernet");
+        if let Err(error) = ethernet.start().await {
+            let error = self.fail(FailureReason::Ethernet, error);
+            if !self.links.policy.wifi {
+                return Err(NetworkError::EthernetError(error));
+            }
+            warn!("Ethernet unavailable, continuing on wifi: {}", error);
+            return Ok(());
+        }
+        // there is no supervising timer yet
+        self.check_ethernet(sleep(self.links.policy.dhcp_timeout)).await;
+        Ok(())
+    }
+
+    // Picks up the cable being plugged in or pulled out, giving up on the lease once lease_expiry finishes
+    pub(crate) async fn check_ethernet(&mut self, lease_expiry: impl Future) {
+        let Some(ethernet) = &self.ethernet else {
+            return;
+        };
+        let plugged_in = ethernet.is_connected().unwrap_or_else(|error| {
+            warn!("Ethernet link state unavailable: {}", error);
+            false
+        });
+        if plugged_in == self.links.ethernet_up {
+            return;
+        }
+        if plugged_in {
+            info!("Ethernet link up, waiting for DHCP lease...");
+            let result = match timeout(ethernet.wait_netif_up(), lease_expiry).await {
+                Some(Ok(())) => ethernet.get_ip_info().map_err(|error| error.to_string()),
+                Some(Err(error)) => Err(error.to_string()),
+                None => Err(format!("no lease within {:?}", self.links.policy.dhcp_timeout)),
+            };
+            match result {
+                Ok(ip_info) => {
+                    info!("Ethernet DHCP info: {:?}", ip_info);
+                    self.links.ethernet_up = true;
+                    self.publish(NetworkEvent::EthernetUp { ip_info });
+                }
+                Err(error) => {
+                    warn!("Ethernet DHCP failed: {}", self.fail(FailureReason::Ethernet, error));
+                }
+            }
+        } else {
+            warn!("Ethernet link down");
+            self.links.ethernet_up = false;
+            self.publish(NetworkEvent::EthernetDown);
+        }
+        self.update_default_route();
+    }
+
+    // The preferred uplink while it is up, otherwise whichever other one is.
+    // Wifi keeps the route to itself when there is no ethernet.
+    pub(crate) fn update_default_route(&mut self) {
+        let Some(ethernet) = &mut self.ethernet else {
+            return;
+        };
+        let preferred = self.links.policy.preferred;
+        let other = match preferred {
+            Uplink::Wifi => Uplink::Ethernet,
+            Uplink::Ethernet => Uplink::Wifi,
+        };
+        let up = |uplink| match uplink {
+            Uplink::Wifi => self.reconnect.connected,
+            Uplink::Ethernet => self.links.ethernet_up,
+        };
+        let route = [preferred, other].into_iter().find(|uplink| up(*uplink));
+        if route.is_none() || route == self.links.default_route {
+            return;
+        }
+        let result = match route {
+            Some(Uplink::Wifi) => self.wifi.set_default_route().map_err(|error| error.to_string()),
+            _ => ethernet.set_default_route().map_err(|error| error.to_string()),
+        };
+        match result {
+            Ok(()) => {
+                info!("Default route now over {:?}", route.unwrap());
+                self.links.default_route = route;
+            }
+            Err(error) => warn!("Could not move default route to {:?}: {}", route.unwrap(), error),
+        }
+    }
+
+    pub(crate) async fn stop_ethernet(&mut self) -> Result<(), E::Error> {
+        self.links.ethernet_up = false;
+        self.links.default_route = None;
+        match &mut self.ethernet {
+            Some(ethernet) => ethernet.stop().await,
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use crate::config::Config;
+    use crate::mocks::block_on::block_on;
+    use crate::mocks::mock_esp_nvs::MockEspNvs;
+    use crate::mocks::mock_ethernet::{MockEthernet, MockEthernetCall};
+    use crate::mocks::mock_mdns::MockMdns;
+    use crate::mocks::mock_timer::MockTimer;
+    use crate::mocks::mock_wifi::{MockWifi, MockWifiCall};
+    use crate::network::Network;
+    use crate::network::ethernet::{LinkPolicy, Uplink};
+    use crate::network::state::NetworkState;
+    use crate::traits::read_write::ReadWrite;
+
+    fn network_with_ethernet(ssid: &'static str, wifi: &MockWifi, ethernet: &MockEthernet, policy: LinkPolicy) -> Network<'static, MockEspNvs, MockWifi, MockMdns, MockEthernet> {
+        let mut config = Config::new(Arc::new(Mutex::new(MockEspNvs::from([]))), ssid, "test_psk");
+        config.read().unwrap();
+        Network::new(Arc::new(Mutex::new(config)), wifi.clone(), MockMdns::new())
+            .with_ethernet(ethernet.clone(), policy)
+    }
+
+    #[test]
+    fn brings_up_ethernet_alone() {
+        let (wifi, ethernet) = (MockWifi::new(), MockEthernet::new());
+        let mut network = network_with_ethernet("", &wifi, &ethernet, LinkPolicy {
+            wifi: false,
+            ..Default::default()
+        });
+        block_on(network.start()).unwrap();
+        assert_eq!(network.state(), NetworkState::Online);
+        assert_eq!(network.default_route(), Some(Uplink::Ethernet));
+        assert!(wifi.calls().is_empty());
+        assert_eq!(ethernet.calls(), [
+            MockEthernetCall::Start,
+            MockEthernetCall::WaitNetifUp,
+            MockEthernetCall::GetIpInfo,
+            MockEthernetCall::SetDefaultRoute,
+        ]);
+
+        ethernet.state().plugged_in = false;
+        let mut timer = MockTimer::new();
+        block_on(network.supervise_once(&mut timer)).unwrap();
+        assert_eq!(network.state(), NetworkState::Disconnected);
+        assert_eq!(timer.sleeps(), [Duration::from_secs(1)]);
+        assert!(wifi.calls().is_empty());
+    }
+
+    #[test]
+    fn moves_default_route_between_links() {
+        let (wifi, ethernet) = (MockWifi::new(), MockEthernet::new());
+        let mut network = network_with_ethernet("test_ssid", &wifi, &ethernet, LinkPolicy::default());
+        block_on(network.start()).unwrap();
+        assert!(network.ethernet_up());
+        assert_eq!(network.default_route(), Some(Uplink::Ethernet));
+        assert!(!wifi.calls().contains(&MockWifiCall::SetDefaultRoute));
+
+        assert_eq!(network.state(), NetworkState::Online);
+
+        let mut timer = MockTimer::new();
+        ethernet.state().plugged_in = false;
+        block_on(network.supervise_once(&mut timer)).unwrap();
+        assert_eq!(network.default_route(), Some(Uplink::Wifi));
+        assert!(wifi.calls().contains(&MockWifiCall::SetDefaultRoute));
+        assert_eq!(network.state(), NetworkState::Online);
+
+        ethernet.state().plugged_in = true;
+        block_on(network.supervise_once(&mut timer)).unwrap();
+        assert_eq!(network.default_route(), Some(Uplink::Ethernet));
+        assert_eq!(ethernet.calls().iter().filter(|call| **call == MockEthernetCall::SetDefaultRoute).count(), 2);
+
+        // the drop is retried on wifi while ethernet keeps the network online
+        wifi.state().connected = false;
+        wifi.state().fail_connects = 10;
+        block_on(network.supervise_once(&mut timer)).unwrap();
+        assert_eq!(network.default_route(), Some(Uplink::Ethernet));
+        assert_eq!(network.state(), NetworkState::Online);
+    }
+
+    #[test]
+    fn gives_up_waiting_for_an_ethernet_lease() {
+        let (wifi, ethernet) = (MockWifi::new(), MockEthernet::new());
+        ethernet.state().dhcp_stalled = true;
+        let mut network = network_with_ethernet("test_ssid", &wifi, &ethernet, LinkPolicy {
+            dhcp_timeout: Duration::from_millis(10),
+            ..Default::default()
+        });
+        block_on(network.start()).unwrap();
+        assert!(!network.ethernet_up());
+        assert_eq!(network.default_route(), Some(Uplink::Wifi));
+        assert_eq!(network.state(), NetworkState::Online);
+
+        // later waits run on the supervising timer
+        let mut timer = MockTimer::new();
+        block_on(network.supervise_once(&mut timer)).unwrap();
+        assert!(!network.ethernet_up());
+        assert_eq!(timer.sleeps().first(), Some(&Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn keeps_ethernet_when_wifi_fails() {
+        let (wifi, ethernet) = (MockWifi::new(), MockEthernet::new());
+        wifi.state().fail_start = true;
+        let mut network = network_with_ethernet("test_ssid", &wifi, &ethernet, LinkPolicy {
+            preferred: Uplink::Wifi,
+            ..Default::default()
+        });
+        block_on(network.start()).unwrap();
+        assert_eq!(network.default_route(), Some(Uplink::Ethernet));
+
+        let (wifi, ethernet) = (MockWifi::new(), MockEthernet::new());
+        ethernet.state().fail_start = true;
+        let mut wired = network_with_ethernet("", &wifi, &ethernet, LinkPolicy {
+            wifi: false,
+            ..Default::default()
+        });
+        assert!(block_on(wired.start()).is_err());
+    }
+}
@@ -0,0 +1,137 @@
+use std::time::Duration;
+
+use log::*;
+
+use crate::config::LastAccessPoint;
+use crate::network::Network;
+use crate::traits::mdns::Mdns;
+use crate::traits::net_if::NetIf;
+use crate::traits::async_read_write::AsyncReadWrite;
+use crate::traits::storage::Storage;
+use crate::traits::time_sync::TimeSync;
+use crate::traits::wifi::Wifi;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConnectMetrics {
+    // connections attempted straight to the last access point without scanning
+    pub fast_attempts: u32,
+    pub fast_failures: u32,
+    pub scans: u32,
+    // from starting wifi to having an ip address
+    pub last_connect_time: Option<Duration>,
+    pub last_connect_fast: bool,
+}
+
+impl<S: Storage, W: Wifi, M: Mdns, E: NetIf, N: TimeSync> Network<'_, S, W, M, E, N> {
+    pub fn connect_metrics(&self) -> ConnectMetrics {
+        self.connect_metrics
+    }
+
+    pub(crate) fn last_access_point(&self) -> Option<LastAccessPoint> {
+        let config = self.config.lock().unwrap();
+        if config.is_hidden() {
+            return None;
+        }
+        config.last_access_point()
+    }
+
+    // Only written when it changes to save flash wear
+    pub(crate) async fn remember_access_point(&mut self) {
+        let (Some(channel), Some(bssid), Some(auth_method)) = (self.channel, self.bssid.or(self.found_bssid), self.scanned_auth_method) else {
+            return;
+        };
+        let access_point = LastAccessPoint { channel, bssid, auth_method };
+        let mut last_ap = {
+            let mut config = self.config.lock().unwrap();
+            if config.last_access_point() == Some(access_point) {
+                return;
+            }
+            info!("Remembering access point {:02x?} on channel {} using {:?}", bssid, channel, auth_method);
+            config.set_last_access_point(Some(access_point));
+            config.last_ap.clone()
+        };
+        if let Err(error) = AsyncReadWrite::write(&mut last_ap).await {
+            warn!("Could not save access point: {}", error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use embedded_svc::wifi::{AuthMethod, Configuration};
+
+    use crate::config::{Config, LastAccessPoint};
+    use crate::mocks::block_on::block_on;
+    use crate::mocks::mock_esp_nvs::MockEspNvs;
+    use crate::mocks::mock_mdns::MockMdns;
+    use crate::mocks::mock_timer::MockTimer;
+    use crate::mocks::mock_wifi::{access_point, MockWifi, MockWifiCall};
+    use crate::network::Network;
+    use crate::traits::read_write::ReadWrite;
+
+    fn boot(storage: &Arc<Mutex<MockEspNvs>>, wifi: &MockWifi) -> Network<'static, MockEspNvs, MockWifi, MockMdns> {
+        let mut config = Config::new(storage.clone(), "test_ssid", "test_psk");
+        config.read().unwrap();
+        let mut network = Network::new(Arc::new(Mutex::new(config)), wifi.clone(), MockMdns::new())
+            .with_timer(MockTimer::new());
+        block_on(network.start()).unwrap();
+        network
+    }
+
+    fn scans(wifi: &MockWifi) -> usize {
+        wifi.calls().iter().filter(|call| **call == MockWifiCall::Scan).count()
+    }
+
+    #[test]
+    fn reconnects_to_last_access_point_without_scanning() {
+        let storage = Arc::new(Mutex::new(MockEspNvs::from([])));
+        let mut wpa3 = access_point("test_ssid", 6);
+        wpa3.auth_method = AuthMethod::WPA3Personal;
+        let wifi = MockWifi::new().with_scan_results(vec![wpa3.clone()]);
+        let network = boot(&storage, &wifi);
+        assert_eq!(scans(&wifi), 1);
+        assert!(!network.connect_metrics().last_connect_fast);
+
+        let wifi = MockWifi::new().with_scan_results(vec![wpa3]);
+        let network = boot(&storage, &wifi);
+        assert_eq!(scans(&wifi), 0);
+        match wifi.configurations().last().unwrap() {
+            Configuration::Client(client) => {
+                assert_eq!(client.channel, Some(6));
+                assert_eq!(client.bssid, Some(access_point("test_ssid", 6).bssid));
+                assert_eq!(client.auth_method, AuthMethod::WPA3Personal);
+            }
+            configuration => panic!("Unexpected configuration: {:?}", configuration),
+        }
+        let metrics = network.connect_metrics();
+        assert_eq!((metrics.fast_attempts, metrics.fast_failures, metrics.scans), (1, 0, 0));
+        assert!(metrics.last_connect_fast);
+        // nothing sleeps on the mock timer while connecting
+        assert_eq!(metrics.last_connect_time, Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn falls_back_to_scan_when_fast_connect_fails() {
+        let storage = Arc::new(Mutex::new(MockEspNvs::from([])));
+        boot(&storage, &MockWifi::new().with_scan_results(vec![access_point("test_ssid", 6)]));
+
+        let wifi = MockWifi::new().with_scan_results(vec![access_point("test_ssid", 11)]);
+        wifi.state().fail_connects = 1;
+        let network = boot(&storage, &wifi);
+        assert_eq!(scans(&wifi), 1);
+        let metrics = network.connect_metrics();
+        assert_eq!((metrics.fast_attempts, metrics.fast_failures, metrics.scans), (1, 1, 1));
+        assert!(!metrics.last_connect_fast);
+
+        let mut config = Config::new(storage.clone(), "test_ssid", "test_psk");
+        config.read().unwrap();
+        assert_eq!(config.last_access_point(), Some(LastAccessPoint {
+            channel: 11,
+            bssid: access_point("test_ssid", 11).bssid,
+            auth_method: AuthMethod::WPA2Personal,
+        }));
+    }
+}
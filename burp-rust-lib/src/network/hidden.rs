@@ -0,0 +1,103 @@
+use std::ops::RangeInclusive;
+
+use heapless::String;
+use log::*;
+
+use crate::network::Network;
+use crate::traits::mdns::Mdns;
+use crate::traits::net_if::NetIf;
+use crate::traits::storage::Storage;
+use crate::traits::time_sync::TimeSync;
+use crate::traits::wifi::Wifi;
+
+// allowed in every regulatory domain
+const FALLBACK_CHANNELS: RangeInclusive<u8> = 1..=11;
+
+impl<S: Storage, W: Wifi, M: Mdns, E: NetIf, N: TimeSync> Network<'_, S, W, M, E, N> {
+    // Hidden networks do not answer broadcast scans. Connecting without a channel sends probes
+    // for the ssid on every channel, if that fails each channel the country allows is probed
+    // on its own in case the access point only answers slower directed probes.
+    pub(crate) async fn connect_hidden_wifi(&mut self, ssid: String<32>, password: String<64>) -> Result<(), W::Error> {
+        info!("Not scanning for hidden ssid: {}", ssid);
+        (self.channel, self.bssid, self.found_bssid, self.scanned_auth_method) = (None, None, None, None);
+        let mut result = self.connect_wifi(ssid.clone(), password.clone()).await;
+        let channels = self.wifi.channels().unwrap_or_else(|error| {
+            warn!("Country channels unavailable: {}, probing {:?}", error, FALLBACK_CHANNELS);
+            FALLBACK_CHANNELS
+        });
+        for channel in channels {
+            let Err(error) = &result else {
+                break;
+            };
+            info!("Probing for hidden ssid {} on channel {} after: {}", ssid, channel, error);
+            self.channel = Some(channel);
+            result = self.connect_wifi(ssid.clone(), password.clone()).await;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use embedded_svc::wifi::Configuration;
+
+    use crate::config::Config;
+    use crate::mocks::block_on::block_on;
+    use crate::mocks::mock_esp_nvs::MockEspNvs;
+    use crate::mocks::mock_mdns::MockMdns;
+    use crate::mocks::mock_wifi::{access_point, MockWifi, MockWifiCall};
+    use crate::network::Network;
+    use crate::traits::read_write::ReadWrite;
+
+    fn config() -> Arc<Mutex<Config<'static, MockEspNvs>>> {
+        let mut config = Config::new(Arc::new(Mutex::new(MockEspNvs::from([]))), "test_ssid", "test_psk");
+        config.read().unwrap();
+        Arc::new(Mutex::new(config))
+    }
+
+    fn clients(wifi: &MockWifi) -> Vec<(Option<u8>, Option<[u8; 6]>)> {
+        wifi.configurations().into_iter().filter_map(|configuration| match configuration {
+            Configuration::Client(client) if !client.ssid.is_empty() => Some((client.channel, client.bssid)),
+            _ => None,
+        }).collect()
+    }
+
+    #[test]
+    fn probes_each_channel_for_hidden_network() {
+        let wifi = MockWifi::new();
+        wifi.state().fail_connects = 3;
+        let config = config();
+        config.lock().unwrap().set_hidden(true);
+        let mut network = Network::new(config, wifi.clone(), MockMdns::new());
+        block_on(network.start()).unwrap();
+        assert!(!wifi.calls().contains(&MockWifiCall::Scan));
+        assert_eq!(clients(&wifi), [(None, None), (Some(1), None), (Some(2), None), (Some(3), None)]);
+    }
+
+    #[test]
+    fn probes_only_channels_the_country_allows() {
+        let wifi = MockWifi::new();
+        wifi.state().fail_connects = 20;
+        wifi.state().channels = 1..=14;
+        let config = config();
+        config.lock().unwrap().set_hidden(true);
+        let mut network = Network::new(config, wifi.clone(), MockMdns::new());
+        assert!(block_on(network.start()).is_err());
+        let probed: Vec<_> = clients(&wifi).into_iter().filter_map(|(channel, _)| channel).collect();
+        assert_eq!(probed, (1..=14).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn connects_to_pinned_access_point() {
+        let pinned = access_point("test_ssid", 11);
+        let wifi = MockWifi::new().with_scan_results(vec![access_point("test_ssid", 1), pinned.clone()]);
+        let config = config();
+        config.lock().unwrap().set_pinned_bssid(Some(pinned.bssid));
+        assert_eq!(config.lock().unwrap().pinned_bssid(), Some(pinned.bssid));
+        let mut network = Network::new(config, wifi.clone(), MockMdns::new());
+        block_on(network.start()).unwrap();
+        assert_eq!(clients(&wifi), [(Some(11), Some(pinned.bssid))]);
+    }
+}
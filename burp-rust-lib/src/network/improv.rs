@@ -0,0 +1,166 @@
+use std::time::Duration;
+
+use log::*;
+
+use crate::network::Network;
+use crate::provisioning::improv::{Improv, ImprovCommand, ImprovError, ImprovState};
+use crate::traits::byte_stream::{ByteStream, SerialPort};
+use crate::traits::mdns::Mdns;
+use crate::traits::net_if::NetIf;
+use crate::traits::storage::Storage;
+use crate::traits::time_sync::TimeSync;
+use crate::traits::timer::Timer;
+use crate::traits::wifi::Wifi;
+
+const IMPROV_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+impl<'a, S: Storage, W: Wifi, M: Mdns, E: NetIf, N: TimeSync> Network<'a, S, W, M, E, N> {
+    // Served whenever supervision waits
+    pub fn with_improv(mut self, improv: Improv<'a, SerialPort>) -> Self {
+        self.improv = Some(improv);
+        self
+    }
+
+    // Sleeps for the duration while answering Improv, ending early when Improv provisions
+    // and connects. Returns whether it did.
+    pub(crate) async fn wait<T: Timer>(&mut self, timer: &mut T, duration: Duration) -> bool {
+        let Some(mut improv) = self.improv.take() else {
+            timer.after(duration).await;
+            return false;
+        };
+        let started = timer.now();
+        let provisioned = loop {
+            let before = improv.state();
+            if let Err(error) = self.serve_improv_once(&mut improv).await {
+                warn!("Improv failed: {}", error);
+            }
+            if before != ImprovState::Provisioned && improv.state() == ImprovState::Provisioned {
+                break true;
+            }
+            let remaining = duration.saturating_sub(timer.now().saturating_sub(started));
+            if remaining.is_zero() {
+                break false;
+            }
+            timer.after(remaining.min(IMPROV_POLL_INTERVAL)).await;
+        };
+        self.improv = Some(improv);
+        provisioned
+    }
+
+    // Answers whatever Improv received since the last call, connecting straight away when it
+    // receives credentials. Expects wifi to have been started.
+    pub async fn serve_improv_once<T: ByteStream>(&mut self, improv: &mut Improv<'_, T>) -> Result<(), ImprovError<T::Error>> {
+        while let Some(command) = improv.poll()? {
+            match command {
+                ImprovCommand::WifiNetworks => {
+                    let networks = self.wifi.scan().await.unwrap_or_else(|error| {
+                        warn!("Improv scan failed: {}", error);
+                        Vec::new()
+                    });
+                    improv.send_networks(&networks)?;
+                }
+                ImprovCommand::WifiSettings(credentials) => {
+                    if let Err(error) = credentials.apply(&mut self.config.lock().unwrap()) {
+                        warn!("Could not save provisioned credentials, using them until restart: {}", error);
+                    }
+                    self.ssid = credentials.ssid.clone();
+                    match self.scan_and_connect_wifi(credentials.ssid, credentials.psk).await {
+                        Ok(()) => {
+                            self.reconnect.connected = true;
+                            self.reconnect.stats.consecutive_failures = 0;
+                            self.reconnect.backoff.reset();
+                            improv.provisioned()?;
+                        }
+                        Err(error) => {
+                            self.fail(self.links.wifi_state.get().failure_reason(), error);
+                            improv.provisioning_failed()?;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::from_utf8;
+    use std::sync::{Arc, Mutex};
+
+    use crate::config::Config;
+    use crate::mocks::block_on::block_on;
+    use crate::mocks::mock_duplex::MockDuplex;
+    use crate::mocks::mock_esp_nvs::MockEspNvs;
+    use crate::mocks::mock_improv::{improv, received, rpc};
+    use crate::mocks::mock_mdns::MockMdns;
+    use crate::mocks::mock_timer::MockTimer;
+    use crate::mocks::mock_wifi::{access_point, MockWifi};
+    use crate::network::Network;
+    use crate::network::state::NetworkState;
+    use crate::provisioning::improv::{ImprovPacket, ImprovState};
+    use crate::traits::byte_stream::{ByteStream, SerialPort};
+    use crate::traits::read_write::ReadWrite;
+
+    fn config() -> Arc<Mutex<Config<'static, MockEspNvs>>> {
+        let mut config = Config::new(Arc::new(Mutex::new(MockEspNvs::from([]))), "", "");
+        config.read().unwrap();
+        Arc::new(Mutex::new(config))
+    }
+
+    #[test]
+    fn provisions_and_reports_redirect_url() {
+        let wifi = MockWifi::new().with_scan_results(vec![access_point("home", 6)]);
+        let config = config();
+        let mut network = Network::new(config.clone(), wifi.clone(), MockMdns::new());
+        let (device, mut host) = MockDuplex::pair();
+        let mut improv = improv(device);
+        host.write_all(&rpc(0x01, &["home", "home_password"])).unwrap();
+        block_on(network.serve_improv_once(&mut improv)).unwrap();
+        assert_eq!(improv.state(), ImprovState::Provisioned);
+        assert_eq!(network.state(), NetworkState::Online);
+        assert_eq!(from_utf8(config.lock().unwrap().ssid.get()).unwrap(), "home");
+        assert_eq!(received(&mut host), [
+            ImprovPacket::new(0x02, &[0x00]),
+            ImprovPacket::new(0x01, &[0x03]),
+            ImprovPacket::new(0x01, &[0x04]),
+            ImprovPacket::new(0x04, b"\x01\x18\x17http://burp-test.local/"),
+        ]);
+    }
+
+    #[test]
+    fn reports_failure_to_connect() {
+        let wifi = MockWifi::new();
+        wifi.state().fail_connects = 1;
+        let mut network = Network::new(config(), wifi.clone(), MockMdns::new());
+        let (device, mut host) = MockDuplex::pair();
+        let mut improv = improv(device);
+        host.write_all(&rpc(0x01, &["home", "home_password"])).unwrap();
+        block_on(network.serve_improv_once(&mut improv)).unwrap();
+        assert_eq!(improv.state(), ImprovState::Authorized);
+        assert_eq!(network.state(), NetworkState::Failed);
+        assert!(received(&mut host).ends_with(&[
+            ImprovPacket::new(0x02, &[0x03]),
+            ImprovPacket::new(0x01, &[0x02]),
+        ]));
+    }
+
+    #[test]
+    fn supervision_serves_improv_from_soft_access_point() {
+        let wifi = MockWifi::new().with_scan_results(vec![access_point("home", 6)]);
+        let (device, mut host) = MockDuplex::pair();
+        let mut network = Network::new(config(), wifi.clone(), MockMdns::new())
+            .with_improv(improv(Box::new(device) as SerialPort));
+        block_on(network.start()).unwrap();
+        assert_eq!(network.state(), NetworkState::SoftAp);
+        host.write_all(&rpc(0x01, &["home", "home_password"])).unwrap();
+        let mut timer = MockTimer::new();
+        block_on(network.supervise_once(&mut timer)).unwrap();
+        assert_eq!(network.state(), NetworkState::Online);
+        assert!(timer.sleeps().is_empty());
+        assert!(received(&mut host).ends_with(&[
+            ImprovPacket::new(0x01, &[0x04]),
+            ImprovPacket::new(0x04, b"\x01\x18\x17http://burp-test.local/"),
+        ]));
+    }
+}
@@ -0,0 +1,113 @@
+use embedded_svc::ipv4::Ipv6Addr;
+use heapless::Vec;
+use log::*;
+
+use crate::network::Network;
+use crate::traits::mdns::Mdns;
+use crate::traits::net_if::NetIf;
+use crate::traits::storage::Storage;
+use crate::traits::time_sync::TimeSync;
+use crate::traits::wifi::Wifi;
+
+// lwIP's default number of addresses per interface, any more reported are dropped
+pub const IPV6_MAX_ADDRESSES: usize = 3;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Ipv6Addresses {
+    // fe80::/10, assigned as soon as the link is up
+    pub link_local: Option<Ipv6Addr>,
+    // from router advertisements (SLAAC), including unique local addresses
+    pub global: Vec<Ipv6Addr, IPV6_MAX_ADDRESSES>,
+}
+
+impl Ipv6Addresses {
+    pub fn new(addresses: &[Ipv6Addr]) -> Ipv6Addresses {
+        let mut ipv6 = Ipv6Addresses::default();
+        for address in addresses.iter().filter(|address| !address.is_unspecified()) {
+            if is_link_local(address) {
+                ipv6.link_local.get_or_insert(*address);
+            } else if !ipv6.global.contains(address) {
+                ipv6.global.push(*address).ok();
+            }
+        }
+        ipv6
+    }
+
+    // Global addresses are routable and need no zone, so they are preferred
+    pub fn preferred(&self) -> Option<Ipv6Addr> {
+        self.global.first().copied().or(self.link_local)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.link_local.is_none() && self.global.is_empty()
+    }
+}
+
+pub fn is_link_local(address: &Ipv6Addr) -> bool {
+    address.segments()[0] & 0xffc0 == 0xfe80
+}
+
+impl<S: Storage, W: Wifi, M: Mdns, E: NetIf, N: TimeSync> Network<'_, S, W, M, E, N> {
+    pub fn ipv6_addresses(&self) -> &Ipv6Addresses {
+        &self.ipv6
+    }
+
+    // SLAAC addresses turn up a while after the link, so this is repeated while connected
+    pub(crate) fn refresh_ipv6(&mut self) {
+        let addresses = match self.wifi.ipv6_addresses() {
+            Ok(addresses) => Ipv6Addresses::new(&addresses),
+            Err(error) => {
+                warn!("IPv6 addresses unavailable: {}", error);
+                return;
+            }
+        };
+        if addresses != self.ipv6 {
+            info!("IPv6 addresses: link-local {:?}, global {:?}", addresses.link_local, addresses.global);
+            self.ipv6 = addresses;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use embedded_svc::ipv4::Ipv6Addr;
+
+    use crate::config::Config;
+    use crate::mocks::block_on::block_on;
+    use crate::mocks::mock_esp_nvs::MockEspNvs;
+    use crate::mocks::mock_mdns::MockMdns;
+    use crate::mocks::mock_timer::MockTimer;
+    use crate::mocks::mock_wifi::MockWifi;
+    use crate::network::Network;
+    use crate::network::ipv6::Ipv6Addresses;
+    use crate::traits::read_write::ReadWrite;
+
+    const LINK_LOCAL: Ipv6Addr = Ipv6Addr::new(0xfe80, 0, 0, 0, 0x0200, 0, 0, 1);
+    const GLOBAL: Ipv6Addr = Ipv6Addr::new(0x2001, 0xdb8, 0, 1, 0x0200, 0, 0, 1);
+
+    #[test]
+    fn classifies_addresses() {
+        let addresses = Ipv6Addresses::new(&[Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0), LINK_LOCAL, GLOBAL, GLOBAL]);
+        assert_eq!(addresses.link_local, Some(LINK_LOCAL));
+        assert_eq!(addresses.global, [GLOBAL]);
+        assert_eq!(addresses.preferred(), Some(GLOBAL));
+        assert_eq!(Ipv6Addresses::new(&[LINK_LOCAL]).preferred(), Some(LINK_LOCAL));
+    }
+
+    #[test]
+    fn tracks_slaac_addresses_while_connected() {
+        let mut config = Config::new(Arc::new(Mutex::new(MockEspNvs::from([]))), "test_ssid", "test_psk");
+        config.read().unwrap();
+        let wifi = MockWifi::new();
+        wifi.state().ipv6_addresses = vec![LINK_LOCAL];
+        let mut network = Network::new(Arc::new(Mutex::new(config)), wifi.clone(), MockMdns::new());
+        block_on(network.start()).unwrap();
+        assert_eq!(network.ipv6_addresses().preferred(), Some(LINK_LOCAL));
+
+        wifi.state().ipv6_addresses.push(GLOBAL);
+        block_on(network.supervise_once(&mut MockTimer::new())).unwrap();
+        assert_eq!(network.ipv6_addresses().global, [GLOBAL]);
+    }
+}
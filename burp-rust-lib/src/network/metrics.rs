@@ -0,0 +1,291 @@
+use std::time::Duration;
+
+use heapless::{Deque, String};
+use log::*;
+
+use crate::network::Network;
+use crate::traits::mdns::Mdns;
+use crate::traits::net_if::NetIf;
+use crate::traits::storage::Storage;
+use crate::traits::time_sync::TimeSync;
+use crate::traits::wifi::Wifi;
+
+pub const SESSION_HISTORY: usize = 8;
+pub const RSSI_HISTORY: usize = 32;
+const DISCONNECT_REASONS: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisconnectReason {
+    // noticed while supervising, the only one that is a real drop
+    LinkLost = 0,
+    Stopped = 1,
+    // connected again without the previous session being seen to end
+    Replaced = 2,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RssiStats {
+    pub samples: u32,
+    pub min: Option<i8>,
+    pub max: Option<i8>,
+    sum: i64,
+}
+
+impl RssiStats {
+    fn add(&mut self, rssi: i8) {
+        self.samples += 1;
+        self.sum += rssi as i64;
+        self.min = Some(self.min.map_or(rssi, |min| min.min(rssi)));
+        self.max = Some(self.max.map_or(rssi, |max| max.max(rssi)));
+    }
+
+    pub fn mean(&self) -> Option<i8> {
+        (self.samples > 0).then(|| (self.sum / self.samples as i64) as i8)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Session {
+    pub ssid: String<32>,
+    pub bssid: Option<[u8; 6]>,
+    pub channel: Option<u8>,
+    // uptime when it connected
+    pub started_at: Duration,
+    // from asking to connect until associated
+    pub connect_time: Duration,
+    // from associated until the DHCP lease
+    pub time_to_ip: Duration,
+    // None while the session is current
+    pub duration: Option<Duration>,
+    pub disconnect_reason: Option<DisconnectReason>,
+    // wifi_err_reason_t given by the driver for a lost link, eg. 8 when the access point
+    // was left or 200 after missing its beacons
+    pub wifi_reason: Option<u16>,
+    pub rssi: RssiStats,
+}
+
+// Times are uptimes from the network's timer, so the ones passed in must be too
+pub struct LinkMetrics {
+    current: Option<Session>,
+    history: Deque<Session, SESSION_HISTORY>,
+    // (uptime, rssi) for the current and earlier sessions
+    rssi_samples: Deque<(Duration, i8), RSSI_HISTORY>,
+    sessions: u32,
+    // over every session, indexed by DisconnectReason
+    ended: [u32; DISCONNECT_REASONS],
+    completed_uptime: Duration,
+}
+
+impl LinkMetrics {
+    pub(crate) fn new() -> LinkMetrics {
+        LinkMetrics {
+            current: None,
+            history: Deque::new(),
+            rssi_samples: Deque::new(),
+            sessions: 0,
+            ended: [0; DISCONNECT_REASONS],
+            completed_uptime: Duration::ZERO,
+        }
+    }
+
+    pub fn current_session(&self, now: Duration) -> Option<Session> {
+        self.current.as_ref().map(|session| Session {
+            duration: Some(now.saturating_sub(session.started_at)),
+            ..session.clone()
+        })
+    }
+
+    // Oldest first, not including the current session
+    pub fn history(&self) -> impl Iterator<Item = &Session> {
+        self.history.iter()
+    }
+
+    pub fn rssi_samples(&self) -> impl Iterator<Item = &(Duration, i8)> {
+        self.rssi_samples.iter()
+    }
+
+    pub fn last_rssi(&self) -> Option<i8> {
+        self.rssi_samples.back().map(|(_, rssi)| *rssi)
+    }
+
+    pub fn sessions(&self) -> u32 {
+        self.sessions
+    }
+
+    pub fn reconnects(&self) -> u32 {
+        self.sessions.saturating_sub(1)
+    }
+
+    // Sessions ended for the reason, including ones dropped from the history
+    pub fn disconnects(&self, reason: DisconnectReason) -> u32 {
+        self.ended[reason as usize]
+    }
+
+    // Links lost, not counting sessions that were stopped or replaced
+    pub fn total_disconnects(&self) -> u32 {
+        self.disconnects(DisconnectReason::LinkLost)
+    }
+
+    // Connected time across every session, including ones dropped from the history
+    pub fn total_uptime(&self, now: Duration) -> Duration {
+        self.completed_uptime + self.current.as_ref().map_or(Duration::ZERO, |session| now.saturating_sub(session.started_at))
+    }
+
+    pub(crate) fn session_started(&mut self, mut session: Session, now: Duration) {
+        self.session_ended(DisconnectReason::Replaced, None, now);
+        self.sessions += 1;
+        session.started_at = now;
+        self.current = Some(session);
+    }
+
+    pub(crate) fn session_ended(&mut self, reason: DisconnectReason, wifi_reason: Option<u16>, now: Duration) {
+        let Some(mut session) = self.current.take() else {
+            return;
+        };
+        let duration = now.saturating_sub(session.started_at);
+        info!("Wifi session on {} ended after {:?}: {:?} (wifi reason {:?})", session.ssid, duration, reason, wifi_reason);
+        self.ended[reason as usize] += 1;
+        self.completed_uptime += duration;
+        session.duration = Some(duration);
+        session.disconnect_reason = Some(reason);
+        session.wifi_reason = wifi_reason;
+        if self.history.is_full() {
+            self.history.pop_front();
+        }
+        self.history.push_back(session).ok();
+    }
+
+    pub(crate) fn add_rssi_sample(&mut self, rssi: i8, now: Duration) {
+        if let Some(session) = &mut self.current {
+            session.rssi.add(rssi);
+        }
+        if self.rssi_samples.is_full() {
+            self.rssi_samples.pop_front();
+        }
+        self.rssi_samples.push_back((now, rssi)).ok();
+    }
+}
+
+impl<S: Storage, W: Wifi, M: Mdns, E: NetIf, N: TimeSync> Network<'_, S, W, M, E, N> {
+    pub fn link_metrics(&self) -> &LinkMetrics {
+        &self.link_metrics
+    }
+
+    pub(crate) fn start_session(&mut self, ssid: String<32>, connect_time: Duration, time_to_ip: Duration) {
+        let ap_info = self.wifi.ap_info().map_err(|error| warn!("Access point info unavailable: {}", error)).ok();
+        self.link_metrics.session_started(Session {
+            ssid,
+            bssid: ap_info.as_ref().map(|ap_info| ap_info.bssid).or(self.bssid).or(self.found_bssid),
+            channel: ap_info.as_ref().map(|ap_info| ap_info.channel).or(self.channel),
+            started_at: Duration::ZERO,
+            connect_time,
+            time_to_ip,
+            duration: None,
+            disconnect_reason: None,
+            wifi_reason: None,
+            rssi: RssiStats::default(),
+        }, self.uptime());
+        if let Some(ap_info) = ap_info {
+            self.link_metrics.add_rssi_sample(ap_info.signal_strength, self.uptime());
+        }
+    }
+
+    pub(crate) fn sample_rssi(&mut self) {
+        match self.wifi.rssi() {
+            Ok(rssi) => self.link_metrics.add_rssi_sample(rssi, self.uptime()),
+            Err(error) => warn!("RSSI unavailable: {}", error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use heapless::String;
+
+    use crate::config::Config;
+    use crate::mocks::block_on::block_on;
+    use crate::mocks::mock_esp_nvs::MockEspNvs;
+    use crate::mocks::mock_mdns::MockMdns;
+    use crate::mocks::mock_timer::MockTimer;
+    use crate::mocks::mock_wifi::{access_point, MockWifi};
+    use crate::network::Network;
+    use crate::network::metrics::{DisconnectReason, LinkMetrics, RssiStats, Session, SESSION_HISTORY};
+    use crate::network::reconnect::ReconnectPolicy;
+    use crate::traits::read_write::ReadWrite;
+    use crate::traits::timer::Timer;
+
+    fn session(ssid: &str) -> Session {
+        Session {
+            ssid: String::from(ssid),
+            bssid: None,
+            channel: None,
+            started_at: Duration::ZERO,
+            connect_time: Duration::ZERO,
+            time_to_ip: Duration::ZERO,
+            duration: None,
+            disconnect_reason: None,
+            wifi_reason: None,
+            rssi: RssiStats::default(),
+        }
+    }
+
+    #[test]
+    fn keeps_a_bounded_session_history() {
+        let mut metrics = LinkMetrics::new();
+        for index in 0..SESSION_HISTORY + 2 {
+            metrics.session_started(session(&index.to_string()), Duration::from_secs(index as u64));
+        }
+        let ended = Duration::from_secs(SESSION_HISTORY as u64 + 4);
+        assert_eq!(metrics.current_session(ended).unwrap().duration, Some(Duration::from_secs(3)));
+        metrics.session_ended(DisconnectReason::LinkLost, Some(200), ended);
+        assert_eq!(metrics.sessions(), SESSION_HISTORY as u32 + 2);
+        assert_eq!(metrics.total_disconnects(), 1);
+        assert_eq!(metrics.history().count(), SESSION_HISTORY);
+        assert_eq!(metrics.history().next().unwrap().ssid, "2");
+        assert_eq!(metrics.disconnects(DisconnectReason::LinkLost), 1);
+        assert_eq!(metrics.disconnects(DisconnectReason::Replaced), SESSION_HISTORY as u32 + 1);
+        let last = metrics.history().last().unwrap();
+        assert_eq!((last.duration, last.wifi_reason), (Some(Duration::from_secs(3)), Some(200)));
+        assert_eq!(metrics.total_uptime(ended), ended);
+        assert!(metrics.current_session(ended).is_none());
+    }
+
+    #[test]
+    fn tracks_rssi_and_disconnects_while_supervising() {
+        let mut config = Config::new(Arc::new(Mutex::new(MockEspNvs::from([]))), "test_ssid", "test_psk");
+        config.read().unwrap();
+        let wifi = MockWifi::new().with_scan_results(vec![access_point("test_ssid", 6)]);
+        let mut timer = MockTimer::new();
+        let mut network = Network::new(Arc::new(Mutex::new(config)), wifi.clone(), MockMdns::new())
+            .with_timer(timer.clone())
+            .with_reconnect_policy(ReconnectPolicy {
+                jitter_percent: 0,
+                ..Default::default()
+            });
+        block_on(network.start()).unwrap();
+        for rssi in [-50, -70] {
+            wifi.state().rssi = rssi;
+            block_on(network.supervise_once(&mut timer)).unwrap();
+        }
+        let current = network.link_metrics().current_session(timer.now()).unwrap();
+        assert_eq!(current.duration, Some(Duration::from_secs(2)));
+        assert_eq!((current.channel, current.bssid), (Some(6), Some(access_point("test_ssid", 6).bssid)));
+        assert_eq!(current.rssi.samples, 3);
+        assert_eq!((current.rssi.min, current.rssi.max, current.rssi.mean()), (Some(-70), Some(-50), Some(-60)));
+
+        wifi.state().connected = false;
+        wifi.state().disconnect_reason = Some(200);
+        block_on(network.supervise_once(&mut timer)).unwrap();
+        let metrics = network.link_metrics();
+        assert_eq!((metrics.sessions(), metrics.reconnects()), (2, 1));
+        assert_eq!(metrics.disconnects(DisconnectReason::LinkLost), 1);
+        assert_eq!(metrics.last_rssi(), Some(-70));
+        let dropped = metrics.history().next().unwrap();
+        assert_eq!(dropped.rssi.samples, 3);
+        assert_eq!((dropped.duration, dropped.wifi_reason), (Some(Duration::from_secs(2)), Some(200)));
+        assert_eq!(metrics.total_uptime(timer.now()), Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,284 @@
+use std::time::Duration;
+
+use log::*;
+
+use crate::events::{EventChannel, Subscriber};
+use crate::name::get_name;
+use crate::network::Network;
+use crate::network::discovery::{merge_query_results, DiscoveredPeer};
+use crate::network::services::MDNS_QUERY_CAPACITY;
+use crate::traits::mdns::Mdns;
+use crate::traits::net_if::NetIf;
+use crate::traits::storage::Storage;
+use crate::traits::time_sync::TimeSync;
+use crate::traits::timer::Timer;
+use crate::traits::wifi::Wifi;
+
+pub const PEER_EVENTS_CAPACITY: usize = 8;
+
+#[derive(Debug, Clone)]
+pub struct PeerRegistryPolicy {
+    pub query_interval: Duration,
+    // peers missing from the answers for this long have left
+    pub expire_after: Duration,
+}
+
+impl Default for PeerRegistryPolicy {
+    fn default() -> Self {
+        PeerRegistryPolicy {
+            query_interval: Duration::from_secs(30),
+            expire_after: Duration::from_secs(95),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PeerEvent {
+    Joined(DiscoveredPeer),
+    Left { instance_name: String },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackedPeer {
+    // always has an instance name
+    pub peer: DiscoveredPeer,
+    // since boot, by the supervising timer
+    pub first_seen: Duration,
+    pub last_seen: Duration,
+}
+
+impl TrackedPeer {
+    pub fn instance_name(&self) -> &str {
+        self.peer.instance_name.as_deref().unwrap_or_default()
+    }
+}
+
+// The _burptech peers answering queries, by instance name
+pub struct PeerRegistry {
+    policy: PeerRegistryPolicy,
+    peers: Vec<TrackedPeer>,
+    last_query_at: Option<Duration>,
+    events: EventChannel<PeerEvent, PEER_EVENTS_CAPACITY>,
+}
+
+impl PeerRegistry {
+    pub fn new(policy: PeerRegistryPolicy) -> PeerRegistry {
+        PeerRegistry {
+            policy,
+            peers: Vec::new(),
+            last_query_at: None,
+            events: EventChannel::new(),
+        }
+    }
+
+    pub fn peers(&self) -> impl Iterator<Item = &TrackedPeer> {
+        self.peers.iter()
+    }
+
+    pub fn get(&self, instance_name: &str) -> Option<&TrackedPeer> {
+        self.peers.iter().find(|tracked| tracked.instance_name() == instance_name)
+    }
+
+    pub fn events(&self) -> EventChannel<PeerEvent, PEER_EVENTS_CAPACITY> {
+        self.events.clone()
+    }
+
+    pub fn subscribe(&self) -> Subscriber<PeerEvent, PEER_EVENTS_CAPACITY> {
+        self.events.subscribe()
+    }
+
+    pub fn is_due(&self, now: Duration) -> bool {
+        self.last_query_at.map_or(true, |at| now.saturating_sub(at) >= self.policy.query_interval)
+    }
+
+    // Takes in the answers to a query made at that time, then expires whoever has not answered for too long
+    pub fn update(&mut self, now: Duration, answers: Vec<DiscoveredPeer>) {
+        self.last_query_at = Some(now);
+        for peer in answers {
+            if peer.instance_name.is_none() {
+                debug!("Ignoring peer without an instance name at {:?}", peer.addresses);
+                continue;
+            }
+            match self.peers.iter_mut().find(|tracked| tracked.peer.instance_name == peer.instance_name) {
+                Some(tracked) => {
+                    tracked.peer = peer;
+                    tracked.last_seen = now;
+                }
+                None => {
+                    info!("Peer {:?} joined on port {}", peer.instance_name, peer.port);
+                    self.events.publish(PeerEvent::Joined(peer.clone()));
+                    self.peers.push(TrackedPeer {
+                        peer,
+                        first_seen: now,
+                        last_seen: now,
+                    });
+                }
+            }
+        }
+        let expire_after = self.policy.expire_after;
+        self.remove_where(|tracked| now.saturating_sub(tracked.last_seen) >= expire_after);
+    }
+
+    // Every peer leaves, as none can be seen while the network is stopped
+    pub fn clear(&mut self) {
+        self.last_query_at = None;
+        self.remove_where(|_| true);
+    }
+
+    fn remove_where(&mut self, leaving: impl Fn(&TrackedPeer) -> bool) {
+        let events = &self.events;
+        self.peers.retain(|tracked| {
+            if !leaving(tracked) {
+                return true;
+            }
+            info!("Peer {} left", tracked.instance_name());
+            events.publish(PeerEvent::Left { instance_name: String::from(tracked.instance_name()) });
+            false
+        });
+    }
+}
+
+impl<S: Storage, W: Wifi, M: Mdns, E: NetIf, N: TimeSync> Network<'_, S, W, M, E, N> {
+    pub fn with_peer_registry_policy(mut self, policy: PeerRegistryPolicy) -> Self {
+        self.peers = PeerRegistry::new(policy);
+        self
+    }
+
+    pub fn peer_registry(&self) -> &PeerRegistry {
+        &self.peers
+    }
+
+    pub(crate) fn query_peers_if_due<T: Timer>(&mut self, timer: &T) {
+        let now = timer.now();
+        if self.peers.is_due(now) {
+            self.query_peers(now);
+        }
+    }
+
+    pub(crate) fn query_peers(&mut self, now: Duration) {
+        match self.find_peers() {
+            Ok(answers) => self.peers.update(now, answers),
+            Err(error) => warn!("Peer query failed: {}", error),
+        }
+    }
+
+    // The device answers its own query, it is left out
+    fn find_peers(&mut self) -> Result<Vec<DiscoveredPeer>, M::Error> {
+        let policy = &self.services.policy;
+        let (service_type, proto) = {
+            let config = self.config.lock().unwrap();
+            let (service_type, proto) = config.mdns_query();
+            (service_type.to_string(), proto.to_string())
+        };
+        debug!("Query {}.{} services", service_type, proto);
+        let mut results: heapless::Vec<M::QueryResult, MDNS_QUERY_CAPACITY> = M::create_query_results();
+        let size = self.mdns.query_ptr(
+            &service_type,
+            &proto,
+            policy.query_timeout,
+            policy.query_max_results.min(MDNS_QUERY_CAPACITY),
+            &mut results,
+        )?;
+        let mut peers = merge_query_results(results[..size].iter().map(M::convert_query_result));
+        peers.retain(|peer| peer.instance_name.as_deref() != Some(get_name()));
+        Ok(peers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use embedded_svc::ipv4::{IpAddr, Ipv4Addr};
+
+    use crate::config::Config;
+    use crate::mocks::block_on::block_on;
+    use crate::mocks::mock_esp_nvs::MockEspNvs;
+    use crate::mocks::mock_mdns::{MockMdns, MockMdnsCall, MockQueryResult};
+    use crate::mocks::mock_timer::MockTimer;
+    use crate::mocks::mock_wifi::{access_point, MockWifi};
+    use crate::name::get_name;
+    use crate::network::Network;
+    use crate::network::discovery::DiscoveredPeer;
+    use crate::network::peers::{PeerEvent, PeerRegistry, PeerRegistryPolicy};
+    use crate::traits::read_write::ReadWrite;
+
+    fn peer(instance_name: &str, port: u16) -> DiscoveredPeer {
+        DiscoveredPeer {
+            instance_name: Some(String::from(instance_name)),
+            port,
+            ..Default::default()
+        }
+    }
+
+    fn query_result(instance_name: &str) -> MockQueryResult {
+        MockQueryResult {
+            instance_name: Some(String::from(instance_name)),
+            port: 1234,
+            addr: vec![IpAddr::V4(Ipv4Addr::new(192, 168, 1, 20))],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn tracks_peers_until_they_expire() {
+        let mut registry = PeerRegistry::new(PeerRegistryPolicy {
+            query_interval: Duration::from_secs(10),
+            expire_after: Duration::from_secs(25),
+        });
+        let mut subscriber = registry.subscribe();
+        assert!(registry.is_due(Duration::ZERO));
+        registry.update(Duration::ZERO, vec![peer("a", 1), peer("b", 2), DiscoveredPeer::default()]);
+        assert!(!registry.is_due(Duration::from_secs(9)));
+        registry.update(Duration::from_secs(10), vec![peer("a", 3)]);
+        assert_eq!(registry.get("a").unwrap().peer.port, 3);
+        assert_eq!(registry.get("a").unwrap().first_seen, Duration::ZERO);
+        registry.update(Duration::from_secs(20), vec![peer("a", 3)]);
+        assert!(registry.get("b").is_some());
+        registry.update(Duration::from_secs(30), vec![peer("a", 3)]);
+        assert_eq!(registry.peers().count(), 1);
+        registry.clear();
+        let events: Vec<PeerEvent> = std::iter::from_fn(|| subscriber.try_recv()).collect();
+        assert_eq!(events, [
+            PeerEvent::Joined(peer("a", 1)),
+            PeerEvent::Joined(peer("b", 2)),
+            PeerEvent::Left { instance_name: String::from("b") },
+            PeerEvent::Left { instance_name: String::from("a") },
+        ]);
+    }
+
+    #[test]
+    fn requeries_while_supervising() {
+        let mut config = Config::new(Arc::new(Mutex::new(MockEspNvs::from([]))), "test_ssid", "test_psk");
+        config.read().unwrap();
+        let mdns = MockMdns::new();
+        mdns.state().query_results = vec![query_result("a"), query_result(get_name()), query_result("b")];
+        let wifi = MockWifi::new().with_scan_results(vec![access_point("test_ssid", 6)]);
+        let mut timer = MockTimer::new();
+        let mut network = Network::new(Arc::new(Mutex::new(config)), wifi, mdns.clone())
+            .with_timer(timer.clone())
+            .with_peer_registry_policy(PeerRegistryPolicy {
+                query_interval: Duration::from_secs(5),
+                expire_after: Duration::from_secs(12),
+            });
+        let mut subscriber = network.peer_registry().subscribe();
+        block_on(network.start()).unwrap();
+        assert_eq!(network.discovered_peers().count(), 2);
+        assert!(network.peer_registry().get(get_name()).is_none());
+
+        mdns.state().query_results = vec![query_result("a")];
+        for _ in 0..16 {
+            block_on(network.supervise_once(&mut timer)).unwrap();
+        }
+        let queries = mdns.calls().iter().filter(|call| matches!(call, MockMdnsCall::QueryPtr { .. })).count();
+        assert_eq!(queries, 4);
+        assert_eq!(network.peer_registry().get("a").unwrap().last_seen, Duration::from_secs(15));
+        let events: Vec<PeerEvent> = std::iter::from_fn(|| subscriber.try_recv()).collect();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[2], PeerEvent::Left { instance_name: String::from("b") });
+
+        block_on(network.stop()).unwrap();
+        assert_eq!(subscriber.try_recv(), Some(PeerEvent::Left { instance_name: String::from("a") }));
+    }
+}
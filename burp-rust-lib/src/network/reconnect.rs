@@ -0,0 +1,259 @@
+use std::time::Duration;
+
+use log::*;
+
+use crate::network::backoff::Backoff;
+use crate::network::{Network, NetworkError};
+use crate::network::metrics::DisconnectReason;
+use crate::network::state::NetworkEvent;
+use crate::traits::mdns::Mdns;
+use crate::traits::net_if::NetIf;
+use crate::traits::storage::Storage;
+use crate::traits::time_sync::TimeSync;
+use crate::traits::timer::Timer;
+use crate::traits::wifi::Wifi;
+
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub jitter_percent: u8,
+    // consecutive failures after which the channel is found again with a fresh scan
+    pub rescan_after_failures: u32,
+    // how often the connection is checked while connected
+    pub poll_interval: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(300),
+            jitter_percent: 20,
+            rescan_after_failures: 3,
+            poll_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReconnectStats {
+    pub disconnects: u32,
+    pub attempts: u32,
+    pub failures: u32,
+    pub reconnects: u32,
+    pub consecutive_failures: u32,
+}
+
+pub(crate) struct Reconnect {
+    pub(crate) policy: ReconnectPolicy,
+    pub(crate) backoff: Backoff,
+    pub(crate) stats: ReconnectStats,
+    pub(crate) connected: bool,
+    // set when new credentials were provisioned so the next attempt finds their channel
+    pub(crate) rescan_pending: bool,
+}
+
+impl Reconnect {
+    pub(crate) fn new(policy: ReconnectPolicy, seed: u32) -> Reconnect {
+        Reconnect {
+            backoff: Backoff::new(policy.initial_backoff, policy.max_backoff, policy.jitter_percent, seed),
+            policy,
+            stats: ReconnectStats::default(),
+            connected: false,
+            rescan_pending: false,
+        }
+    }
+}
+
+impl<S: Storage, W: Wifi, M: Mdns, E: NetIf, N: TimeSync> Network<'_, S, W, M, E, N> {
+    pub fn reconnect_stats(&self) -> ReconnectStats {
+        self.reconnect.stats
+    }
+
+    // Only returns on errors that retrying cannot fix, such as invalid credentials in the config
+    pub async fn supervise<T: Timer>(&mut self, timer: &mut T) -> NetworkError<W::Error, M::Error, E::Error> {
+        loop {
+            if let Err(error) = self.supervise_once(timer).await {
+                return error;
+            }
+        }
+    }
+
+    pub async fn supervise_once<T: Timer>(&mut self, timer: &mut T) -> Result<(), NetworkError<W::Error, M::Error, E::Error>> {
+        if self.stopped {
+            self.wait(timer, self.reconnect.policy.poll_interval).await;
+            return Ok(());
+        }
+        self.check_ethernet(timer.after(self.links.policy.dhcp_timeout)).await;
+        if !self.links.policy.wifi {
+            if self.links.ethernet_up {
+                self.sync_time_if_due(timer).await;
+                self.query_peers_if_due(timer);
+            }
+            self.wait(timer, self.reconnect.policy.poll_interval).await;
+            return Ok(());
+        }
+        let connected = self.wifi.is_connected().unwrap_or_else(|error| {
+            warn!("Wifi connection state unavailable: {}", error);
+            false
+        });
+        if connected {
+            self.reconnect.connected = true;
+            self.sample_rssi();
+            self.refresh_ipv6();
+            self.sync_time_if_due(timer).await;
+            self.survey_if_due(timer).await;
+            self.query_peers_if_due(timer);
+            self.wait(timer, self.reconnect.policy.poll_interval).await;
+            return Ok(());
+        }
+        if self.reconnect.connected {
+            self.reconnect.connected = false;
+            self.reconnect.stats.disconnects += 1;
+            let wifi_reason = self.wifi.disconnect_reason();
+            self.link_metrics.session_ended(DisconnectReason::LinkLost, wifi_reason, self.uptime());
+            warn!("Wifi disconnected with reason {:?} ({} disconnects so far)", wifi_reason, self.reconnect.stats.disconnects);
+            self.publish(NetworkEvent::Disconnected { ssid: self.ssid.clone() });
+            self.update_default_route();
+        }
+
+        let (ssid, password) = self.get_credentials().map_err(NetworkError::Utf8Error)?;
+        if ssid.is_empty() {
+            if !self.soft_ap.active {
+                self.enter_soft_ap(false).map_err(NetworkError::WifiError)?;
+            }
+            self.wait_in_soft_ap(timer).await;
+            return Ok(());
+        }
+        self.reconnect.stats.attempts += 1;
+        info!(
+            "Wifi reconnect attempt {} ({} consecutive failures)",
+            self.reconnect.stats.attempts,
+            self.reconnect.stats.consecutive_failures,
+        );
+        let rescan = self.reconnect.rescan_pending || self.reconnect.stats.consecutive_failures > 0
+            && self.reconnect.stats.consecutive_failures % self.reconnect.policy.rescan_after_failures.max(1) == 0;
+        self.reconnect.rescan_pending = false;
+        let started = timer.now();
+        let result = if rescan {
+            info!("Rescanning after {} consecutive failures", self.reconnect.stats.consecutive_failures);
+            self.scan_and_connect_wifi(ssid, password).await
+        } else {
+            self.connect_wifi(ssid, password).await
+        };
+        match result {
+            Ok(()) => {
+                self.reconnect.connected = true;
+                self.reconnect.stats.reconnects += 1;
+                self.reconnect.stats.consecutive_failures = 0;
+                self.reconnect.backoff.reset();
+                self.update_default_route();
+                info!("Wifi reconnected in {:?}", timer.now().saturating_sub(started));
+            }
+            Err(error) => {
+                let error = self.fail(self.links.wifi_state.get().failure_reason(), error);
+                self.reconnect.stats.failures += 1;
+                self.reconnect.stats.consecutive_failures += 1;
+                if !self.soft_ap.active && self.reconnect.stats.consecutive_failures >= self.soft_ap.policy.enter_after_failures {
+                    warn!("Falling back to soft access point after {} consecutive failures", self.reconnect.stats.consecutive_failures);
+                    if let Err(error) = self.enter_soft_ap(true) {
+                        warn!("Soft access point unavailable: {}", error);
+                    }
+                }
+                if self.soft_ap.active {
+                    warn!("Wifi reconnect failed: {}, retrying from soft access point", error);
+                    self.wait_in_soft_ap(timer).await;
+                } else {
+                    let delay = self.reconnect.backoff.next_delay();
+                    warn!("Wifi reconnect failed: {}, retrying in {:?}", error, delay);
+                    self.wait(timer, delay).await;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use embedded_svc::wifi::Configuration;
+
+    use crate::config::Config;
+    use crate::mocks::block_on::block_on;
+    use crate::mocks::mock_esp_nvs::MockEspNvs;
+    use crate::mocks::mock_mdns::MockMdns;
+    use crate::mocks::mock_timer::MockTimer;
+    use crate::mocks::mock_wifi::{access_point, MockWifi, MockWifiCall};
+    use crate::network::Network;
+    use crate::network::reconnect::{ReconnectPolicy, ReconnectStats};
+    use crate::network::soft_ap::SoftApPolicy;
+    use crate::traits::read_write::ReadWrite;
+
+    fn network(wifi: &MockWifi) -> Network<'static, MockEspNvs, MockWifi, MockMdns> {
+        let mut config = Config::new(Arc::new(Mutex::new(MockEspNvs::from([]))), "test_ssid", "test_psk");
+        config.read().unwrap();
+        Network::new(Arc::new(Mutex::new(config)), wifi.clone(), MockMdns::new())
+            .with_reconnect_policy(ReconnectPolicy {
+                initial_backoff: Duration::from_secs(1),
+                max_backoff: Duration::from_secs(4),
+                jitter_percent: 0,
+                rescan_after_failures: 2,
+                poll_interval: Duration::from_secs(10),
+            })
+            .with_soft_ap_policy(SoftApPolicy {
+                enter_after_failures: u32::MAX,
+                ..Default::default()
+            })
+    }
+
+    #[test]
+    fn polls_while_connected() {
+        let wifi = MockWifi::new();
+        let mut network = network(&wifi);
+        block_on(network.start()).unwrap();
+        let mut timer = MockTimer::new();
+        block_on(network.supervise_once(&mut timer)).unwrap();
+        assert_eq!(timer.sleeps(), [Duration::from_secs(10)]);
+        assert_eq!(network.reconnect_stats(), ReconnectStats::default());
+    }
+
+    #[test]
+    fn retries_with_capped_backoff_and_rescans() {
+        let wifi = MockWifi::new().with_scan_results(vec![access_point("test_ssid", 6)]);
+        let mut network = network(&wifi);
+        block_on(network.start()).unwrap();
+        wifi.state().connected = false;
+        wifi.state().fail_connects = 5;
+        wifi.state().scan_results = vec![access_point("test_ssid", 11)];
+        wifi.state().calls.clear();
+        let mut timer = MockTimer::new();
+        for _ in 0..6 {
+            block_on(network.supervise_once(&mut timer)).unwrap();
+        }
+        assert_eq!(timer.sleeps(), [1, 2, 4, 4, 4].map(Duration::from_secs));
+        assert_eq!(network.reconnect_stats(), ReconnectStats {
+            disconnects: 1,
+            attempts: 6,
+            failures: 5,
+            reconnects: 1,
+            consecutive_failures: 0,
+        });
+        assert_eq!(wifi.calls().iter().filter(|call| **call == MockWifiCall::Scan).count(), 2);
+        match wifi.configurations().last().unwrap() {
+            Configuration::Client(client) => assert_eq!(client.channel, Some(11)),
+            configuration => panic!("Unexpected configuration: {:?}", configuration),
+        }
+
+        block_on(network.supervise_once(&mut timer)).unwrap();
+        assert_eq!(timer.sleeps().last(), Some(&Duration::from_secs(10)));
+        wifi.state().connected = false;
+        wifi.state().fail_connects = 1;
+        block_on(network.supervise_once(&mut timer)).unwrap();
+        assert_eq!(timer.sleeps().last(), Some(&Duration::from_secs(1)));
+        assert_eq!(network.reconnect_stats().disconnects, 2);
+    }
+}
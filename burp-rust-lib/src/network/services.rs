@@ -0,0 +1,295 @@
+use std::error::Error;
+use std::time::Duration;
+
+use log::*;
+use thiserror::Error;
+
+use crate::config::MdnsConfigError;
+use crate::name::get_name;
+use crate::network::Network;
+use crate::network::txt::BuildInfo;
+use crate::traits::async_read_write::AsyncReadWrite;
+use crate::traits::mdns::Mdns;
+use crate::traits::net_if::NetIf;
+use crate::traits::storage::Storage;
+use crate::traits::time_sync::TimeSync;
+use crate::traits::wifi::Wifi;
+
+// capacity of the buffer query results are read into
+pub const MDNS_QUERY_CAPACITY: usize = 32;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MdnsService {
+    // eg. _http
+    pub service_type: String,
+    // _tcp or _udp
+    pub proto: String,
+    pub port: u16,
+    pub txt: Vec<(String, String)>,
+}
+
+impl MdnsService {
+    pub fn new(service_type: &str, proto: &str, port: u16) -> MdnsService {
+        MdnsService {
+            service_type: String::from(service_type),
+            proto: String::from(proto),
+            port,
+            txt: Vec::new(),
+        }
+    }
+
+    pub fn with_txt(mut self, key: &str, value: &str) -> Self {
+        self.txt.retain(|(existing, _)| existing != key);
+        self.txt.push((String::from(key), String::from(value)));
+        self
+    }
+
+    pub(crate) fn is(&self, service_type: &str, proto: &str) -> bool {
+        self.service_type == service_type && self.proto == proto
+    }
+
+    // As stored in the config, eg. _http._tcp:80;path=/;v=2. None when a name or value would not parse back.
+    pub(crate) fn to_record(&self) -> Option<String> {
+        if !is_storable_name(&self.service_type) || !is_storable_name(&self.proto) {
+            return None;
+        }
+        let mut record = format!("{}.{}:{}", self.service_type, self.proto, self.port);
+        for (key, value) in &self.txt {
+            if !is_storable_name(key) || value.contains([';', '\n', '\r']) {
+                return None;
+            }
+            record.push_str(&format!(";{}={}", key, value));
+        }
+        Some(record)
+    }
+
+    pub(crate) fn from_record(record: &str) -> Option<MdnsService> {
+        let mut fields = record.split(';');
+        let (name, port) = fields.next()?.split_once(':')?;
+        let (service_type, proto) = name.split_once('.')?;
+        let mut service = MdnsService::new(service_type, proto, port.parse().ok()?);
+        for field in fields {
+            let (key, value) = field.split_once('=')?;
+            service = service.with_txt(key, value);
+        }
+        Some(service)
+    }
+}
+
+pub(crate) fn is_storable_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains(['.', ':', ';', '=', '\n', '\r'])
+}
+
+#[derive(Error, Debug)]
+pub enum ServiceError<M: Error> {
+    #[error("{0}")]
+    Config(#[from] MdnsConfigError),
+    #[error("{0}")]
+    Mdns(M),
+}
+
+// The services and the query are in the config
+#[derive(Debug, Clone)]
+pub struct MdnsPolicy {
+    // no more than MDNS_QUERY_CAPACITY
+    pub query_max_results: usize,
+    pub query_timeout: Duration,
+}
+
+impl Default for MdnsPolicy {
+    fn default() -> Self {
+        MdnsPolicy {
+            query_max_results: 20,
+            // mdns queries block, peers on the local network answer well within this
+            query_timeout: Duration::from_millis(250),
+        }
+    }
+}
+
+pub(crate) struct MdnsServices {
+    pub(crate) policy: MdnsPolicy,
+    pub(crate) build: BuildInfo,
+    // true between starting mdns and stopping
+    pub(crate) advertising: bool,
+}
+
+impl MdnsServices {
+    pub(crate) fn new(policy: MdnsPolicy) -> MdnsServices {
+        MdnsServices {
+            policy,
+            build: BuildInfo::default(),
+            advertising: false,
+        }
+    }
+}
+
+impl<S: Storage, W: Wifi, M: Mdns, E: NetIf, N: TimeSync> Network<'_, S, W, M, E, N> {
+    pub fn with_mdns_policy(mut self, policy: MdnsPolicy) -> Self {
+        self.services.policy = policy;
+        self
+    }
+
+    pub fn services(&self) -> Vec<MdnsService> {
+        self.config.lock().unwrap().mdns_services()
+    }
+
+    // Replaces any service of the same type and saves the list, advertised straight away once mdns has started
+    pub async fn add_service(&mut self, service: MdnsService) -> Result<(), ServiceError<M::Error>> {
+        let mut services = self.services();
+        let replaced = take_service(&mut services, &service.service_type, &service.proto).is_some();
+        services.push(service.clone());
+        self.save_services(&services).await?;
+        if !self.services.advertising {
+            return Ok(());
+        }
+        let service = self.with_device_txt(&service);
+        if replaced {
+            self.mdns.remove_service(&service.service_type, &service.proto).map_err(ServiceError::Mdns)?;
+        }
+        advertise(&mut self.mdns, &service).map_err(ServiceError::Mdns)
+    }
+
+    pub async fn remove_service(&mut self, service_type: &str, proto: &str) -> Result<Option<MdnsService>, ServiceError<M::Error>> {
+        let mut services = self.services();
+        let removed = take_service(&mut services, service_type, proto);
+        if removed.is_none() {
+            return Ok(None);
+        }
+        self.save_services(&services).await?;
+        if self.services.advertising {
+            info!("Removing {}.{} service", service_type, proto);
+            self.mdns.remove_service(service_type, proto).map_err(ServiceError::Mdns)?;
+        }
+        Ok(removed)
+    }
+
+    pub(crate) fn advertise_services(&mut self) -> Result<(), M::Error> {
+        let services: Vec<MdnsService> = self.services().iter()
+            .map(|service| self.with_device_txt(service))
+            .collect();
+        for service in &services {
+            advertise(&mut self.mdns, service)?;
+        }
+        self.services.advertising = true;
+        Ok(())
+    }
+
+    // Kept in memory when writing fails, like the rest of the config
+    async fn save_services(&mut self, services: &[MdnsService]) -> Result<(), MdnsConfigError> {
+        let mut mdns_services = {
+            let mut config = self.config.lock().unwrap();
+            config.set_mdns_services(services)?;
+            config.mdns_services.clone()
+        };
+        if let Err(error) = AsyncReadWrite::write(&mut mdns_services).await {
+            warn!("Could not save mDNS services: {}", error);
+        }
+        Ok(())
+    }
+}
+
+fn take_service(services: &mut Vec<MdnsService>, service_type: &str, proto: &str) -> Option<MdnsService> {
+    let index = services.iter().position(|service| service.is(service_type, proto))?;
+    Some(services.remove(index))
+}
+
+fn advertise<M: Mdns>(mdns: &mut M, service: &MdnsService) -> Result<(), M::Error> {
+    info!("Adding {}.{} service on port {}", service.service_type, service.proto, service.port);
+    let txt: Vec<(&str, &str)> = service.txt.iter().map(|(key, value)| (key.as_str(), value.as_str())).collect();
+    mdns.add_service(Some(get_name()), &service.service_type, &service.proto, service.port, &txt)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use crate::config::Config;
+    use crate::mocks::block_on::block_on;
+    use crate::mocks::mock_esp_nvs::MockEspNvs;
+    use crate::mocks::mock_mdns::{MockMdns, MockMdnsCall};
+    use crate::mocks::mock_wifi::MockWifi;
+    use crate::name::get_name;
+    use crate::network::Network;
+    use crate::network::services::{MdnsPolicy, MdnsService, ServiceError};
+    use crate::traits::read_write::ReadWrite;
+
+    fn service_calls(mdns: &MockMdns) -> Vec<MockMdnsCall> {
+        mdns.calls().into_iter()
+            .filter(|call| matches!(call, MockMdnsCall::AddService { .. } | MockMdnsCall::RemoveService { .. }))
+            .collect()
+    }
+
+    fn added(service_type: &str, port: u16, txt: &[(&str, &str)]) -> MockMdnsCall {
+        MockMdnsCall::AddService {
+            instance_name: Some(String::from(get_name())),
+            service_type: String::from(service_type),
+            proto: String::from("_tcp"),
+            port,
+            txt: txt.iter().map(|(key, value)| (String::from(*key), String::from(*value))).collect(),
+        }
+    }
+
+    #[test]
+    fn advertises_configured_services_and_queries() {
+        let mut config = Config::new(Arc::new(Mutex::new(MockEspNvs::from([]))), "test_ssid", "test_psk");
+        config.read().unwrap();
+        config.set_mdns_services(&[
+            MdnsService::new("_http", "_tcp", 80).with_txt("path", "/"),
+            MdnsService::new("_peer", "_tcp", 4000),
+        ]).unwrap();
+        config.set_mdns_query("_peer", "_tcp").unwrap();
+        let mdns = MockMdns::new();
+        let mut network = Network::new(Arc::new(Mutex::new(config)), MockWifi::new(), mdns.clone())
+            .with_mdns_policy(MdnsPolicy {
+                query_max_results: 5,
+                query_timeout: Duration::from_secs(1),
+            });
+        block_on(network.add_service(MdnsService::new("_metrics", "_tcp", 9100))).unwrap();
+        assert!(service_calls(&mdns).is_empty());
+
+        block_on(network.start()).unwrap();
+        assert_eq!(service_calls(&mdns), [
+            added("_http", 80, &[("path", "/")]),
+            added("_peer", 4000, &[]),
+            added("_metrics", 9100, &[]),
+        ]);
+        assert!(mdns.calls().contains(&MockMdnsCall::QueryPtr {
+            service_type: String::from("_peer"),
+            proto: String::from("_tcp"),
+            timeout: Duration::from_secs(1),
+            max_results: 5,
+        }));
+    }
+
+    #[test]
+    fn adds_and_removes_services_at_runtime() {
+        let storage = Arc::new(Mutex::new(MockEspNvs::from([])));
+        let mut config = Config::new(storage.clone(), "test_ssid", "test_psk");
+        config.read().unwrap();
+        let mdns = MockMdns::new();
+        let mut network = Network::new(Arc::new(Mutex::new(config)), MockWifi::new(), mdns.clone());
+        block_on(network.start()).unwrap();
+        block_on(network.add_service(MdnsService::new("_metrics", "_tcp", 9100))).unwrap();
+        block_on(network.add_service(MdnsService::new("_metrics", "_tcp", 9101).with_txt("v", "2"))).unwrap();
+        let removed = block_on(network.remove_service("_burptech", "_tcp")).unwrap();
+        assert_eq!(removed.map(|service| service.port), Some(1234));
+        assert!(block_on(network.remove_service("_burptech", "_tcp")).unwrap().is_none());
+        let unstorable = MdnsService::new("_http", "_tcp", 80).with_txt("path", "a;b");
+        assert!(matches!(block_on(network.add_service(unstorable)), Err(ServiceError::Config(_))));
+        let remove = |service_type: &str| MockMdnsCall::RemoveService {
+            service_type: String::from(service_type),
+            proto: String::from("_tcp"),
+        };
+        assert_eq!(service_calls(&mdns)[1..], [
+            added("_metrics", 9100, &[]),
+            remove("_metrics"),
+            added("_metrics", 9101, &[("v", "2")]),
+            remove("_burptech"),
+        ]);
+        assert_eq!(network.services(), [MdnsService::new("_metrics", "_tcp", 9101).with_txt("v", "2")]);
+        let mut rebooted = Config::new(storage, "test_ssid", "test_psk");
+        rebooted.read().unwrap();
+        assert_eq!(rebooted.mdns_services(), network.services());
+    }
+}
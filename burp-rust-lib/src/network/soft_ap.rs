@@ -0,0 +1,245 @@
+use std::time::Duration;
+
+use embedded_svc::wifi::{AccessPointConfiguration, AuthMethod, ClientConfiguration, Configuration};
+use heapless::String;
+use log::*;
+
+use crate::name::get_name;
+use crate::network::Network;
+use crate::network::state::{FailureReason, NetworkEvent};
+use crate::provisioning::Credentials;
+use crate::provisioning::wifi_uri::WifiUri;
+use crate::traits::mdns::Mdns;
+use crate::traits::net_if::NetIf;
+use crate::traits::storage::Storage;
+use crate::traits::time_sync::TimeSync;
+use crate::traits::wifi::Wifi;
+
+#[derive(Debug, Clone)]
+pub struct SoftApPolicy {
+    // consecutive station failures after which the access point is brought up
+    pub enter_after_failures: u32,
+    // how often station mode is retried while the access point is up
+    pub station_retry_interval: Duration,
+    pub channel: u8,
+    pub max_connections: u16,
+    // WPA2, 8 to 63 characters. Without one the access point is open, which is only allowed before
+    // there are credentials, otherwise anyone who keeps the device offline could replace them.
+    pub passphrase: Option<String<64>>,
+}
+
+impl Default for SoftApPolicy {
+    fn default() -> Self {
+        SoftApPolicy {
+            enter_after_failures: 5,
+            station_retry_interval: Duration::from_secs(60),
+            channel: 1,
+            max_connections: 4,
+            passphrase: None,
+        }
+    }
+}
+
+pub(crate) struct SoftAp {
+    pub(crate) policy: SoftApPolicy,
+    pub(crate) active: bool,
+}
+
+impl SoftAp {
+    pub(crate) fn new(policy: SoftApPolicy) -> SoftAp {
+        SoftAp {
+            policy,
+            active: false,
+        }
+    }
+}
+
+impl<S: Storage, W: Wifi, M: Mdns, E: NetIf, N: TimeSync> Network<'_, S, W, M, E, N> {
+    pub fn with_soft_ap_policy(mut self, policy: SoftApPolicy) -> Self {
+        self.soft_ap = SoftAp::new(policy);
+        self
+    }
+
+    pub fn soft_ap_active(&self) -> bool {
+        self.soft_ap.active
+    }
+
+    // Runs the access point alongside the station when there are credentials to keep retrying with
+    pub(crate) fn enter_soft_ap(&mut self, station: bool) -> Result<(), W::Error> {
+        if station && self.soft_ap.policy.passphrase.is_none() {
+            warn!("Not opening an unprotected access point while credentials are stored");
+            return Ok(());
+        }
+        let access_point = self.access_point_configuration();
+        info!("Starting soft access point {} on channel {}", access_point.ssid, access_point.channel);
+        let ssid = access_point.ssid.clone();
+        // the portal needs the station interface to scan
+        let configuration = if station || self.portal.is_some() {
+            Configuration::Mixed(ClientConfiguration::default(), access_point)
+        } else {
+            Configuration::AccessPoint(access_point)
+        };
+        self.wifi.set_configuration(&configuration)
+            .map_err(|error| self.fail(FailureReason::SoftAp, error))?;
+        self.soft_ap.active = true;
+        self.publish(NetworkEvent::SoftAp { ssid });
+        Ok(())
+    }
+
+    // For showing as a QR code so phones can join the soft access point
+    pub fn soft_ap_wifi_uri(&self) -> WifiUri {
+        let access_point = self.access_point_configuration();
+        WifiUri {
+            credentials: Credentials {
+                ssid: access_point.ssid,
+                psk: access_point.password,
+            },
+            auth_method: Some(access_point.auth_method),
+            hidden: false,
+        }
+    }
+
+    pub(crate) fn leave_soft_ap(&mut self, client: ClientConfiguration) -> Result<(), W::Error> {
+        info!("Stopping soft access point");
+        self.wifi.set_configuration(&Configuration::Client(client))?;
+        self.soft_ap.active = false;
+        if let Some(portal) = &mut self.portal {
+            portal.close();
+        }
+        Ok(())
+    }
+
+    pub(crate) fn wifi_configuration(&self, client: ClientConfiguration) -> Configuration {
+        if self.soft_ap.active {
+            Configuration::Mixed(client, self.access_point_configuration())
+        } else {
+            Configuration::Client(client)
+        }
+    }
+
+    fn access_point_configuration(&self) -> AccessPointConfiguration {
+        let name = get_name();
+        let mut end = name.len().min(32);
+        while !name.is_char_boundary(end) {
+            end -= 1;
+        }
+        let (auth_method, password) = match &self.soft_ap.policy.passphrase {
+            Some(passphrase) => (AuthMethod::WPA2Personal, passphrase.clone()),
+            None => (AuthMethod::None, String::new()),
+        };
+        AccessPointConfiguration {
+            ssid: String::from(&name[..end]),
+            channel: self.soft_ap.policy.channel,
+            auth_method,
+            password,
+            max_connections: self.soft_ap.policy.max_connections,
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use embedded_svc::wifi::{AuthMethod, Configuration};
+
+    use crate::config::Config;
+    use crate::mocks::block_on::block_on;
+    use crate::mocks::mock_esp_nvs::MockEspNvs;
+    use crate::mocks::mock_mdns::MockMdns;
+    use crate::mocks::mock_timer::MockTimer;
+    use crate::mocks::mock_wifi::{MockWifi, MockWifiCall};
+    use crate::network::Network;
+    use crate::network::reconnect::ReconnectPolicy;
+    use crate::network::soft_ap::SoftApPolicy;
+    use crate::network::state::NetworkState;
+    use crate::traits::read_write::ReadWrite;
+
+    fn network(wifi: &MockWifi, ssid: &'static str) -> Network<'static, MockEspNvs, MockWifi, MockMdns> {
+        let mut config = Config::new(Arc::new(Mutex::new(MockEspNvs::from([]))), ssid, "test_psk");
+        config.read().unwrap();
+        Network::new(Arc::new(Mutex::new(config)), wifi.clone(), MockMdns::new())
+            .with_reconnect_policy(ReconnectPolicy {
+                initial_backoff: Duration::from_secs(1),
+                max_backoff: Duration::from_secs(4),
+                jitter_percent: 0,
+                rescan_after_failures: 100,
+                poll_interval: Duration::from_secs(10),
+            })
+            .with_soft_ap_policy(SoftApPolicy {
+                enter_after_failures: 2,
+                station_retry_interval: Duration::from_secs(30),
+                passphrase: Some(heapless::String::from("soft_ap_pass")),
+                ..Default::default()
+            })
+    }
+
+    #[test]
+    fn starts_access_point_without_credentials() {
+        let wifi = MockWifi::new();
+        let mut network = network(&wifi, "");
+        block_on(network.start()).unwrap();
+        assert!(network.soft_ap_active());
+        assert_eq!(network.state(), NetworkState::SoftAp);
+        assert!(matches!(wifi.configurations().last(), Some(Configuration::AccessPoint(_))));
+        assert!(!wifi.calls().contains(&MockWifiCall::Connect));
+        assert!(network.soft_ap_wifi_uri().to_string().starts_with("WIFI:T:WPA;S:"));
+
+        let mut timer = MockTimer::new();
+        block_on(network.supervise_once(&mut timer)).unwrap();
+        assert_eq!(timer.sleeps(), [Duration::from_secs(30)]);
+        assert!(!wifi.calls().contains(&MockWifiCall::Connect));
+    }
+
+    #[test]
+    fn falls_back_to_access_point_and_retries_station() {
+        let wifi = MockWifi::new();
+        wifi.state().fail_connects = 4;
+        let mut network = network(&wifi, "test_ssid");
+        assert!(block_on(network.start()).is_err());
+        let mut timer = MockTimer::new();
+        for _ in 0..3 {
+            block_on(network.supervise_once(&mut timer)).unwrap();
+        }
+        assert!(network.soft_ap_active());
+        assert_eq!(timer.sleeps(), [1, 30, 30].map(Duration::from_secs));
+        match wifi.configurations().last().unwrap() {
+            Configuration::Mixed(client, access_point) => {
+                assert_eq!(client.ssid, "test_ssid");
+                assert_eq!(access_point.channel, 1);
+                assert_eq!((access_point.auth_method, access_point.password.as_str()), (AuthMethod::WPA2Personal, "soft_ap_pass"));
+            }
+            configuration => panic!("Unexpected configuration: {:?}", configuration),
+        }
+
+        block_on(network.supervise_once(&mut timer)).unwrap();
+        assert!(!network.soft_ap_active());
+        assert_eq!(network.state(), NetworkState::Online);
+        assert!(matches!(wifi.configurations().last(), Some(Configuration::Client(_))));
+    }
+
+    #[test]
+    fn opens_an_unprotected_access_point_only_without_credentials() {
+        let wifi = MockWifi::new();
+        let mut provisioning = network(&wifi, "").with_soft_ap_policy(SoftApPolicy::default());
+        block_on(provisioning.start()).unwrap();
+        assert!(provisioning.soft_ap_active());
+        assert!(provisioning.soft_ap_wifi_uri().to_string().starts_with("WIFI:T:nopass;S:"));
+
+        let wifi = MockWifi::new();
+        wifi.state().fail_connects = 10;
+        let mut station = network(&wifi, "test_ssid").with_soft_ap_policy(SoftApPolicy {
+            enter_after_failures: 2,
+            ..Default::default()
+        });
+        assert!(block_on(station.start()).is_err());
+        let mut timer = MockTimer::new();
+        for _ in 0..4 {
+            block_on(station.supervise_once(&mut timer)).unwrap();
+        }
+        assert!(!station.soft_ap_active());
+        assert!(wifi.configurations().iter().all(|configuration| matches!(configuration, Configuration::Client(_))));
+    }
+}
@@ -0,0 +1,116 @@
+use std::fmt::Display;
+
+use embedded_svc::ipv4::IpInfo;
+use heapless::String;
+
+const FAILURE_MESSAGE_MAX_BYTES: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetworkState {
+    Idle,
+    Scanning,
+    Connecting,
+    ObtainingIp,
+    Online,
+    Disconnected,
+    SoftAp,
+    Stopped,
+    Failed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureReason {
+    InvalidConfig,
+    Start,
+    Scan,
+    Connect,
+    ObtainIp,
+    Mdns,
+    SoftAp,
+    Stop,
+    Ethernet,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NetworkEvent {
+    Scanning {
+        ssid: String<32>,
+    },
+    Connecting {
+        ssid: String<32>,
+        channel: Option<u8>,
+    },
+    ObtainingIp {
+        ssid: String<32>,
+        channel: Option<u8>,
+    },
+    Online {
+        ssid: String<32>,
+        channel: Option<u8>,
+        ip_info: IpInfo,
+    },
+    Disconnected {
+        ssid: String<32>,
+    },
+    // ssid is the soft access point's own
+    SoftAp {
+        ssid: String<32>,
+    },
+    Stopped {
+        ssid: String<32>,
+    },
+    EthernetUp {
+        ip_info: IpInfo,
+    },
+    EthernetDown,
+    Failed {
+        ssid: String<32>,
+        reason: FailureReason,
+        // truncated description of the underlying error
+        message: String<FAILURE_MESSAGE_MAX_BYTES>,
+    },
+}
+
+impl NetworkEvent {
+    pub fn state(&self) -> NetworkState {
+        match self {
+            NetworkEvent::Scanning { .. } => NetworkState::Scanning,
+            NetworkEvent::Connecting { .. } => NetworkState::Connecting,
+            NetworkEvent::ObtainingIp { .. } => NetworkState::ObtainingIp,
+            NetworkEvent::Online { .. } => NetworkState::Online,
+            NetworkEvent::Disconnected { .. } => NetworkState::Disconnected,
+            NetworkEvent::SoftAp { .. } => NetworkState::SoftAp,
+            NetworkEvent::Stopped { .. } => NetworkState::Stopped,
+            NetworkEvent::EthernetUp { .. } => NetworkState::Online,
+            NetworkEvent::EthernetDown => NetworkState::Disconnected,
+            NetworkEvent::Failed { .. } => NetworkState::Failed,
+        }
+    }
+
+    pub fn failed(ssid: String<32>, reason: FailureReason, error: &impl Display) -> NetworkEvent {
+        let description = error.to_string();
+        let mut end = description.len().min(FAILURE_MESSAGE_MAX_BYTES);
+        while !description.is_char_boundary(end) {
+            end -= 1;
+        }
+        let mut message = String::new();
+        message.push_str(&description[..end]).ok();
+        NetworkEvent::Failed {
+            ssid,
+            reason,
+            message,
+        }
+    }
+}
+
+impl NetworkState {
+    // the reason a wifi error is reported with depends on what was in progress at the time
+    pub fn failure_reason(&self) -> FailureReason {
+        match self {
+            NetworkState::Scanning => FailureReason::Scan,
+            NetworkState::Connecting => FailureReason::Connect,
+            NetworkState::ObtainingIp => FailureReason::ObtainIp,
+            _ => FailureReason::Start,
+        }
+    }
+}
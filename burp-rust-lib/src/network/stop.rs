@@ -0,0 +1,97 @@
+use log::*;
+
+use crate::network::{Network, NetworkError};
+use crate::network::metrics::DisconnectReason;
+use crate::network::state::{FailureReason, NetworkEvent};
+use crate::traits::mdns::Mdns;
+use crate::traits::net_if::NetIf;
+use crate::traits::storage::Storage;
+use crate::traits::time_sync::TimeSync;
+use crate::traits::wifi::Wifi;
+
+impl<S: Storage, W: Wifi, M: Mdns, E: NetIf, N: TimeSync> Network<'_, S, W, M, E, N> {
+    // Services are removed while still connected so that goodbyes reach the network.
+    // Supervising does nothing until started again.
+    pub async fn stop(&mut self) -> Result<(), NetworkError<W::Error, M::Error, E::Error>> {
+        info!("Stopping network");
+        self.stopped = true;
+        self.reconnect.connected = false;
+        self.link_metrics.session_ended(DisconnectReason::Stopped, None, self.uptime());
+        let mdns_result = self.mdns.remove_services();
+        self.services.advertising = false;
+        self.peers.clear();
+        if let Some(portal) = &mut self.portal {
+            portal.close();
+        }
+        if let Err(error) = &mdns_result {
+            warn!("Removing MDNS services failed: {}", error);
+        }
+        if self.links.policy.wifi {
+            if self.wifi.is_connected().unwrap_or(false) {
+                if let Err(error) = self.wifi.disconnect().await {
+                    warn!("Wifi disconnect failed: {}", error);
+                }
+            }
+            self.wifi.stop().await.map_err(|error| NetworkError::WifiError(self.fail(FailureReason::Stop, error)))?;
+            self.soft_ap.active = false;
+        }
+        self.stop_ethernet().await.map_err(|error| NetworkError::EthernetError(self.fail(FailureReason::Stop, error)))?;
+        mdns_result.map_err(|error| NetworkError::MdnsError(self.fail(FailureReason::Stop, error)))?;
+        self.publish(NetworkEvent::Stopped { ssid: self.ssid.clone() });
+        Ok(())
+    }
+
+    // Picks up any credentials changed in the config since starting
+    pub async fn restart(&mut self) -> Result<(), NetworkError<W::Error, M::Error, E::Error>> {
+        self.stop().await?;
+        self.start().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use embedded_svc::wifi::Configuration;
+
+    use crate::config::Config;
+    use crate::mocks::block_on::block_on;
+    use crate::mocks::mock_esp_nvs::MockEspNvs;
+    use crate::mocks::mock_mdns::{MockMdns, MockMdnsCall};
+    use crate::mocks::mock_timer::MockTimer;
+    use crate::mocks::mock_wifi::{MockWifi, MockWifiCall};
+    use crate::network::Network;
+    use crate::network::state::NetworkState;
+    use crate::traits::read_write::ReadWrite;
+
+    #[test]
+    fn stops_cleanly_and_restarts_with_new_credentials() {
+        let mut config = Config::new(Arc::new(Mutex::new(MockEspNvs::from([]))), "test_ssid", "test_psk");
+        config.read().unwrap();
+        let config = Arc::new(Mutex::new(config));
+        let wifi = MockWifi::new();
+        let mdns = MockMdns::new();
+        let mut network = Network::new(config.clone(), wifi.clone(), mdns.clone());
+        block_on(network.start()).unwrap();
+
+        wifi.state().calls.clear();
+        block_on(network.stop()).unwrap();
+        assert_eq!(network.state(), NetworkState::Stopped);
+        assert_eq!(wifi.calls(), [MockWifiCall::Disconnect, MockWifiCall::Stop]);
+        assert_eq!(mdns.calls().last(), Some(&MockMdnsCall::RemoveServices));
+        let mut timer = MockTimer::new();
+        block_on(network.supervise_once(&mut timer)).unwrap();
+        assert!(!wifi.calls().contains(&MockWifiCall::Connect));
+
+        config.lock().unwrap().ssid.set("new_ssid".as_bytes());
+        block_on(network.restart()).unwrap();
+        assert_eq!(network.state(), NetworkState::Online);
+        match wifi.configurations().last().unwrap() {
+            Configuration::Client(client) => assert_eq!(client.ssid, "new_ssid"),
+            configuration => panic!("Unexpected configuration: {:?}", configuration),
+        }
+        block_on(network.supervise_once(&mut timer)).unwrap();
+        assert_eq!(timer.sleeps(), [Duration::from_secs(1), Duration::from_secs(1)]);
+    }
+}
@@ -0,0 +1,219 @@
+use std::cmp::Reverse;
+use std::time::Duration;
+
+use embedded_svc::wifi::{AccessPointInfo, AuthMethod};
+use heapless::{String, Vec};
+use log::*;
+
+use crate::network::Network;
+use crate::traits::mdns::Mdns;
+use crate::traits::net_if::NetIf;
+use crate::traits::storage::Storage;
+use crate::traits::time_sync::TimeSync;
+use crate::traits::timer::Timer;
+use crate::traits::wifi::Wifi;
+
+pub const SURVEY_MAX_RESULTS: usize = 32;
+pub const SURVEY_CHANNELS: usize = 14;
+// 20MHz channels in 2.4GHz overlap with this many neighbours either side
+const OVERLAPPING_CHANNELS: u8 = 4;
+const NON_OVERLAPPING_CHANNELS: [u8; 3] = [1, 6, 11];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SurveyEntry {
+    pub ssid: String<32>,
+    pub bssid: [u8; 6],
+    pub rssi: i8,
+    pub channel: u8,
+    pub auth_method: AuthMethod,
+    // the configured ssid
+    pub known: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChannelCongestion {
+    pub channel: u8,
+    pub access_points: u8,
+    // access points on this channel or close enough to overlap it
+    pub overlapping: u8,
+    pub strongest_rssi: Option<i8>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Survey {
+    // strongest first, one per bssid
+    pub entries: Vec<SurveyEntry, SURVEY_MAX_RESULTS>,
+}
+
+impl Survey {
+    pub fn new(access_points: &[AccessPointInfo], known_ssid: &str) -> Survey {
+        let mut sorted: std::vec::Vec<&AccessPointInfo> = access_points.iter().collect();
+        sorted.sort_by_key(|access_point| Reverse(access_point.signal_strength));
+        let mut entries: Vec<SurveyEntry, SURVEY_MAX_RESULTS> = Vec::new();
+        for access_point in sorted {
+            if entries.iter().any(|entry| entry.bssid == access_point.bssid) {
+                continue;
+            }
+            let entry = SurveyEntry {
+                ssid: access_point.ssid.clone(),
+                bssid: access_point.bssid,
+                rssi: access_point.signal_strength,
+                channel: access_point.channel,
+                auth_method: access_point.auth_method,
+                known: !known_ssid.is_empty() && access_point.ssid == known_ssid,
+            };
+            if entries.push(entry).is_err() {
+                break;
+            }
+        }
+        Survey { entries }
+    }
+
+    pub fn channel_congestion(&self) -> [ChannelCongestion; SURVEY_CHANNELS] {
+        let mut congestion = [ChannelCongestion::default(); SURVEY_CHANNELS];
+        for (index, channel) in congestion.iter_mut().enumerate() {
+            channel.channel = index as u8 + 1;
+        }
+        for entry in &self.entries {
+            for channel in congestion.iter_mut() {
+                if entry.channel.abs_diff(channel.channel) <= OVERLAPPING_CHANNELS {
+                    channel.overlapping += 1;
+                }
+                if entry.channel == channel.channel {
+                    channel.access_points += 1;
+                    channel.strongest_rssi = Some(channel.strongest_rssi.map_or(entry.rssi, |rssi| rssi.max(entry.rssi)));
+                }
+            }
+        }
+        congestion
+    }
+
+    // Of the channels that do not overlap each other
+    pub fn least_congested_channel(&self) -> u8 {
+        let congestion = self.channel_congestion();
+        NON_OVERLAPPING_CHANNELS.into_iter()
+            .min_by_key(|channel| {
+                let channel = &congestion[*channel as usize - 1];
+                (channel.overlapping, channel.strongest_rssi)
+            })
+            .unwrap()
+    }
+}
+
+pub(crate) struct Surveys {
+    pub(crate) interval: Option<Duration>,
+    pub(crate) last_at: Option<Duration>,
+    pub(crate) last: Option<Survey>,
+}
+
+impl Surveys {
+    pub(crate) fn new() -> Surveys {
+        Surveys {
+            interval: None,
+            last_at: None,
+            last: None,
+        }
+    }
+}
+
+impl<S: Storage, W: Wifi, M: Mdns, E: NetIf, N: TimeSync> Network<'_, S, W, M, E, N> {
+    // Surveys are then taken while supervising a connection
+    pub fn with_survey_interval(mut self, interval: Duration) -> Self {
+        self.surveys.interval = Some(interval);
+        self
+    }
+
+    pub async fn survey(&mut self) -> Result<Survey, W::Error> {
+        let access_points = self.wifi.scan().await?;
+        let survey = Survey::new(&access_points, &self.ssid);
+        info!("Survey found {} access points", survey.entries.len());
+        self.surveys.last = Some(survey.clone());
+        Ok(survey)
+    }
+
+    pub fn last_survey(&self) -> Option<&Survey> {
+        self.surveys.last.as_ref()
+    }
+
+    pub(crate) async fn survey_if_due<T: Timer>(&mut self, timer: &T) {
+        let Some(interval) = self.surveys.interval else {
+            return;
+        };
+        let now = timer.now();
+        if self.surveys.last_at.is_some_and(|last_at| now.saturating_sub(last_at) < interval) {
+            return;
+        }
+        self.surveys.last_at = Some(now);
+        if let Err(error) = self.survey().await {
+            warn!("Background survey failed: {}", error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use embedded_svc::wifi::AccessPointInfo;
+
+    use crate::config::Config;
+    use crate::mocks::block_on::block_on;
+    use crate::mocks::mock_esp_nvs::MockEspNvs;
+    use crate::mocks::mock_mdns::MockMdns;
+    use crate::mocks::mock_timer::MockTimer;
+    use crate::mocks::mock_wifi::{access_point, MockWifi, MockWifiCall};
+    use crate::network::Network;
+    use crate::network::survey::Survey;
+    use crate::traits::read_write::ReadWrite;
+
+    fn access_point_with(ssid: &str, channel: u8, rssi: i8, last_bssid_byte: u8) -> AccessPointInfo {
+        let mut access_point = access_point(ssid, channel);
+        access_point.signal_strength = rssi;
+        access_point.bssid[5] = last_bssid_byte;
+        access_point
+    }
+
+    #[test]
+    fn sorts_and_deduplicates_access_points() {
+        let survey = Survey::new(&[
+            access_point_with("weak", 1, -80, 1),
+            access_point_with("home", 6, -50, 2),
+            access_point_with("home", 6, -70, 2),
+            access_point_with("home", 11, -60, 3),
+        ], "home");
+        let entries: Vec<_> = survey.entries.iter().map(|entry| (entry.ssid.as_str(), entry.rssi, entry.known)).collect();
+        assert_eq!(entries, [("home", -50, true), ("home", -60, true), ("weak", -80, false)]);
+    }
+
+    #[test]
+    fn summarises_channel_congestion() {
+        let survey = Survey::new(&[
+            access_point_with("a", 1, -50, 1),
+            access_point_with("b", 1, -70, 2),
+            access_point_with("c", 3, -60, 3),
+            access_point_with("d", 11, -40, 4),
+        ], "");
+        let congestion = survey.channel_congestion();
+        assert_eq!((congestion[0].access_points, congestion[0].overlapping, congestion[0].strongest_rssi), (2, 3, Some(-50)));
+        assert_eq!((congestion[5].access_points, congestion[5].overlapping, congestion[5].strongest_rssi), (0, 1, None));
+        assert_eq!(survey.least_congested_channel(), 6);
+    }
+
+    #[test]
+    fn surveys_periodically_while_connected() {
+        let mut config = Config::new(Arc::new(Mutex::new(MockEspNvs::from([]))), "test_ssid", "test_psk");
+        config.read().unwrap();
+        let wifi = MockWifi::new().with_scan_results(vec![access_point("test_ssid", 6)]);
+        let mut network = Network::new(Arc::new(Mutex::new(config)), wifi.clone(), MockMdns::new())
+            .with_survey_interval(Duration::from_secs(2));
+        block_on(network.start()).unwrap();
+        wifi.state().calls.clear();
+        let mut timer = MockTimer::new();
+        for _ in 0..4 {
+            block_on(network.supervise_once(&mut timer)).unwrap();
+        }
+        assert_eq!(wifi.calls().iter().filter(|call| **call == MockWifiCall::Scan).count(), 2);
+        assert!(network.last_survey().unwrap().entries[0].known);
+    }
+}
@@ -0,0 +1,64 @@
+use crate::network::Network;
+use crate::time::{SharedClock, TimeKeeper};
+use crate::traits::mdns::Mdns;
+use crate::traits::net_if::NetIf;
+use crate::traits::storage::Storage;
+use crate::traits::time_sync::TimeSync;
+use crate::traits::timer::Timer;
+use crate::traits::wifi::Wifi;
+
+impl<'a, S: Storage, W: Wifi, M: Mdns, E: NetIf, N: TimeSync> Network<'a, S, W, M, E, N> {
+    // Synced from the servers in the config once supervising finds the network online
+    pub fn with_time_sync<N2: TimeSync>(mut self, time_keeper: TimeKeeper<SharedClock, N2>) -> Network<'a, S, W, M, E, N2> {
+        let ethernet = self.ethernet.take();
+        self.rebuild(ethernet, Some(time_keeper))
+    }
+
+    pub fn time_keeper(&self) -> Option<&TimeKeeper<SharedClock, N>> {
+        self.time_keeper.as_ref()
+    }
+
+    pub(crate) async fn sync_time_if_due<T: Timer>(&mut self, timer: &T) {
+        let Some(time_keeper) = &mut self.time_keeper else {
+            return;
+        };
+        let servers: Vec<String> = self.config.lock().unwrap().ntp_servers().into_iter().map(String::from).collect();
+        let servers: Vec<&str> = servers.iter().map(String::as_str).collect();
+        time_keeper.sync_if_due(timer, &servers).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::config::Config;
+    use crate::mocks::block_on::block_on;
+    use crate::mocks::mock_esp_nvs::MockEspNvs;
+    use crate::mocks::mock_mdns::MockMdns;
+    use crate::mocks::mock_sntp_server::SERVER_TIME;
+    use crate::mocks::mock_time_sync::MockTimeSync;
+    use crate::mocks::mock_timer::MockTimer;
+    use crate::mocks::mock_wifi::MockWifi;
+    use crate::network::Network;
+    use crate::time::{MonotonicClock, SharedClock, TimeKeeper, TimeSyncState};
+    use crate::traits::clock::Clock;
+    use crate::traits::read_write::ReadWrite;
+
+    #[test]
+    fn syncs_once_online() {
+        let mut config = Config::new(Arc::new(Mutex::new(MockEspNvs::from([]))), "test_ssid", "test_psk");
+        config.read().unwrap();
+        config.set_ntp_servers(&["time.example", "[::1]:123"]).unwrap();
+        let clock: SharedClock = Arc::new(Mutex::new(MonotonicClock::new()));
+        let time_sync = MockTimeSync::new().answer("time.example", SERVER_TIME);
+        let mut network = Network::new(Arc::new(Mutex::new(config)), MockWifi::new(), MockMdns::new())
+            .with_time_sync(TimeKeeper::new(clock.clone(), time_sync.clone()));
+        block_on(network.start()).unwrap();
+        assert_eq!(network.time_keeper().unwrap().state(), TimeSyncState::Unsynced);
+        block_on(network.supervise_once(&mut MockTimer::new())).unwrap();
+        assert_eq!(network.time_keeper().unwrap().state(), TimeSyncState::Synced);
+        assert!(clock.utc().unwrap() >= SERVER_TIME);
+        assert_eq!(time_sync.state().queried, vec![vec!["time.example".to_string(), "[::1]:123".to_string()]]);
+    }
+}
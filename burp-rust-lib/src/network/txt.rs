@@ -0,0 +1,323 @@
+use std::str::from_utf8;
+
+use const_hex::{decode_to_slice, encode_to_slice};
+use log::*;
+use thiserror::Error;
+
+use crate::network::Network;
+use crate::network::discovery::DiscoveredPeer;
+use crate::network::services::MdnsService;
+use crate::traits::mdns::Mdns;
+use crate::traits::net_if::NetIf;
+use crate::traits::storage::Storage;
+use crate::traits::time_sync::TimeSync;
+use crate::traits::wifi::Wifi;
+
+// bumped whenever peers need to change how they talk to each other
+pub const PROTOCOL_VERSION: u8 = 1;
+// the service whose TXT records describe the device
+pub const DEVICE_SERVICE_TYPE: &str = "_burptech";
+pub const DEVICE_SERVICE_PROTO: &str = "_tcp";
+pub const FINGERPRINT_BYTES: usize = 32;
+// RFC 6763 section 6, every key=value string and the record as a whole
+const TXT_STRING_MAX_BYTES: usize = 255;
+// fits an answer in a single 512 byte packet
+const TXT_RECORD_MAX_BYTES: usize = 400;
+
+const PROTOCOL_VERSION_KEY: &str = "pv";
+const FIRMWARE_VERSION_KEY: &str = "fw";
+const HARDWARE_MODEL_KEY: &str = "hw";
+const ROLE_KEY: &str = "role";
+const FINGERPRINT_KEY: &str = "id";
+const CAPABILITIES_KEY: &str = "caps";
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum TxtError {
+    #[error("TXT record has no {0}")]
+    Missing(&'static str),
+    #[error("TXT record has an invalid {0}")]
+    Invalid(&'static str),
+    #[error("TXT record does not fit in {TXT_RECORD_MAX_BYTES} bytes")]
+    TooLong,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Role {
+    #[default]
+    Node,
+    Gateway,
+    Controller,
+}
+
+impl Role {
+    pub const ALL: [Role; 3] = [Role::Node, Role::Gateway, Role::Controller];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Node => "node",
+            Role::Gateway => "gateway",
+            Role::Controller => "controller",
+        }
+    }
+
+    pub fn parse(role: &str) -> Option<Role> {
+        Role::ALL.into_iter().find(|known| known.as_str() == role)
+    }
+}
+
+// Fixed when the firmware is built
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildInfo {
+    pub firmware_version: &'static str,
+    pub hardware_model: &'static str,
+    pub capabilities: Vec<&'static str>,
+}
+
+impl Default for BuildInfo {
+    fn default() -> Self {
+        BuildInfo {
+            firmware_version: env!("CARGO_PKG_VERSION"),
+            hardware_model: "unknown",
+            capabilities: Vec::new(),
+        }
+    }
+}
+
+// What a _burptech._tcp service says about the device behind it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceTxt {
+    pub protocol_version: u8,
+    pub firmware_version: String,
+    pub hardware_model: String,
+    pub role: Role,
+    // of the device identity, None until provisioned
+    pub fingerprint: Option<[u8; FINGERPRINT_BYTES]>,
+    pub capabilities: Vec<String>,
+}
+
+impl DeviceTxt {
+    pub fn new(build: &BuildInfo, role: Role, fingerprint: Option<[u8; FINGERPRINT_BYTES]>) -> DeviceTxt {
+        DeviceTxt {
+            protocol_version: PROTOCOL_VERSION,
+            firmware_version: String::from(build.firmware_version),
+            hardware_model: String::from(build.hardware_model),
+            role,
+            fingerprint,
+            capabilities: build.capabilities.iter().map(|capability| String::from(*capability)).collect(),
+        }
+    }
+
+    // Capabilities are dropped from the end until the record fits
+    pub fn encode(&self) -> Result<Vec<(String, String)>, TxtError> {
+        self.encode_with(&[])
+    }
+
+    // The same, with records configured on the service replacing device details that share their key
+    pub fn encode_with(&self, configured: &[(String, String)]) -> Result<Vec<(String, String)>, TxtError> {
+        let mut txt = vec![
+            (String::from(PROTOCOL_VERSION_KEY), self.protocol_version.to_string()),
+            (String::from(FIRMWARE_VERSION_KEY), self.firmware_version.clone()),
+            (String::from(HARDWARE_MODEL_KEY), self.hardware_model.clone()),
+            (String::from(ROLE_KEY), String::from(self.role.as_str())),
+        ];
+        if let Some(fingerprint) = &self.fingerprint {
+            let mut hex = [0_u8; FINGERPRINT_BYTES * 2];
+            encode_to_slice(fingerprint, &mut hex).unwrap();
+            txt.push((String::from(FINGERPRINT_KEY), String::from(from_utf8(&hex).unwrap())));
+        }
+        let overridden = |key: &str| configured.iter().any(|(own, _)| own == key);
+        txt.retain(|(key, _)| !overridden(key));
+        txt.extend(configured.iter().cloned());
+        if txt.iter().any(|entry| entry_bytes(entry) > TXT_STRING_MAX_BYTES) || record_bytes(&txt) > TXT_RECORD_MAX_BYTES {
+            return Err(TxtError::TooLong);
+        }
+        let mut capabilities = match overridden(CAPABILITIES_KEY) {
+            true => &[][..],
+            false => &self.capabilities[..],
+        };
+        while !capabilities.is_empty() {
+            txt.push((String::from(CAPABILITIES_KEY), capabilities.join(",")));
+            if entry_bytes(txt.last().unwrap()) <= TXT_STRING_MAX_BYTES && record_bytes(&txt) <= TXT_RECORD_MAX_BYTES {
+                break;
+            }
+            txt.pop();
+            capabilities = &capabilities[..capabilities.len() - 1];
+        }
+        if capabilities.len() < self.capabilities.len() && !overridden(CAPABILITIES_KEY) {
+            warn!("Only {} of {} capabilities fit in the TXT record", capabilities.len(), self.capabilities.len());
+        }
+        Ok(txt)
+    }
+
+    // Unknown keys are left for later protocol versions
+    pub fn decode(txt: &[(String, String)]) -> Result<DeviceTxt, TxtError> {
+        let get = |key| txt.iter().find(|(existing, _)| existing == key).map(|(_, value)| value.as_str());
+        let required = |key| get(key).ok_or(TxtError::Missing(key));
+        let fingerprint = match get(FINGERPRINT_KEY) {
+            Some(hex) => {
+                let mut fingerprint = [0_u8; FINGERPRINT_BYTES];
+                decode_to_slice(hex, &mut fingerprint).map_err(|_| TxtError::Invalid(FINGERPRINT_KEY))?;
+                Some(fingerprint)
+            }
+            None => None,
+        };
+        Ok(DeviceTxt {
+            protocol_version: required(PROTOCOL_VERSION_KEY)?.parse().map_err(|_| TxtError::Invalid(PROTOCOL_VERSION_KEY))?,
+            firmware_version: String::from(required(FIRMWARE_VERSION_KEY)?),
+            hardware_model: String::from(required(HARDWARE_MODEL_KEY)?),
+            role: Role::parse(required(ROLE_KEY)?).ok_or(TxtError::Invalid(ROLE_KEY))?,
+            fingerprint,
+            capabilities: get(CAPABILITIES_KEY)
+                .map_or(Vec::new(), |capabilities| capabilities.split(',').filter(|capability| !capability.is_empty()).map(String::from).collect()),
+        })
+    }
+
+    pub fn has_capability(&self, capability: &str) -> bool {
+        self.capabilities.iter().any(|known| known == capability)
+    }
+}
+
+// key=value
+fn entry_bytes((key, value): &(String, String)) -> usize {
+    key.len() + 1 + value.len()
+}
+
+// each string is preceded by its length
+fn record_bytes(txt: &[(String, String)]) -> usize {
+    txt.iter().map(|entry| entry_bytes(entry) + 1).sum()
+}
+
+impl DiscoveredPeer {
+    pub fn device_txt(&self) -> Result<DeviceTxt, TxtError> {
+        DeviceTxt::decode(&self.txt)
+    }
+}
+
+impl<S: Storage, W: Wifi, M: Mdns, E: NetIf, N: TimeSync> Network<'_, S, W, M, E, N> {
+    pub fn with_build_info(mut self, build: BuildInfo) -> Self {
+        self.services.build = build;
+        self
+    }
+
+    pub fn device_txt(&self) -> DeviceTxt {
+        let config = self.config.lock().unwrap();
+        DeviceTxt::new(&self.services.build, config.role(), config.fingerprint())
+    }
+
+    // Records the service was configured with take precedence
+    pub(crate) fn with_device_txt(&self, service: &MdnsService) -> MdnsService {
+        if !service.is(DEVICE_SERVICE_TYPE, DEVICE_SERVICE_PROTO) {
+            return service.clone();
+        }
+        // the configured records alone are kept within the mDNS services limit by Config
+        let txt = self.device_txt().encode_with(&service.txt).unwrap_or_else(|error| {
+            warn!("Advertising without device details: {}", error);
+            service.txt.clone()
+        });
+        MdnsService {
+            txt,
+            ..service.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::config::Config;
+    use crate::mocks::block_on::block_on;
+    use crate::mocks::mock_esp_nvs::MockEspNvs;
+    use crate::mocks::mock_mdns::{MockMdns, MockMdnsCall};
+    use crate::mocks::mock_wifi::MockWifi;
+    use crate::network::Network;
+    use crate::network::txt::{BuildInfo, DeviceTxt, Role, TxtError, PROTOCOL_VERSION};
+    use crate::traits::read_write::ReadWrite;
+
+    fn build() -> BuildInfo {
+        BuildInfo {
+            firmware_version: "1.2.3",
+            hardware_model: "esp32",
+            capabilities: vec!["ota", "metrics"],
+        }
+    }
+
+    #[test]
+    fn round_trips_through_txt() {
+        let device = DeviceTxt::new(&build(), Role::Gateway, Some([0xab; 32]));
+        let txt = device.encode().unwrap();
+        assert_eq!(txt[0], (String::from("pv"), PROTOCOL_VERSION.to_string()));
+        assert_eq!(txt.iter().find(|(key, _)| key == "id").unwrap().1, "ab".repeat(32));
+        assert_eq!(DeviceTxt::decode(&txt), Ok(device.clone()));
+        assert!(device.has_capability("ota"));
+
+        let mut txt = txt;
+        txt.retain(|(key, _)| key != "fw");
+        assert_eq!(DeviceTxt::decode(&txt), Err(TxtError::Missing("fw")));
+        txt.push((String::from("fw"), String::from("2.0.0")));
+        txt.iter_mut().find(|(key, _)| key == "role").unwrap().1 = String::from("king");
+        assert_eq!(DeviceTxt::decode(&txt), Err(TxtError::Invalid("role")));
+    }
+
+    #[test]
+    fn drops_capabilities_that_do_not_fit() {
+        let long = concat!("0123456789abcdefghijklmnopqrstuvwxyz", "0123456789abcdefghijklmnopqrstuvwxyz", "0123456789abcdefghijklmnopqrstuvwxyz");
+        let device = DeviceTxt::new(&BuildInfo {
+            capabilities: vec!["ota", long, long, long],
+            ..build()
+        }, Role::Node, Some([0; 32]));
+        let txt = device.encode().unwrap();
+        let decoded = DeviceTxt::decode(&txt).unwrap();
+        assert_eq!(decoded.capabilities.len(), 3);
+        assert!(txt.iter().map(|(key, value)| key.len() + value.len() + 2).sum::<usize>() <= 400);
+
+        // a record of exactly TXT_RECORD_MAX_BYTES fits
+        let mut device = DeviceTxt::new(&build(), Role::Node, None);
+        device.capabilities.clear();
+        device.hardware_model = "h".repeat(200);
+        let record_bytes = |txt: &[(String, String)]| txt.iter().map(|(key, value)| key.len() + value.len() + 2).sum::<usize>();
+        let spare = 400 - record_bytes(&device.encode().unwrap()) - "caps=".len() - 1;
+        device.capabilities = vec!["c".repeat(spare - 2), String::from("d")];
+        assert_eq!(record_bytes(&device.encode().unwrap()), 400);
+        device.capabilities = vec!["c".repeat(spare), String::from("d")];
+        assert_eq!(DeviceTxt::decode(&device.encode().unwrap()).unwrap().capabilities, ["c".repeat(spare)]);
+
+        device.firmware_version = "y".repeat(300);
+        assert_eq!(device.encode(), Err(TxtError::TooLong));
+    }
+
+    #[test]
+    fn fits_configured_records_in_the_same_limits() {
+        let device = DeviceTxt::new(&build(), Role::Node, Some([0; 32]));
+        let configured = [
+            (String::from("hw"), String::from("custom")),
+            (String::from("note"), "n".repeat(250)),
+            (String::from("more"), "m".repeat(20)),
+        ];
+        let txt = device.encode_with(&configured).unwrap();
+        assert_eq!(txt.iter().filter(|(key, _)| key == "hw").collect::<Vec<_>>(), [&configured[0]]);
+        assert!(txt.iter().map(|(key, value)| key.len() + value.len() + 2).sum::<usize>() <= 400);
+        assert_eq!(DeviceTxt::decode(&txt).unwrap().capabilities, ["ota"]);
+
+        assert_eq!(device.encode_with(&[(String::from("note"), "n".repeat(255))]), Err(TxtError::TooLong));
+        assert_eq!(device.encode_with(&[(String::from("note"), "n".repeat(200)), (String::from("more"), "m".repeat(200))]), Err(TxtError::TooLong));
+    }
+
+    #[test]
+    fn advertises_device_details() {
+        let mut config = Config::new(Arc::new(Mutex::new(MockEspNvs::from([]))), "test_ssid", "test_psk");
+        config.read().unwrap();
+        config.set_role(Role::Controller);
+        let mdns = MockMdns::new();
+        let mut network = Network::new(Arc::new(Mutex::new(config)), MockWifi::new(), mdns.clone())
+            .with_build_info(build());
+        block_on(network.start()).unwrap();
+        let txt = mdns.calls().into_iter().find_map(|call| match call {
+            MockMdnsCall::AddService { txt, .. } => Some(txt),
+            _ => None,
+        }).unwrap();
+        let device = DeviceTxt::decode(&txt).unwrap();
+        assert_eq!(device, DeviceTxt::new(&build(), Role::Controller, None));
+        assert_eq!(network.device_txt(), device);
+    }
+}
@@ -0,0 +1,106 @@
+use heapless::String;
+use log::*;
+use thiserror::Error;
+
+use crate::config::Config;
+use crate::traits::read_write::ReadWrite;
+use crate::traits::storage::Storage;
+
+pub mod captive_portal;
+pub mod dns;
+pub mod http;
+pub mod improv;
+pub mod wifi_uri;
+
+const SSID_MAX_BYTES: usize = 32;
+const PSK_MIN_BYTES: usize = 8;
+const PSK_MAX_BYTES: usize = 64;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum CredentialsError {
+    #[error("SSID must not be empty")]
+    EmptySsid,
+    #[error("SSID must be at most {SSID_MAX_BYTES} bytes")]
+    SsidTooLong,
+    #[error("Password must be {PSK_MIN_BYTES} to 63 characters or 64 hex digits")]
+    InvalidPsk,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credentials {
+    pub ssid: String<SSID_MAX_BYTES>,
+    pub psk: String<PSK_MAX_BYTES>,
+}
+
+impl Credentials {
+    // An empty psk is allowed for open networks
+    pub fn new(ssid: &str, psk: &str) -> Result<Credentials, CredentialsError> {
+        if ssid.is_empty() {
+            return Err(CredentialsError::EmptySsid);
+        }
+        if ssid.len() > SSID_MAX_BYTES {
+            return Err(CredentialsError::SsidTooLong);
+        }
+        let passphrase = (PSK_MIN_BYTES..PSK_MAX_BYTES).contains(&psk.len())
+            && psk.bytes().all(|byte| (0x20..0x7f).contains(&byte));
+        let raw_key = psk.len() == PSK_MAX_BYTES && psk.bytes().all(|byte| byte.is_ascii_hexdigit());
+        if !psk.is_empty() && !passphrase && !raw_key {
+            return Err(CredentialsError::InvalidPsk);
+        }
+        Ok(Credentials {
+            ssid: String::from(ssid),
+            psk: String::from(psk),
+        })
+    }
+
+    // The auth method goes back to being detected as the new network may use a different one
+    pub fn apply<S: Storage>(&self, config: &mut Config<'_, S>) -> Result<(), S::Error> {
+        info!("Saving credentials for ssid: {}", self.ssid);
+        config.ssid.set(self.ssid.as_bytes());
+        config.psk.set(self.psk.as_bytes());
+        config.set_auth_method_override(None);
+        config.set_last_access_point(None);
+        config.write()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::from_utf8;
+    use std::sync::{Arc, Mutex};
+
+    use embedded_svc::wifi::AuthMethod;
+
+    use crate::config::Config;
+    use crate::mocks::mock_esp_nvs::MockEspNvs;
+    use crate::provisioning::{Credentials, CredentialsError};
+    use crate::traits::read_write::ReadWrite;
+
+    #[test]
+    fn validates_credentials() {
+        assert!(Credentials::new("ssid", "").is_ok());
+        assert!(Credentials::new("ssid", "12345678").is_ok());
+        assert!(Credentials::new("ssid", &"a".repeat(63)).is_ok());
+        assert!(Credentials::new("ssid", &"0f".repeat(32)).is_ok());
+        assert!(Credentials::new(&"s".repeat(32), "").is_ok());
+        assert_eq!(Credentials::new("", "12345678"), Err(CredentialsError::EmptySsid));
+        assert_eq!(Credentials::new(&"s".repeat(33), ""), Err(CredentialsError::SsidTooLong));
+        assert_eq!(Credentials::new("ssid", "1234567"), Err(CredentialsError::InvalidPsk));
+        assert_eq!(Credentials::new("ssid", &"x".repeat(64)), Err(CredentialsError::InvalidPsk));
+        assert_eq!(Credentials::new("ssid", "tab\tpassword"), Err(CredentialsError::InvalidPsk));
+    }
+
+    #[test]
+    fn applies_credentials_to_config() {
+        let storage = Arc::new(Mutex::new(MockEspNvs::from([])));
+        let mut config = Config::new(storage.clone(), "", "");
+        config.read().unwrap();
+        config.set_auth_method_override(Some(AuthMethod::WPA3Personal));
+        Credentials::new("new_ssid", "new_password").unwrap().apply(&mut config).unwrap();
+        let mut config = Config::new(storage, "", "");
+        config.read().unwrap();
+        assert_eq!(from_utf8(config.ssid.get()).unwrap(), "new_ssid");
+        assert_eq!(from_utf8(config.psk.get()).unwrap(), "new_password");
+        assert_eq!(config.auth_method_override(), None);
+    }
+}
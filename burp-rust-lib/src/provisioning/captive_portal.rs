@@ -0,0 +1,235 @@
+use std::io;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+
+use embedded_svc::ipv4::Ipv4Addr;
+use embedded_svc::wifi::AccessPointInfo;
+use log::*;
+
+use crate::provisioning::Credentials;
+use crate::provisioning::dns::DnsResponder;
+use crate::provisioning::http::{HttpPortal, Response};
+
+// from accepting a connection to having sent the response
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+// browsers open a few at once, some of them left idle
+const MAX_CONNECTIONS: usize = 4;
+
+enum Exchange {
+    Pending,
+    Finished(Option<Credentials>),
+}
+
+struct Connection {
+    stream: TcpStream,
+    peer: SocketAddr,
+    received: Vec<u8>,
+    // None while the request is still being received
+    response: Option<Response>,
+    written: usize,
+    deadline: Instant,
+}
+
+impl Connection {
+    // Reads and writes whatever the socket takes without waiting
+    fn advance(&mut self, http: &HttpPortal, networks: &[AccessPointInfo]) -> io::Result<Exchange> {
+        if Instant::now() >= self.deadline {
+            return Err(io::Error::new(ErrorKind::TimedOut, "request timed out"));
+        }
+        if self.response.is_none() {
+            let mut chunk = [0_u8; 256];
+            let closed = loop {
+                match self.stream.read(&mut chunk) {
+                    Ok(0) => break true,
+                    Ok(len) => self.received.extend_from_slice(&chunk[..len]),
+                    Err(error) if error.kind() == ErrorKind::WouldBlock => break false,
+                    Err(error) => return Err(error),
+                }
+            };
+            self.response = http.handle(&self.received, networks);
+            if self.response.is_none() {
+                return match closed {
+                    true => Err(io::Error::new(ErrorKind::UnexpectedEof, "connection closed")),
+                    false => Ok(Exchange::Pending),
+                };
+            }
+        }
+        let response = self.response.as_mut().unwrap();
+        while self.written < response.bytes.len() {
+            match self.stream.write(&response.bytes[self.written..]) {
+                Ok(0) => return Err(io::Error::new(ErrorKind::WriteZero, "connection closed")),
+                Ok(len) => self.written += len,
+                Err(error) if error.kind() == ErrorKind::WouldBlock => return Ok(Exchange::Pending),
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(Exchange::Finished(response.credentials.take()))
+    }
+}
+
+struct Sockets {
+    listener: TcpListener,
+    dns: DnsResponder,
+}
+
+impl Sockets {
+    fn bind(http_addr: SocketAddr, dns_addr: SocketAddr, ip: Ipv4Addr) -> io::Result<Sockets> {
+        let listener = TcpListener::bind(http_addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(Sockets { listener, dns: DnsResponder::bind(dns_addr, ip)? })
+    }
+}
+
+// Non-blocking so it can be polled from the network supervisor between station retries.
+// Each connection is moved along a little on every poll.
+pub struct CaptivePortal {
+    http_addr: SocketAddr,
+    dns_addr: SocketAddr,
+    ip: Ipv4Addr,
+    // None until bound and again once closed
+    sockets: Option<Sockets>,
+    http: HttpPortal,
+    connections: Vec<Connection>,
+}
+
+impl CaptivePortal {
+    // ip is the soft access point address that every DNS query is answered with.
+    // Nothing is bound until the soft access point comes up.
+    pub fn new(http_addr: SocketAddr, dns_addr: SocketAddr, ip: Ipv4Addr) -> CaptivePortal {
+        CaptivePortal {
+            http_addr,
+            dns_addr,
+            ip,
+            sockets: None,
+            http: HttpPortal::new(ip),
+            connections: Vec::new(),
+        }
+    }
+
+    // Does nothing when already bound
+    pub fn bind(&mut self) -> io::Result<()> {
+        if self.sockets.is_none() {
+            self.sockets = Some(Sockets::bind(self.http_addr, self.dns_addr, self.ip)?);
+        }
+        Ok(())
+    }
+
+    pub fn is_bound(&self) -> bool {
+        self.sockets.is_some()
+    }
+
+    pub fn http_addr(&self) -> io::Result<SocketAddr> {
+        self.sockets()?.listener.local_addr()
+    }
+
+    pub fn dns_addr(&self) -> io::Result<SocketAddr> {
+        self.sockets()?.dns.local_addr()
+    }
+
+    fn sockets(&self) -> io::Result<&Sockets> {
+        self.sockets.as_ref().ok_or_else(|| io::Error::from(ErrorKind::NotConnected))
+    }
+
+    // Frees the ports and drops open connections
+    pub fn close(&mut self) {
+        self.sockets = None;
+        self.connections.clear();
+    }
+
+    // Answers pending DNS queries and moves the HTTP connections along, returns
+    // the credentials once the response to submitting them has been sent
+    pub fn poll(&mut self, networks: &[AccessPointInfo]) -> io::Result<Option<Credentials>> {
+        let sockets = self.sockets.as_ref().ok_or_else(|| io::Error::from(ErrorKind::NotConnected))?;
+        sockets.dns.poll()?;
+        while self.connections.len() < MAX_CONNECTIONS {
+            let (stream, peer) = match sockets.listener.accept() {
+                Ok(accepted) => accepted,
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+                Err(error) => return Err(error),
+            };
+            debug!("Portal connection from {}", peer);
+            stream.set_nonblocking(true)?;
+            self.connections.push(Connection {
+                stream,
+                peer,
+                received: Vec::new(),
+                response: None,
+                written: 0,
+                deadline: Instant::now() + REQUEST_TIMEOUT,
+            });
+        }
+        let mut provisioned = None;
+        let http = &self.http;
+        self.connections.retain_mut(|connection| match connection.advance(http, networks) {
+            Ok(Exchange::Pending) => true,
+            Ok(Exchange::Finished(credentials)) => {
+                provisioned = provisioned.take().or(credentials);
+                false
+            }
+            // a client going away is not a portal failure
+            Err(error) => {
+                warn!("Portal request from {} failed: {}", connection.peer, error);
+                false
+            }
+        });
+        Ok(provisioned)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream, UdpSocket};
+    use std::time::{Duration, Instant};
+
+    use embedded_svc::ipv4::Ipv4Addr;
+
+    use crate::provisioning::captive_portal::CaptivePortal;
+
+    #[test]
+    fn serves_other_clients_while_one_stays_silent() {
+        let mut portal = CaptivePortal::new(
+            "127.0.0.1:0".parse().unwrap(),
+            "127.0.0.1:0".parse().unwrap(),
+            Ipv4Addr::new(192, 168, 71, 1),
+        );
+        portal.bind().unwrap();
+        let _silent = TcpStream::connect(portal.http_addr().unwrap()).unwrap();
+        let mut client = TcpStream::connect(portal.http_addr().unwrap()).unwrap();
+        client.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+        client.set_nonblocking(true).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(2);
+        let mut response = Vec::new();
+        while !response.ends_with(b"</html>") {
+            assert!(Instant::now() < deadline, "portal did not respond");
+            let polled = Instant::now();
+            assert_eq!(portal.poll(&[]).unwrap(), None);
+            assert!(polled.elapsed() < Duration::from_millis(100));
+            let mut chunk = [0_u8; 1024];
+            if let Ok(len) = client.read(&mut chunk) {
+                response.extend_from_slice(&chunk[..len]);
+            }
+        }
+        assert!(response.starts_with(b"HTTP/1.1 200 OK\r\n"));
+    }
+
+    #[test]
+    fn holds_its_ports_only_while_bound() {
+        let mut portal = CaptivePortal::new(
+            "127.0.0.1:0".parse().unwrap(),
+            "127.0.0.1:0".parse().unwrap(),
+            Ipv4Addr::new(192, 168, 71, 1),
+        );
+        assert!(portal.http_addr().is_err());
+        assert!(portal.poll(&[]).is_err());
+        portal.bind().unwrap();
+        let (http_addr, dns_addr) = (portal.http_addr().unwrap(), portal.dns_addr().unwrap());
+        portal.close();
+        assert!(!portal.is_bound());
+        drop(TcpListener::bind(http_addr).unwrap());
+        drop(UdpSocket::bind(dns_addr).unwrap());
+        portal.bind().unwrap();
+        assert_eq!(portal.poll(&[]).unwrap(), None);
+    }
+}
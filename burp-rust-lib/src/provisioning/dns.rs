@@ -0,0 +1,154 @@
+use std::io;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, UdpSocket};
+
+use embedded_svc::ipv4::Ipv4Addr;
+use log::*;
+
+const HEADER_BYTES: usize = 12;
+const MAX_MESSAGE_BYTES: usize = 512;
+const TYPE_A: u16 = 1;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+const ANSWER_TTL_SECONDS: u32 = 60;
+const ANSWER_BYTES: usize = 16;
+
+// Answers every A query with the same address so that clients on the soft access point
+// open the captive portal whatever host they look up
+pub fn wildcard_response(query: &[u8], ip: Ipv4Addr, response: &mut [u8]) -> Option<usize> {
+    if query.len() < HEADER_BYTES {
+        return None;
+    }
+    let flags = u16::from_be_bytes([query[2], query[3]]);
+    let is_response = flags & 0x8000 != 0;
+    let opcode = (flags >> 11) & 0xf;
+    let question_count = u16::from_be_bytes([query[4], query[5]]);
+    if is_response || opcode != 0 || question_count != 1 {
+        return None;
+    }
+    let mut position = HEADER_BYTES;
+    loop {
+        let len = *query.get(position)? as usize;
+        position += 1;
+        if len == 0 {
+            break;
+        }
+        // compression pointers are not expected in a question
+        if len & 0xc0 != 0 {
+            return None;
+        }
+        position += len;
+    }
+    let question_end = position + 4;
+    let question = query.get(HEADER_BYTES..question_end)?;
+    let qtype = u16::from_be_bytes([query[position], query[position + 1]]);
+    let qclass = u16::from_be_bytes([query[position + 2], query[position + 3]]);
+    let answer = (qtype == TYPE_A || qtype == TYPE_ANY) && qclass == CLASS_IN;
+
+    let len = question_end + if answer { ANSWER_BYTES } else { 0 };
+    if response.len() < len {
+        return None;
+    }
+    response[..2].copy_from_slice(&query[..2]);
+    // response, authoritative, recursion desired copied, recursion available
+    let response_flags = 0x8480 | (flags & 0x0100);
+    response[2..4].copy_from_slice(&response_flags.to_be_bytes());
+    response[4..6].copy_from_slice(&1_u16.to_be_bytes());
+    response[6..8].copy_from_slice(&(answer as u16).to_be_bytes());
+    response[8..12].fill(0);
+    response[HEADER_BYTES..question_end].copy_from_slice(question);
+    if answer {
+        let answer = &mut response[question_end..len];
+        // name is a pointer to the question
+        answer[..2].copy_from_slice(&0xc00c_u16.to_be_bytes());
+        answer[2..4].copy_from_slice(&TYPE_A.to_be_bytes());
+        answer[4..6].copy_from_slice(&CLASS_IN.to_be_bytes());
+        answer[6..10].copy_from_slice(&ANSWER_TTL_SECONDS.to_be_bytes());
+        answer[10..12].copy_from_slice(&4_u16.to_be_bytes());
+        answer[12..16].copy_from_slice(&ip.octets());
+    }
+    Some(len)
+}
+
+pub struct DnsResponder {
+    socket: UdpSocket,
+    ip: Ipv4Addr,
+}
+
+impl DnsResponder {
+    pub fn bind(addr: SocketAddr, ip: Ipv4Addr) -> io::Result<DnsResponder> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_nonblocking(true)?;
+        Ok(DnsResponder { socket, ip })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    // Answers the queries already waiting without blocking, returns how many were answered
+    pub fn poll(&self) -> io::Result<usize> {
+        let mut query = [0_u8; MAX_MESSAGE_BYTES];
+        let mut response = [0_u8; MAX_MESSAGE_BYTES];
+        let mut answered = 0;
+        loop {
+            let (len, source) = match self.socket.recv_from(&mut query) {
+                Ok(received) => received,
+                Err(error) if error.kind() == ErrorKind::WouldBlock => return Ok(answered),
+                Err(error) => return Err(error),
+            };
+            match wildcard_response(&query[..len], self.ip, &mut response) {
+                Some(len) => {
+                    self.socket.send_to(&response[..len], source)?;
+                    answered += 1;
+                }
+                None => debug!("Ignoring malformed DNS query from {}", source),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_svc::ipv4::Ipv4Addr;
+
+    use crate::provisioning::dns::wildcard_response;
+
+    fn query(name: &str, qtype: u16) -> Vec<u8> {
+        let mut query = vec![0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+        for label in name.split('.') {
+            query.push(label.len() as u8);
+            query.extend_from_slice(label.as_bytes());
+        }
+        query.push(0);
+        query.extend_from_slice(&qtype.to_be_bytes());
+        query.extend_from_slice(&1_u16.to_be_bytes());
+        query
+    }
+
+    #[test]
+    fn answers_a_queries_with_portal_address() {
+        let query = query("connectivitycheck.gstatic.com", 1);
+        let mut response = [0_u8; 512];
+        let len = wildcard_response(&query, Ipv4Addr::new(192, 168, 71, 1), &mut response).unwrap();
+        assert_eq!(len, query.len() + 16);
+        assert_eq!(&response[..2], &[0x12, 0x34]);
+        assert_eq!(&response[2..4], &[0x85, 0x80]);
+        assert_eq!(&response[6..8], &[0, 1]);
+        assert_eq!(&response[12..query.len()], &query[12..]);
+        assert_eq!(&response[len - 4..len], &[192, 168, 71, 1]);
+    }
+
+    #[test]
+    fn answers_other_queries_without_records_and_ignores_malformed_ones() {
+        let query = query("example.com", 28);
+        let mut response = [0_u8; 512];
+        let len = wildcard_response(&query, Ipv4Addr::new(192, 168, 71, 1), &mut response).unwrap();
+        assert_eq!(len, query.len());
+        assert_eq!(&response[6..8], &[0, 0]);
+        assert_eq!(wildcard_response(&query[..query.len() - 2], Ipv4Addr::new(192, 168, 71, 1), &mut response), None);
+        let mut reply = query.clone();
+        reply[2] |= 0x80;
+        assert_eq!(wildcard_response(&reply, Ipv4Addr::new(192, 168, 71, 1), &mut response), None);
+    }
+}
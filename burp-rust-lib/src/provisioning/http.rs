@@ -0,0 +1,259 @@
+use std::cmp::Reverse;
+use std::fmt::Write as _;
+use std::str::from_utf8;
+
+use embedded_svc::ipv4::Ipv4Addr;
+use embedded_svc::wifi::{AccessPointInfo, AuthMethod};
+use log::*;
+
+use crate::provisioning::Credentials;
+
+const MAX_HEAD_BYTES: usize = 2048;
+const MAX_BODY_BYTES: usize = 512;
+
+struct Request {
+    method: String,
+    path: String,
+    body: Vec<u8>,
+}
+
+pub struct Response {
+    pub bytes: Vec<u8>,
+    // once a valid form is submitted
+    pub credentials: Option<Credentials>,
+}
+
+// Serves the provisioning form and redirects everything else to it, which is what makes
+// phones pop the portal up when they probe for connectivity
+pub struct HttpPortal {
+    ip: Ipv4Addr,
+}
+
+impl HttpPortal {
+    pub fn new(ip: Ipv4Addr) -> HttpPortal {
+        HttpPortal { ip }
+    }
+
+    // Responds to a single request, None until all of it has been received
+    pub fn handle(&self, received: &[u8], networks: &[AccessPointInfo]) -> Option<Response> {
+        let request = match parse_request(received) {
+            Ok(Some(request)) => request,
+            Ok(None) => return None,
+            Err(error) => {
+                warn!("Bad portal request: {}", error);
+                return Some(response("400 Bad Request", &[], "Bad request", None));
+            }
+        };
+        info!("Portal request: {} {}", request.method, request.path);
+        Some(match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/") => page_response("200 OK", &form_page(networks, None), None),
+            ("POST", "/") => {
+                let ssid = form_value(&request.body, "ssid").unwrap_or_default();
+                let psk = form_value(&request.body, "psk").unwrap_or_default();
+                match Credentials::new(&ssid, &psk) {
+                    Ok(credentials) => {
+                        let page = page(&format!("<p>Saved, connecting to {}</p>", escape(&credentials.ssid)));
+                        page_response("200 OK", &page, Some(credentials))
+                    }
+                    Err(error) => page_response("400 Bad Request", &form_page(networks, Some(&error.to_string())), None),
+                }
+            }
+            _ => {
+                let location = format!("http://{}/", self.ip);
+                response("302 Found", &[("Location", &location)], "", None)
+            }
+        })
+    }
+}
+
+// Ok(None) while more of the request is still to come
+fn parse_request(received: &[u8]) -> Result<Option<Request>, &'static str> {
+    let Some(head_end) = received.windows(4).position(|window| window == b"\r\n\r\n") else {
+        return if received.len() > MAX_HEAD_BYTES { Err("request head too large") } else { Ok(None) };
+    };
+    if head_end > MAX_HEAD_BYTES {
+        return Err("request head too large");
+    }
+    let head = from_utf8(&received[..head_end]).map_err(|_| "request head is not utf8")?;
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let (Some(method), Some(target)) = (request_line.next(), request_line.next()) else {
+        return Err("bad request line");
+    };
+    let path = target.split('?').next().unwrap_or_default();
+    let content_length = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .map(|(_, value)| value.trim().parse::<usize>())
+        .transpose()
+        .map_err(|_| "bad content length")?
+        .unwrap_or(0);
+    if content_length > MAX_BODY_BYTES {
+        return Err("request body too large");
+    }
+    let body = &received[head_end + 4..];
+    if body.len() < content_length {
+        return Ok(None);
+    }
+    Ok(Some(Request {
+        method: method.into(),
+        path: path.into(),
+        body: body[..content_length].to_vec(),
+    }))
+}
+
+fn response(status: &str, headers: &[(&str, &str)], body: &str, credentials: Option<Credentials>) -> Response {
+    let mut text = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n", status, body.len());
+    for (name, value) in headers {
+        write!(text, "{}: {}\r\n", name, value).ok();
+    }
+    write!(text, "\r\n{}", body).ok();
+    Response {
+        bytes: text.into_bytes(),
+        credentials,
+    }
+}
+
+fn page_response(status: &str, page: &str, credentials: Option<Credentials>) -> Response {
+    response(status, &[("Content-Type", "text/html; charset=utf-8")], page, credentials)
+}
+
+fn page(content: &str) -> String {
+    format!(
+        "<!DOCTYPE html><html><head><meta name=\"viewport\" content=\"width=device-width\">\
+        <title>Wi-Fi setup</title></head><body>{}</body></html>",
+        content,
+    )
+}
+
+fn form_page(networks: &[AccessPointInfo], error: Option<&str>) -> String {
+    let mut content = String::from("<h1>Wi-Fi setup</h1>");
+    if let Some(error) = error {
+        write!(content, "<p><strong>{}</strong></p>", escape(error)).ok();
+    }
+    content.push_str("<form method=\"post\" action=\"/\"><p><label>Network <input name=\"ssid\" list=\"networks\" required></label></p><datalist id=\"networks\">");
+    let mut sorted: Vec<&AccessPointInfo> = networks.iter().filter(|network| !network.ssid.is_empty()).collect();
+    sorted.sort_by_key(|network| Reverse(network.signal_strength));
+    // the strongest access point of each ssid
+    let mut networks: Vec<&AccessPointInfo> = Vec::new();
+    for network in sorted {
+        if !networks.iter().any(|known| known.ssid == network.ssid) {
+            networks.push(network);
+        }
+    }
+    for network in &networks {
+        write!(content, "<option value=\"{}\">", escape(&network.ssid)).ok();
+    }
+    content.push_str("</datalist><p><label>Password <input name=\"psk\" type=\"password\"></label></p><p><button>Save</button></p></form><ul>");
+    for network in &networks {
+        write!(
+            content,
+            "<li>{} ({} dBm{})</li>",
+            escape(&network.ssid),
+            network.signal_strength,
+            if network.auth_method == AuthMethod::None { ", open" } else { "" },
+        ).ok();
+    }
+    content.push_str("</ul>");
+    page(&content)
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// application/x-www-form-urlencoded, None when the field is missing or not valid utf8
+fn form_value(body: &[u8], name: &str) -> Option<String> {
+    body.split(|byte| *byte == b'&')
+        .filter_map(|pair| {
+            let separator = pair.iter().position(|byte| *byte == b'=')?;
+            Some((&pair[..separator], &pair[separator + 1..]))
+        })
+        .find(|(key, _)| url_decode(key).as_deref() == Some(name))
+        .and_then(|(_, value)| url_decode(value))
+}
+
+fn url_decode(encoded: &[u8]) -> Option<String> {
+    let mut decoded = Vec::with_capacity(encoded.len());
+    let mut bytes = encoded.iter();
+    while let Some(byte) = bytes.next() {
+        match byte {
+            b'+' => decoded.push(b' '),
+            b'%' => {
+                let high = (*bytes.next()? as char).to_digit(16)?;
+                let low = (*bytes.next()? as char).to_digit(16)?;
+                decoded.push((high * 16 + low) as u8);
+            }
+            byte => decoded.push(*byte),
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_svc::ipv4::Ipv4Addr;
+
+    use crate::mocks::mock_wifi::access_point;
+    use crate::provisioning::Credentials;
+    use crate::provisioning::http::HttpPortal;
+
+    fn request(request: &str) -> (Option<Credentials>, String) {
+        let portal = HttpPortal::new(Ipv4Addr::new(192, 168, 71, 1));
+        let networks = [access_point("home<net>", 6), access_point("office", 1)];
+        assert!(portal.handle(&request.as_bytes()[..request.len() - 1], &networks).is_none());
+        let response = portal.handle(request.as_bytes(), &networks).unwrap();
+        (response.credentials, String::from_utf8(response.bytes).unwrap())
+    }
+
+    fn post(body: &str) -> (Option<Credentials>, String) {
+        request(&format!("POST / HTTP/1.1\r\nHost: 192.168.71.1\r\nContent-Length: {}\r\n\r\n{}", body.len(), body))
+    }
+
+    #[test]
+    fn serves_form_listing_scanned_networks() {
+        let (credentials, response) = request("GET / HTTP/1.1\r\nHost: 192.168.71.1\r\n\r\n");
+        assert_eq!(credentials, None);
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("<option value=\"home&lt;net&gt;\">"));
+        assert!(response.contains("<option value=\"office\">"));
+    }
+
+    #[test]
+    fn redirects_connectivity_checks_to_form() {
+        let (credentials, response) = request("GET /generate_204 HTTP/1.1\r\nHost: clients3.google.com\r\n\r\n");
+        assert_eq!(credentials, None);
+        assert!(response.starts_with("HTTP/1.1 302 Found\r\n"));
+        assert!(response.contains("Location: http://192.168.71.1/\r\n"));
+    }
+
+    #[test]
+    fn returns_submitted_credentials() {
+        let (credentials, response) = post("ssid=home+net%21&psk=p%40ssw0rd%26more");
+        assert_eq!(credentials, Some(Credentials::new("home net!", "p@ssw0rd&more").unwrap()));
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("connecting to home net!"));
+    }
+
+    #[test]
+    fn rejects_invalid_credentials() {
+        let (credentials, response) = post("ssid=home&psk=short");
+        assert_eq!(credentials, None);
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        assert!(response.contains("Password must be"));
+        let (credentials, response) = request("BROKEN\r\n\r\n");
+        assert_eq!(credentials, None);
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    }
+}
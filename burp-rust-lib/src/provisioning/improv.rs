@@ -0,0 +1,394 @@
+use std::str::from_utf8;
+
+use embedded_svc::wifi::{AccessPointInfo, AuthMethod};
+use heapless::Vec;
+use log::*;
+use thiserror::Error;
+
+use crate::provisioning::Credentials;
+use crate::traits::byte_stream::ByteStream;
+
+// https://www.improv-wifi.com/serial/
+const HEADER: &[u8; 6] = b"IMPROV";
+const VERSION: u8 = 1;
+const MAX_DATA_BYTES: usize = 255;
+// header, version, type, length, data and checksum
+const MAX_PACKET_BYTES: usize = HEADER.len() + 3 + MAX_DATA_BYTES + 1;
+
+const TYPE_CURRENT_STATE: u8 = 0x01;
+const TYPE_ERROR_STATE: u8 = 0x02;
+const TYPE_RPC_COMMAND: u8 = 0x03;
+const TYPE_RPC_RESULT: u8 = 0x04;
+
+const COMMAND_WIFI_SETTINGS: u8 = 0x01;
+const COMMAND_CURRENT_STATE: u8 = 0x02;
+const COMMAND_DEVICE_INFO: u8 = 0x03;
+const COMMAND_WIFI_NETWORKS: u8 = 0x04;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImprovState {
+    Authorized = 0x02,
+    Provisioning = 0x03,
+    Provisioned = 0x04,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImprovErrorCode {
+    None = 0x00,
+    InvalidRpc = 0x01,
+    UnknownRpc = 0x02,
+    UnableToConnect = 0x03,
+    Unknown = 0xff,
+}
+
+#[derive(Error, Debug)]
+pub enum ImprovError<E> {
+    #[error("Improv transport error: {0}")]
+    Transport(E),
+}
+
+// Commands that need the network to answer, the rest are answered by Improv itself
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImprovCommand {
+    WifiSettings(Credentials),
+    WifiNetworks,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImprovPacket {
+    pub packet_type: u8,
+    pub data: Vec<u8, MAX_DATA_BYTES>,
+}
+
+impl ImprovPacket {
+    pub fn new(packet_type: u8, data: &[u8]) -> ImprovPacket {
+        ImprovPacket {
+            packet_type,
+            data: Vec::from_slice(&data[..data.len().min(MAX_DATA_BYTES)]).unwrap(),
+        }
+    }
+
+    // Strings are length prefixed and truncated to fit in a single packet
+    fn rpc_result(command: u8, strings: &[&[u8]]) -> ImprovPacket {
+        let mut data: Vec<u8, MAX_DATA_BYTES> = Vec::new();
+        data.push(command).ok();
+        data.push(0).ok();
+        for string in strings {
+            if data.len() == MAX_DATA_BYTES {
+                break;
+            }
+            let len = string.len().min(MAX_DATA_BYTES - data.len() - 1);
+            data.push(len as u8).ok();
+            data.extend_from_slice(&string[..len]).ok();
+        }
+        data[1] = (data.len() - 2) as u8;
+        ImprovPacket {
+            packet_type: TYPE_RPC_RESULT,
+            data,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8, MAX_PACKET_BYTES> {
+        let mut packet: Vec<u8, MAX_PACKET_BYTES> = Vec::new();
+        packet.extend_from_slice(HEADER).ok();
+        packet.push(VERSION).ok();
+        packet.push(self.packet_type).ok();
+        packet.push(self.data.len() as u8).ok();
+        packet.extend_from_slice(&self.data).ok();
+        packet.push(checksum(&packet)).ok();
+        packet
+    }
+}
+
+fn checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0_u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    Checksum,
+    Version(u8),
+}
+
+// Picks packets out of a serial line that also carries log output
+pub struct ImprovParser {
+    buffer: Vec<u8, MAX_PACKET_BYTES>,
+}
+
+impl Default for ImprovParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ImprovParser {
+    pub fn new() -> ImprovParser {
+        ImprovParser {
+            buffer: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, byte: u8) -> Option<Result<ImprovPacket, ParseError>> {
+        let position = self.buffer.len();
+        if position < HEADER.len() && byte != HEADER[position] {
+            self.buffer.clear();
+            if byte == HEADER[0] {
+                self.buffer.push(byte).ok();
+            }
+            return None;
+        }
+        self.buffer.push(byte).ok();
+        let header_len = HEADER.len() + 3;
+        if self.buffer.len() < header_len {
+            return None;
+        }
+        let data_len = self.buffer[header_len - 1] as usize;
+        if self.buffer.len() < header_len + data_len + 1 {
+            return None;
+        }
+        let packet = &self.buffer[..header_len + data_len];
+        let result = if checksum(packet) != byte {
+            Err(ParseError::Checksum)
+        } else if packet[HEADER.len()] != VERSION {
+            Err(ParseError::Version(packet[HEADER.len()]))
+        } else {
+            Ok(ImprovPacket::new(packet[HEADER.len() + 1], &packet[header_len..]))
+        };
+        self.buffer.clear();
+        Some(result)
+    }
+}
+
+fn parse_string(data: &[u8]) -> Option<(&str, &[u8])> {
+    let len = *data.first()? as usize;
+    let string = data.get(1..1 + len)?;
+    Some((from_utf8(string).ok()?, &data[1 + len..]))
+}
+
+#[derive(Debug, Clone)]
+pub struct DeviceInfo<'a> {
+    pub firmware_name: &'a str,
+    pub firmware_version: &'a str,
+    pub chip: &'a str,
+    pub device_name: &'a str,
+}
+
+pub struct Improv<'a, T> {
+    stream: T,
+    parser: ImprovParser,
+    state: ImprovState,
+    device_info: DeviceInfo<'a>,
+    redirect_url: Option<&'a str>,
+}
+
+impl<'a, T: ByteStream> Improv<'a, T> {
+    // Devices are always authorized as there is no physical button to press
+    pub fn new(stream: T, device_info: DeviceInfo<'a>) -> Improv<'a, T> {
+        Improv {
+            stream,
+            parser: ImprovParser::new(),
+            state: ImprovState::Authorized,
+            device_info,
+            redirect_url: None,
+        }
+    }
+
+    // Where the browser is sent once provisioned
+    pub fn with_redirect_url(mut self, redirect_url: &'a str) -> Self {
+        self.redirect_url = Some(redirect_url);
+        self
+    }
+
+    pub fn state(&self) -> ImprovState {
+        self.state
+    }
+
+    // Reads whatever is available and answers what it can, returning the first command
+    // that needs the network
+    pub fn poll(&mut self) -> Result<Option<ImprovCommand>, ImprovError<T::Error>> {
+        let mut byte = [0_u8; 1];
+        while self.stream.read(&mut byte).map_err(ImprovError::Transport)? > 0 {
+            match self.parser.push(byte[0]) {
+                None => {}
+                Some(Err(error)) => {
+                    warn!("Bad improv packet: {:?}", error);
+                    self.send_error(ImprovErrorCode::InvalidRpc)?;
+                }
+                Some(Ok(packet)) if packet.packet_type == TYPE_RPC_COMMAND => {
+                    if let Some(command) = self.handle_rpc(&packet.data)? {
+                        return Ok(Some(command));
+                    }
+                }
+                Some(Ok(packet)) => debug!("Ignoring improv packet of type {}", packet.packet_type),
+            }
+        }
+        Ok(None)
+    }
+
+    fn handle_rpc(&mut self, data: &[u8]) -> Result<Option<ImprovCommand>, ImprovError<T::Error>> {
+        let payload = match data {
+            [_, len, payload @ ..] if payload.len() == *len as usize => payload,
+            _ => {
+                self.send_error(ImprovErrorCode::InvalidRpc)?;
+                return Ok(None);
+            }
+        };
+        match data[0] {
+            COMMAND_WIFI_SETTINGS => {
+                let credentials = parse_string(payload)
+                    .and_then(|(ssid, rest)| Some((ssid, parse_string(rest)?.0)))
+                    .and_then(|(ssid, psk)| Credentials::new(ssid, psk).ok());
+                match credentials {
+                    Some(credentials) => {
+                        self.send_error(ImprovErrorCode::None)?;
+                        self.set_state(ImprovState::Provisioning)?;
+                        Ok(Some(ImprovCommand::WifiSettings(credentials)))
+                    }
+                    None => {
+                        self.send_error(ImprovErrorCode::InvalidRpc)?;
+                        Ok(None)
+                    }
+                }
+            }
+            // identify, the serial flavour answers with the current state
+            COMMAND_CURRENT_STATE => {
+                info!("Improv identify");
+                self.send_state()?;
+                if self.state == ImprovState::Provisioned {
+                    self.send_redirect_url()?;
+                }
+                Ok(None)
+            }
+            COMMAND_DEVICE_INFO => {
+                let info = self.device_info.clone();
+                self.send(&ImprovPacket::rpc_result(COMMAND_DEVICE_INFO, &[
+                    info.firmware_name.as_bytes(),
+                    info.firmware_version.as_bytes(),
+                    info.chip.as_bytes(),
+                    info.device_name.as_bytes(),
+                ]))?;
+                Ok(None)
+            }
+            COMMAND_WIFI_NETWORKS => Ok(Some(ImprovCommand::WifiNetworks)),
+            command => {
+                warn!("Unknown improv command: {}", command);
+                self.send_error(ImprovErrorCode::UnknownRpc)?;
+                Ok(None)
+            }
+        }
+    }
+
+    // One result per network and an empty one to end the list
+    pub fn send_networks(&mut self, networks: &[AccessPointInfo]) -> Result<(), ImprovError<T::Error>> {
+        for network in networks.iter().filter(|network| !network.ssid.is_empty()) {
+            let mut rssi: heapless::String<4> = heapless::String::new();
+            core::fmt::write(&mut rssi, format_args!("{}", network.signal_strength)).ok();
+            let secured: &[u8] = if network.auth_method == AuthMethod::None { b"NO" } else { b"YES" };
+            self.send(&ImprovPacket::rpc_result(COMMAND_WIFI_NETWORKS, &[
+                network.ssid.as_bytes(),
+                rssi.as_bytes(),
+                secured,
+            ]))?;
+        }
+        self.send(&ImprovPacket::rpc_result(COMMAND_WIFI_NETWORKS, &[]))
+    }
+
+    pub fn provisioned(&mut self) -> Result<(), ImprovError<T::Error>> {
+        self.set_state(ImprovState::Provisioned)?;
+        self.send_redirect_url()
+    }
+
+    pub fn provisioning_failed(&mut self) -> Result<(), ImprovError<T::Error>> {
+        self.send_error(ImprovErrorCode::UnableToConnect)?;
+        self.set_state(ImprovState::Authorized)
+    }
+
+    fn set_state(&mut self, state: ImprovState) -> Result<(), ImprovError<T::Error>> {
+        self.state = state;
+        self.send_state()
+    }
+
+    fn send_state(&mut self) -> Result<(), ImprovError<T::Error>> {
+        self.send(&ImprovPacket::new(TYPE_CURRENT_STATE, &[self.state as u8]))
+    }
+
+    fn send_error(&mut self, error: ImprovErrorCode) -> Result<(), ImprovError<T::Error>> {
+        self.send(&ImprovPacket::new(TYPE_ERROR_STATE, &[error as u8]))
+    }
+
+    fn send_redirect_url(&mut self) -> Result<(), ImprovError<T::Error>> {
+        let packet = match self.redirect_url {
+            Some(url) => ImprovPacket::rpc_result(COMMAND_WIFI_SETTINGS, &[url.as_bytes()]),
+            None => ImprovPacket::rpc_result(COMMAND_WIFI_SETTINGS, &[]),
+        };
+        self.send(&packet)
+    }
+
+    fn send(&mut self, packet: &ImprovPacket) -> Result<(), ImprovError<T::Error>> {
+        self.stream.write_all(&packet.encode()).map_err(ImprovError::Transport)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mocks::mock_duplex::MockDuplex;
+    use crate::mocks::mock_improv::{improv, received, rpc};
+    use crate::mocks::mock_wifi::access_point;
+    use crate::provisioning::Credentials;
+    use crate::provisioning::improv::{ImprovCommand, ImprovPacket, ImprovParser, ParseError};
+    use crate::traits::byte_stream::ByteStream;
+
+    #[test]
+    fn parses_packets_between_log_output() {
+        let mut parser = ImprovParser::new();
+        let mut stream = b"I (123) boot: IMPRO".to_vec();
+        stream.extend(ImprovPacket::new(0x01, &[0x02]).encode());
+        stream.extend(b"\r\nlog line\r\n");
+        let packets: Vec<_> = stream.into_iter().filter_map(|byte| parser.push(byte)).collect();
+        assert_eq!(packets, [Ok(ImprovPacket::new(0x01, &[0x02]))]);
+        let mut corrupt = ImprovPacket::new(0x01, &[0x02]).encode();
+        *corrupt.last_mut().unwrap() ^= 0xff;
+        let packets: Vec<_> = corrupt.into_iter().filter_map(|byte| parser.push(byte)).collect();
+        assert_eq!(packets, [Err(ParseError::Checksum)]);
+    }
+
+    #[test]
+    fn answers_identify_and_device_info() {
+        let (device, mut host) = MockDuplex::pair();
+        let mut improv = improv(device);
+        host.write_all(&rpc(0x02, &[])).unwrap();
+        host.write_all(&rpc(0x03, &[])).unwrap();
+        host.write_all(&rpc(0x7f, &[])).unwrap();
+        assert_eq!(improv.poll().unwrap(), None);
+        assert_eq!(received(&mut host), [
+            ImprovPacket::new(0x01, &[0x02]),
+            ImprovPacket::new(0x04, b"\x03\x1b\x04burp\x050.1.0\x05ESP32\x09burp-test"),
+            ImprovPacket::new(0x02, &[0x02]),
+        ]);
+    }
+
+    #[test]
+    fn returns_network_commands_and_sends_results() {
+        let (device, mut host) = MockDuplex::pair();
+        let mut improv = improv(device);
+        host.write_all(&rpc(0x04, &[])).unwrap();
+        host.write_all(&rpc(0x01, &["home", "home_password"])).unwrap();
+        assert_eq!(improv.poll().unwrap(), Some(ImprovCommand::WifiNetworks));
+        improv.send_networks(&[access_point("home", 6)]).unwrap();
+        assert_eq!(received(&mut host), [
+            ImprovPacket::new(0x04, b"\x04\x0d\x04home\x03-60\x03YES"),
+            ImprovPacket::new(0x04, &[0x04, 0x00]),
+        ]);
+        assert_eq!(
+            improv.poll().unwrap(),
+            Some(ImprovCommand::WifiSettings(Credentials::new("home", "home_password").unwrap())),
+        );
+        host.write_all(&rpc(0x01, &["home", "short"])).unwrap();
+        assert_eq!(improv.poll().unwrap(), None);
+        assert_eq!(received(&mut host), [
+            ImprovPacket::new(0x02, &[0x00]),
+            ImprovPacket::new(0x01, &[0x03]),
+            ImprovPacket::new(0x02, &[0x01]),
+        ]);
+    }
+}
@@ -0,0 +1,271 @@
+use std::fmt::{Display, Formatter};
+use std::str::{from_utf8, Utf8Error};
+
+use embedded_svc::wifi::AuthMethod;
+use heapless::String;
+use thiserror::Error;
+
+use crate::config::Config;
+use crate::provisioning::{Credentials, CredentialsError};
+use crate::traits::read_write::ReadWrite;
+use crate::traits::storage::Storage;
+
+const PREFIX: &str = "WIFI:";
+const SPECIAL_CHARACTERS: [char; 5] = ['\\', ';', ',', '"', ':'];
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum WifiUriError {
+    #[error("URI does not start with {PREFIX}")]
+    MissingPrefix,
+    #[error("URI has no SSID")]
+    MissingSsid,
+    #[error("Field is not in the form key:value")]
+    BadField,
+    #[error("Unknown authentication type")]
+    UnknownType,
+    #[error("WEP key must be 5 or 13 characters or 10 or 26 hex digits")]
+    InvalidWepKey,
+    #[error("{0}")]
+    Credentials(#[from] CredentialsError),
+}
+
+// The WIFI: format used by QR codes, eg. WIFI:T:WPA;S:home;P:secret;H:false;;
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WifiUri {
+    pub credentials: Credentials,
+    // None when the URI leaves it to be detected, T:WPA covers every WPA version
+    pub auth_method: Option<AuthMethod>,
+    pub hidden: bool,
+}
+
+impl WifiUri {
+    pub fn parse(uri: &str) -> Result<WifiUri, WifiUriError> {
+        let fields = uri.strip_prefix(PREFIX).ok_or(WifiUriError::MissingPrefix)?;
+        let mut ssid = None;
+        let mut psk = std::string::String::new();
+        let mut auth_type = std::string::String::new();
+        let mut hidden = false;
+        for field in split_unescaped(fields).into_iter().filter(|field| !field.is_empty()) {
+            let (key, value) = field.split_once(':').ok_or(WifiUriError::BadField)?;
+            let value = unescape(value);
+            match key {
+                "S" => ssid = Some(value),
+                "P" => psk = value,
+                "T" => auth_type = value,
+                "H" => hidden = value.eq_ignore_ascii_case("true"),
+                // fields from later versions of the format, eg. R for transition disable
+                _ => {}
+            }
+        }
+        let ssid = ssid.ok_or(WifiUriError::MissingSsid)?;
+        let auth_method = match auth_type.as_str() {
+            "WPA" | "WPA2" => None,
+            "SAE" | "WPA3" => Some(AuthMethod::WPA3Personal),
+            "WEP" => Some(AuthMethod::WEP),
+            "nopass" | "" => Some(AuthMethod::None),
+            _ => return Err(WifiUriError::UnknownType),
+        };
+        let credentials = match auth_method {
+            Some(AuthMethod::None) => Credentials::new(&ssid, "")?,
+            Some(AuthMethod::WEP) => {
+                let valid = matches!(psk.len(), 5 | 13)
+                    || matches!(psk.len(), 10 | 26) && psk.bytes().all(|byte| byte.is_ascii_hexdigit());
+                if !valid {
+                    return Err(WifiUriError::InvalidWepKey);
+                }
+                Credentials {
+                    ssid: Credentials::new(&ssid, "")?.ssid,
+                    psk: String::from(psk.as_str()),
+                }
+            }
+            _ => Credentials::new(&ssid, &psk)?,
+        };
+        Ok(WifiUri {
+            credentials,
+            auth_method,
+            hidden,
+        })
+    }
+
+    pub fn from_config<S: Storage>(config: &Config<'_, S>) -> Result<WifiUri, Utf8Error> {
+        Ok(WifiUri {
+            credentials: Credentials {
+                ssid: String::from(from_utf8(config.ssid.get())?),
+                psk: String::from(from_utf8(config.psk.get())?),
+            },
+            auth_method: config.auth_method_override(),
+            hidden: config.is_hidden(),
+        })
+    }
+
+    pub fn apply<S: Storage>(&self, config: &mut Config<'_, S>) -> Result<(), S::Error> {
+        config.ssid.set(self.credentials.ssid.as_bytes());
+        config.psk.set(self.credentials.psk.as_bytes());
+        config.set_auth_method_override(self.auth_method);
+        config.set_hidden(self.hidden);
+        config.set_last_access_point(None);
+        config.write()
+    }
+
+    fn auth_type(&self) -> &'static str {
+        match self.auth_method {
+            Some(AuthMethod::None) => "nopass",
+            Some(AuthMethod::WEP) => "WEP",
+            Some(AuthMethod::WPA3Personal) => "SAE",
+            None if self.credentials.psk.is_empty() => "nopass",
+            _ => "WPA",
+        }
+    }
+}
+
+impl Display for WifiUri {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}T:{};S:{};", PREFIX, self.auth_type(), escape(&self.credentials.ssid))?;
+        if self.auth_type() != "nopass" {
+            write!(f, "P:{};", escape(&self.credentials.psk))?;
+        }
+        if self.hidden {
+            write!(f, "H:true;")?;
+        }
+        write!(f, ";")
+    }
+}
+
+fn split_unescaped(fields: &str) -> Vec<&str> {
+    let mut split = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    for (index, c) in fields.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            ';' => {
+                split.push(&fields[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    split.push(&fields[start..]);
+    split
+}
+
+fn unescape(value: &str) -> std::string::String {
+    // quotes mark a value that would otherwise be read as hex
+    let value = match value.strip_prefix('"').and_then(|value| value.strip_suffix('"')) {
+        Some(quoted) if !quoted.ends_with('\\') || quoted.ends_with("\\\\") => quoted,
+        _ => value,
+    };
+    let mut unescaped = std::string::String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.extend(chars.next()),
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+fn escape(value: &str) -> std::string::String {
+    let mut escaped = std::string::String::with_capacity(value.len());
+    let hex = !value.is_empty() && value.bytes().all(|byte| byte.is_ascii_hexdigit());
+    if hex {
+        escaped.push('"');
+    }
+    for c in value.chars() {
+        if SPECIAL_CHARACTERS.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    if hex {
+        escaped.push('"');
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use embedded_svc::wifi::AuthMethod;
+
+    use crate::config::Config;
+    use crate::mocks::mock_esp_nvs::MockEspNvs;
+    use crate::provisioning::{Credentials, CredentialsError};
+    use crate::provisioning::wifi_uri::{WifiUri, WifiUriError};
+    use crate::traits::read_write::ReadWrite;
+
+    #[test]
+    fn parses_uris() {
+        assert_eq!(WifiUri::parse("WIFI:T:WPA;S:home;P:home_password;H:false;;"), Ok(WifiUri {
+            credentials: Credentials::new("home", "home_password").unwrap(),
+            auth_method: None,
+            hidden: false,
+        }));
+        assert_eq!(WifiUri::parse(r#"WIFI:S:my\;net\:1;T:SAE;P:"pa\\ss\"word";H:true;;"#), Ok(WifiUri {
+            credentials: Credentials::new("my;net:1", "pa\\ss\"word").unwrap(),
+            auth_method: Some(AuthMethod::WPA3Personal),
+            hidden: true,
+        }));
+        assert_eq!(WifiUri::parse(r#"WIFI:T:nopass;S:"abcdef";;"#).unwrap().credentials.ssid, "abcdef");
+        assert_eq!(WifiUri::parse("WIFI:T:WEP;S:old;P:12345;;").unwrap().credentials.psk, "12345");
+    }
+
+    #[test]
+    fn rejects_invalid_uris() {
+        assert_eq!(WifiUri::parse("T:WPA;S:home;;"), Err(WifiUriError::MissingPrefix));
+        assert_eq!(WifiUri::parse("WIFI:T:WPA;P:home_password;;"), Err(WifiUriError::MissingSsid));
+        assert_eq!(WifiUri::parse("WIFI:T:WPA;S:home;bad;;"), Err(WifiUriError::BadField));
+        assert_eq!(WifiUri::parse("WIFI:T:WPA2-EAP;S:home;;"), Err(WifiUriError::UnknownType));
+        assert_eq!(WifiUri::parse("WIFI:T:WEP;S:old;P:1234;;"), Err(WifiUriError::InvalidWepKey));
+        assert_eq!(
+            WifiUri::parse("WIFI:T:WPA;S:home;P:short;;"),
+            Err(WifiUriError::Credentials(CredentialsError::InvalidPsk)),
+        );
+    }
+
+    #[test]
+    fn generates_uris_that_parse_back() {
+        for uri in [
+            WifiUri {
+                credentials: Credentials::new("my;net:\"1\"", "pa\\ss,word").unwrap(),
+                auth_method: None,
+                hidden: true,
+            },
+            WifiUri {
+                credentials: Credentials::new("home", "home_password").unwrap(),
+                auth_method: Some(AuthMethod::WPA3Personal),
+                hidden: false,
+            },
+            WifiUri {
+                credentials: Credentials::new("cafe", "").unwrap(),
+                auth_method: Some(AuthMethod::None),
+                hidden: false,
+            },
+        ] {
+            assert_eq!(WifiUri::parse(&uri.to_string()), Ok(uri));
+        }
+        let uri = WifiUri {
+            credentials: Credentials::new("home", "home_password").unwrap(),
+            auth_method: Some(AuthMethod::WPA2Personal),
+            hidden: false,
+        };
+        assert_eq!(uri.to_string(), "WIFI:T:WPA;S:home;P:home_password;;");
+    }
+
+    #[test]
+    fn maps_onto_config() {
+        let storage = Arc::new(Mutex::new(MockEspNvs::from([])));
+        let mut config = Config::new(storage.clone(), "", "");
+        config.read().unwrap();
+        let uri = WifiUri::parse("WIFI:T:WEP;S:old;P:12345;H:true;;").unwrap();
+        uri.apply(&mut config).unwrap();
+        let mut config = Config::new(storage, "", "");
+        config.read().unwrap();
+        assert!(config.is_hidden());
+        assert_eq!(config.auth_method_override(), Some(AuthMethod::WEP));
+        assert_eq!(WifiUri::from_config(&config), Ok(uri));
+    }
+}
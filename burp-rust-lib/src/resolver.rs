@@ -0,0 +1,74 @@
+use std::io;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::futures::{completion, sleep, timeout};
+use crate::workers;
+
+// A host or address with an optional port, IPv6 addresses take brackets when they have one.
+// None when the port is not a number.
+pub fn split_host_port(target: &str) -> Option<(&str, Option<u16>)> {
+    if let Some(rest) = target.strip_prefix('[') {
+        let (host, rest) = rest.split_once(']')?;
+        return match rest.strip_prefix(':') {
+            Some(port) => Some((host, Some(port.parse().ok()?))),
+            None if rest.is_empty() => Some((host, None)),
+            None => None,
+        };
+    }
+    if target.parse::<Ipv6Addr>().is_ok() {
+        return Some((target, None));
+    }
+    match target.rsplit_once(':') {
+        Some((host, port)) => Some((host, Some(port.parse().ok()?))),
+        None => Some((target, None)),
+    }
+}
+
+// Addresses are returned as they are. Names go through the system resolver, which blocks, so
+// the lookup runs on the resolver worker and is left to finish there if it takes too long.
+// Lookups given up on before the worker gets to them are skipped rather than piling up.
+pub async fn resolve(host: &str, port: u16, duration: Duration) -> io::Result<Vec<SocketAddr>> {
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return Ok(vec![SocketAddr::new(ip, port)]);
+    }
+    let host = host.to_string();
+    let (completer, lookup) = completion();
+    workers::resolver().submit(move || {
+        if !completer.is_abandoned() {
+            completer.complete((host.as_str(), port).to_socket_addrs().map(|addresses| addresses.collect()));
+        }
+    });
+    timeout(lookup, sleep(duration)).await
+        .unwrap_or_else(|| Err(io::Error::new(io::ErrorKind::TimedOut, "lookup timed out")))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    use crate::mocks::block_on::block_on;
+    use crate::resolver::{resolve, split_host_port};
+
+    #[test]
+    fn splits_hosts_from_ports() {
+        assert_eq!(split_host_port("pool.ntp.org"), Some(("pool.ntp.org", None)));
+        assert_eq!(split_host_port("pool.ntp.org:123"), Some(("pool.ntp.org", Some(123))));
+        assert_eq!(split_host_port("192.168.1.1:53"), Some(("192.168.1.1", Some(53))));
+        assert_eq!(split_host_port("fe80::1"), Some(("fe80::1", None)));
+        assert_eq!(split_host_port("::1"), Some(("::1", None)));
+        assert_eq!(split_host_port("[::1]"), Some(("::1", None)));
+        assert_eq!(split_host_port("[::1]:123"), Some(("::1", Some(123))));
+        assert_eq!(split_host_port("host:port"), None);
+        assert_eq!(split_host_port("[::1]123"), None);
+    }
+
+    #[test]
+    fn resolves_without_blocking() {
+        let address: SocketAddr = "[::1]:123".parse().unwrap();
+        assert_eq!(block_on(resolve("::1", 123, Duration::ZERO)).unwrap(), vec![address]);
+        let addresses = block_on(resolve("localhost", 123, Duration::from_secs(5))).unwrap();
+        assert!(addresses.iter().all(|address| address.ip().is_loopback() && address.port() == 123));
+    }
+}
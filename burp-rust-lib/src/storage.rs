@@ -0,0 +1,29 @@
+use log::*;
+
+use crate::traits::storage::{Storage, StorageStats};
+
+pub mod cached_storage;
+pub mod async_storage_adaptor;
+pub mod write_counting_storage;
+
+const LOW_FREE_ENTRIES_PERCENT: u8 = 20;
+
+pub fn log_storage_stats<S: Storage>(storage: &S) -> Result<StorageStats, S::Error> {
+    let stats = storage.stats()?;
+    info!(
+        "Storage: {} of {} entries used ({}%), {} free, {} namespaces, {} entries in this namespace",
+        stats.used_entries,
+        stats.total_entries,
+        stats.used_percent(),
+        stats.free_entries,
+        stats.namespace_count,
+        stats.namespace_used_entries,
+    );
+    for namespace in &stats.namespaces {
+        info!("Storage namespace {}: {} entries used", namespace.name, namespace.used_entries);
+    }
+    if stats.used_percent() >= 100 - LOW_FREE_ENTRIES_PERCENT {
+        warn!("Storage is running out of free entries, only {} left", stats.free_entries);
+    }
+    Ok(stats)
+}
@@ -0,0 +1,148 @@
+use std::cell::RefCell;
+use std::sync::{Arc, Mutex};
+
+use crate::futures::Completion;
+use crate::traits::async_storage::AsyncStorage;
+use crate::traits::storage::{Storage, StorageStats};
+use crate::workers;
+
+type PendingWrite<S> = Completion<Result<(), <S as Storage>::Error>>;
+
+// Wraps a blocking Storage so that flash access and waiting for the shared lock happen on the
+// storage worker while the executor runs other tasks. Operations run one at a time in order.
+// Writes are handed over without waiting for them, commit waits and reports the first that failed.
+pub struct AsyncStorageAdaptor<S: Storage> {
+    storage: Arc<Mutex<S>>,
+    writing: RefCell<Vec<PendingWrite<S>>>,
+}
+
+impl<S: Storage> AsyncStorageAdaptor<S> {
+    pub fn new(storage: Arc<Mutex<S>>) -> AsyncStorageAdaptor<S> {
+        AsyncStorageAdaptor {
+            storage,
+            writing: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn run<T: Send + 'static>(&self, operation: impl FnOnce(&mut S) -> T + Send + 'static) -> Completion<T> {
+        let storage = self.storage.clone();
+        workers::storage().run(move || operation(&mut storage.lock().unwrap()))
+    }
+
+    async fn start_write(&self, write: impl FnOnce(&mut S) -> Result<(), S::Error> + Send + 'static) -> Result<(), S::Error> {
+        let write = self.run(write);
+        self.writing.borrow_mut().push(write);
+        Ok(())
+    }
+}
+
+macro_rules! async_get_set {
+    ($get:ident, $set:ident, $type:ty) => {
+        async fn $get(&self, name: &str) -> Result<Option<$type>, Self::Error> {
+            let name = String::from(name);
+            self.run(move |storage| storage.$get(&name)).await
+        }
+
+        async fn $set(&self, name: &str, val: $type) -> Result<(), Self::Error> {
+            let name = String::from(name);
+            self.start_write(move |storage| storage.$set(&name, val)).await
+        }
+    };
+}
+
+impl<S: Storage> AsyncStorage for AsyncStorageAdaptor<S> {
+    type Error = S::Error;
+
+    async fn stats(&self) -> Result<StorageStats, Self::Error> {
+        self.run(|storage| storage.stats()).await
+    }
+
+    async fn get_blob<'a>(&self, name: &str, buf: &'a mut [u8]) -> Result<Option<&'a [u8]>, Self::Error> {
+        let name = String::from(name);
+        let mut blob = vec![0_u8; buf.len()];
+        let (blob, len) = self.run(move |storage| {
+            let len = storage.get_blob(&name, &mut blob).map(|found| found.map(|found| found.len()));
+            (blob, len)
+        }).await;
+        Ok(len?.map(|len| {
+            buf[..len].copy_from_slice(&blob[..len]);
+            &buf[..len]
+        }))
+    }
+
+    async fn set_blob(&mut self, name: &str, val: &[u8]) -> Result<(), Self::Error> {
+        let (name, val) = (String::from(name), val.to_vec());
+        self.start_write(move |storage| storage.set_blob(&name, &val)).await
+    }
+
+    async fn remove(&mut self, name: &str) -> Result<bool, Self::Error> {
+        let name = String::from(name);
+        self.run(move |storage| storage.remove(&name)).await
+    }
+
+    async_get_set!(get_u8, set_u8, u8);
+    async_get_set!(get_i8, set_i8, i8);
+    async_get_set!(get_u16, set_u16, u16);
+    async_get_set!(get_i16, set_i16, i16);
+    async_get_set!(get_u32, set_u32, u32);
+    async_get_set!(get_i32, set_i32, i32);
+    async_get_set!(get_u64, set_u64, u64);
+    async_get_set!(get_i64, set_i64, i64);
+
+    async fn commit(&mut self) -> Result<(), Self::Error> {
+        let writing = self.writing.take();
+        let mut result = Ok(());
+        for write in writing {
+            if let Err(error) = write.await {
+                result = result.and(Err(error));
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+    use std::pin::pin;
+    use std::sync::{Arc, Mutex};
+    use std::task::Context;
+
+    use crate::mocks::block_on::{block_on, noop_waker};
+    use crate::mocks::faulty_storage::{FaultyStorage, FaultyStorageError};
+    use crate::mocks::mock_esp_nvs::{MockEspNvs, MockEspNvsValue};
+    use crate::storage::async_storage_adaptor::AsyncStorageAdaptor;
+    use crate::traits::async_storage::AsyncStorage;
+    use crate::traits::storage::Storage;
+
+    #[test]
+    fn yields_while_storage_is_locked() {
+        let storage = Arc::new(Mutex::new(MockEspNvs::from([
+            (String::from("count"), MockEspNvsValue::U32Value(7)),
+        ])));
+        let adaptor = AsyncStorageAdaptor::new(storage.clone());
+        let waker = noop_waker();
+        let mut context = Context::from_waker(&waker);
+        let guard = storage.lock().unwrap();
+        let mut future = pin!(adaptor.get_u32("count"));
+        assert!(future.as_mut().poll(&mut context).is_pending());
+        assert!(future.as_mut().poll(&mut context).is_pending());
+        drop(guard);
+        assert!(matches!(block_on(future), Ok(Some(7))));
+    }
+
+    #[test]
+    fn commit_reports_failed_writes() {
+        let faulty = FaultyStorage::new(MockEspNvs::from([]));
+        faulty.fail_key("broken");
+        let storage = Arc::new(Mutex::new(faulty));
+        let mut adaptor = AsyncStorageAdaptor::new(storage.clone());
+        block_on(adaptor.set_u8("broken", 1)).unwrap();
+        block_on(adaptor.set_blob("name", b"value")).unwrap();
+        let mut buf = [0_u8; 8];
+        assert_eq!(block_on(adaptor.get_blob("name", &mut buf)).unwrap(), Some(&b"value"[..]));
+        assert!(matches!(block_on(adaptor.commit()), Err(FaultyStorageError::InjectedKey(_))));
+        assert!(block_on(adaptor.commit()).is_ok());
+        assert_eq!(storage.lock().unwrap().inner().get_u8("broken").unwrap(), None);
+    }
+}
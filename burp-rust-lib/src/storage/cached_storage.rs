@@ -0,0 +1,368 @@
+use std::cell::{Cell, RefCell};
+
+use heapless::String;
+use log::*;
+
+use crate::traits::storage::{Storage, StorageStats};
+
+const MAX_KEY_BYTES: usize = 15;
+
+enum CachedValue {
+    Blob(Vec<u8>),
+    U8(u8),
+    I8(i8),
+    U16(u16),
+    I16(i16),
+    U32(u32),
+    I32(i32),
+    U64(u64),
+    I64(i64),
+}
+
+trait Cached: Copy {
+    fn to_cached(self) -> CachedValue;
+    fn from_cached(value: &CachedValue) -> Option<Self>;
+}
+
+macro_rules! impl_cached {
+    ($type:ty, $variant:ident) => {
+        impl Cached for $type {
+            fn to_cached(self) -> CachedValue {
+                CachedValue::$variant(self)
+            }
+
+            fn from_cached(value: &CachedValue) -> Option<Self> {
+                match value {
+                    CachedValue::$variant(value) => Some(*value),
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+impl_cached!(u8, U8);
+impl_cached!(i8, I8);
+impl_cached!(u16, U16);
+impl_cached!(i16, I16);
+impl_cached!(u32, U32);
+impl_cached!(i32, I32);
+impl_cached!(u64, U64);
+impl_cached!(i64, I64);
+
+struct CacheEntry {
+    name: String<MAX_KEY_BYTES>,
+    // None caches the absence of a key
+    value: Option<CachedValue>,
+}
+
+// Entries are kept least recently used first and the oldest is evicted once N entries are cached
+pub struct CachedStorage<S, const N: usize> {
+    storage: S,
+    entries: RefCell<heapless::Vec<CacheEntry, N>>,
+    hits: Cell<u32>,
+    misses: Cell<u32>,
+}
+
+impl<S: Storage, const N: usize> CachedStorage<S, N> {
+    pub fn new(storage: S) -> CachedStorage<S, N> {
+        CachedStorage {
+            storage,
+            entries: RefCell::new(heapless::Vec::new()),
+            hits: Cell::new(0),
+            misses: Cell::new(0),
+        }
+    }
+
+    pub fn hits(&self) -> u32 {
+        self.hits.get()
+    }
+
+    pub fn misses(&self) -> u32 {
+        self.misses.get()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.borrow().is_empty()
+    }
+
+    pub fn clear(&self) {
+        self.entries.borrow_mut().clear();
+    }
+
+    pub fn log_stats(&self) {
+        info!(
+            "Storage cache: {} hits, {} misses, {}/{} entries",
+            self.hits(),
+            self.misses(),
+            self.len(),
+            N,
+        );
+    }
+
+    pub fn into_inner(self) -> S {
+        self.storage
+    }
+
+    // Some(value) on a hit, None on a miss
+    fn lookup<T>(&self, name: &str, extract: impl FnOnce(&Option<CachedValue>) -> Option<T>) -> Option<T> {
+        let mut entries = self.entries.borrow_mut();
+        let found = entries.iter().position(|entry| entry.name == name).and_then(|index| {
+            let entry = entries.remove(index);
+            let extracted = extract(&entry.value);
+            entries.push(entry).ok();
+            extracted
+        });
+        match found {
+            Some(_) => self.hits.set(self.hits.get().wrapping_add(1)),
+            None => self.misses.set(self.misses.get().wrapping_add(1)),
+        }
+        found
+    }
+
+    fn insert(&self, name: &str, value: Option<CachedValue>) {
+        self.invalidate(name);
+        // keys too long for NVS are never cached, the storage will reject them anyway
+        if name.len() <= MAX_KEY_BYTES {
+            let mut entries = self.entries.borrow_mut();
+            if entries.is_full() {
+                entries.remove(0);
+            }
+            entries.push(CacheEntry { name: String::from(name), value }).ok();
+        }
+    }
+
+    fn invalidate(&self, name: &str) {
+        self.entries.borrow_mut().retain(|entry| entry.name != name);
+    }
+
+    fn get<T: Cached>(
+        &self,
+        name: &str,
+        get: impl FnOnce(&S, &str) -> Result<Option<T>, S::Error>,
+    ) -> Result<Option<T>, S::Error> {
+        let hit = self.lookup(name, |value| match value {
+            None => Some(None),
+            Some(value) => T::from_cached(value).map(Some),
+        });
+        match hit {
+            Some(value) => Ok(value),
+            None => {
+                let value = get(&self.storage, name)?;
+                self.insert(name, value.map(T::to_cached));
+                Ok(value)
+            }
+        }
+    }
+
+    fn set<T: Cached>(
+        &self,
+        name: &str,
+        val: T,
+        set: impl FnOnce(&S, &str, T) -> Result<(), S::Error>,
+    ) -> Result<(), S::Error> {
+        match set(&self.storage, name, val) {
+            Ok(()) => {
+                self.insert(name, Some(val.to_cached()));
+                Ok(())
+            }
+            Err(error) => {
+                self.invalidate(name);
+                Err(error)
+            }
+        }
+    }
+}
+
+impl<S: Storage, const N: usize> Storage for CachedStorage<S, N> {
+    type Error = S::Error;
+
+    fn stats(&self) -> Result<StorageStats, Self::Error> {
+        self.storage.stats()
+    }
+
+    fn get_blob<'a>(&self, name: &str, buf: &'a mut [u8]) -> Result<Option<&'a [u8]>, Self::Error> {
+        let len = buf.len();
+        let hit = self.lookup(name, |value| match value {
+            None => Some(None),
+            Some(CachedValue::Blob(blob)) if blob.len() <= len => Some(Some(blob.clone())),
+            Some(_) => None,
+        });
+        match hit {
+            Some(None) => Ok(None),
+            Some(Some(blob)) => {
+                buf[..blob.len()].copy_from_slice(&blob);
+                Ok(Some(&buf[..blob.len()]))
+            }
+            None => {
+                let blob = self.storage.get_blob(name, buf)?;
+                self.insert(name, blob.map(|blob| CachedValue::Blob(Vec::from(blob))));
+                Ok(blob)
+            }
+        }
+    }
+
+    fn set_blob(&mut self, name: &str, val: &[u8]) -> Result<(), Self::Error> {
+        match self.storage.set_blob(name, val) {
+            Ok(()) => {
+                self.insert(name, Some(CachedValue::Blob(Vec::from(val))));
+                Ok(())
+            }
+            Err(error) => {
+                self.invalidate(name);
+                Err(error)
+            }
+        }
+    }
+
+    fn remove(&mut self, name: &str) -> Result<bool, Self::Error> {
+        self.invalidate(name);
+        self.storage.remove(name)
+    }
+
+    fn get_u8(&self, name: &str) -> Result<Option<u8>, Self::Error> {
+        self.get(name, S::get_u8)
+    }
+
+    fn set_u8(&self, name: &str, val: u8) -> Result<(), Self::Error> {
+        self.set(name, val, S::set_u8)
+    }
+
+    fn get_i8(&self, name: &str) -> Result<Option<i8>, Self::Error> {
+        self.get(name, S::get_i8)
+    }
+
+    fn set_i8(&self, name: &str, val: i8) -> Result<(), Self::Error> {
+        self.set(name, val, S::set_i8)
+    }
+
+    fn get_u16(&self, name: &str) -> Result<Option<u16>, Self::Error> {
+        self.get(name, S::get_u16)
+    }
+
+    fn set_u16(&self, name: &str, val: u16) -> Result<(), Self::Error> {
+        self.set(name, val, S::set_u16)
+    }
+
+    fn get_i16(&self, name: &str) -> Result<Option<i16>, Self::Error> {
+        self.get(name, S::get_i16)
+    }
+
+    fn set_i16(&self, name: &str, val: i16) -> Result<(), Self::Error> {
+        self.set(name, val, S::set_i16)
+    }
+
+    fn get_u32(&self, name: &str) -> Result<Option<u32>, Self::Error> {
+        self.get(name, S::get_u32)
+    }
+
+    fn set_u32(&self, name: &str, val: u32) -> Result<(), Self::Error> {
+        self.set(name, val, S::set_u32)
+    }
+
+    fn get_i32(&self, name: &str) -> Result<Option<i32>, Self::Error> {
+        self.get(name, S::get_i32)
+    }
+
+    fn set_i32(&self, name: &str, val: i32) -> Result<(), Self::Error> {
+        self.set(name, val, S::set_i32)
+    }
+
+    fn get_u64(&self, name: &str) -> Result<Option<u64>, Self::Error> {
+        self.get(name, S::get_u64)
+    }
+
+    fn set_u64(&self, name: &str, val: u64) -> Result<(), Self::Error> {
+        self.set(name, val, S::set_u64)
+    }
+
+    fn get_i64(&self, name: &str) -> Result<Option<i64>, Self::Error> {
+        self.get(name, S::get_i64)
+    }
+
+    fn set_i64(&self, name: &str, val: i64) -> Result<(), Self::Error> {
+        self.set(name, val, S::set_i64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mocks::faulty_storage::FaultyStorage;
+    use crate::mocks::mock_esp_nvs::{MockEspNvs, MockEspNvsValue};
+    use crate::storage::cached_storage::CachedStorage;
+    use crate::traits::storage::Storage;
+
+    fn cached_storage<const N: usize>() -> CachedStorage<FaultyStorage<MockEspNvs>, N> {
+        CachedStorage::new(FaultyStorage::new(MockEspNvs::from([
+            (String::from("ssid"), MockEspNvsValue::BlobValue(Vec::from("stored_ssid"))),
+            (String::from("count"), MockEspNvsValue::U32Value(7)),
+        ])))
+    }
+
+    #[test]
+    fn repeated_reads_are_served_from_cache() {
+        let storage: CachedStorage<_, 4> = cached_storage();
+        let mut buffer = [0_u8; 32];
+        assert_eq!(storage.get_blob("ssid", &mut buffer).unwrap().unwrap(), "stored_ssid".as_bytes());
+        assert_eq!(storage.get_blob("ssid", &mut buffer).unwrap().unwrap(), "stored_ssid".as_bytes());
+        assert_eq!(storage.get_u32("count").unwrap(), Some(7));
+        assert_eq!(storage.get_u32("count").unwrap(), Some(7));
+        assert_eq!(storage.get_u8("missing").unwrap(), None);
+        assert_eq!(storage.get_u8("missing").unwrap(), None);
+        assert_eq!(storage.hits(), 3);
+        assert_eq!(storage.misses(), 3);
+        assert_eq!(storage.into_inner().calls(), 3);
+    }
+
+    #[test]
+    fn writes_go_through_to_storage_and_cache() {
+        let mut storage: CachedStorage<_, 4> = cached_storage();
+        storage.set_blob("ssid", "new_ssid".as_bytes()).unwrap();
+        storage.set_u32("count", 8).unwrap();
+        let mut buffer = [0_u8; 32];
+        assert_eq!(storage.get_blob("ssid", &mut buffer).unwrap().unwrap(), "new_ssid".as_bytes());
+        assert_eq!(storage.get_u32("count").unwrap(), Some(8));
+        assert_eq!(storage.hits(), 2);
+        let inner = storage.into_inner();
+        assert_eq!(inner.inner().get_blob("ssid", &mut buffer).unwrap().unwrap(), "new_ssid".as_bytes());
+        assert_eq!(inner.inner().get_u32("count").unwrap(), Some(8));
+    }
+
+    #[test]
+    fn failed_writes_invalidate_entries() {
+        let storage: CachedStorage<_, 4> = cached_storage();
+        assert_eq!(storage.get_u32("count").unwrap(), Some(7));
+        storage.storage.set_full(true);
+        assert!(storage.set_u32("count", 8).is_err());
+        assert_eq!(storage.get_u32("count").unwrap(), Some(7));
+        assert_eq!(storage.misses(), 2);
+    }
+
+    #[test]
+    fn remove_invalidates_entries() {
+        let mut storage: CachedStorage<_, 4> = cached_storage();
+        assert_eq!(storage.get_u32("count").unwrap(), Some(7));
+        assert!(storage.remove("count").unwrap());
+        assert_eq!(storage.get_u32("count").unwrap(), None);
+        assert_eq!(storage.misses(), 2);
+    }
+
+    #[test]
+    fn least_recently_used_entries_are_evicted() {
+        let storage: CachedStorage<_, 2> = cached_storage();
+        let mut buffer = [0_u8; 32];
+        storage.get_blob("ssid", &mut buffer).unwrap();
+        storage.get_u32("count").unwrap();
+        storage.get_blob("ssid", &mut buffer).unwrap();
+        storage.get_u8("missing").unwrap();
+        assert_eq!(storage.len(), 2);
+        storage.get_blob("ssid", &mut buffer).unwrap();
+        storage.get_u32("count").unwrap();
+        assert_eq!(storage.hits(), 2);
+        assert_eq!(storage.misses(), 4);
+    }
+}
@@ -0,0 +1,223 @@
+use std::cell::{Cell, RefCell};
+use std::cmp::Reverse;
+
+use heapless::String;
+use log::*;
+
+use crate::traits::storage::{Storage, StorageStats};
+
+const MAX_KEY_BYTES: usize = 15;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WriteCount {
+    pub name: String<MAX_KEY_BYTES>,
+    pub writes: u32,
+    // removing a key also writes to flash but is counted on its own
+    pub removes: u32,
+}
+
+// Counts successful writes and removes per key so that config fields rewritten too often can be spotted.
+// Only the first N keys written are tracked, writes to further keys are counted as untracked.
+pub struct WriteCountingStorage<S, const N: usize> {
+    storage: S,
+    counts: RefCell<heapless::Vec<WriteCount, N>>,
+    untracked_writes: Cell<u32>,
+}
+
+impl<S: Storage, const N: usize> WriteCountingStorage<S, N> {
+    pub fn new(storage: S) -> WriteCountingStorage<S, N> {
+        WriteCountingStorage {
+            storage,
+            counts: RefCell::new(heapless::Vec::new()),
+            untracked_writes: Cell::new(0),
+        }
+    }
+
+    pub fn write_count(&self, name: &str) -> u32 {
+        self.counts.borrow().iter().find(|count| count.name == name).map_or(0, |count| count.writes)
+    }
+
+    pub fn remove_count(&self, name: &str) -> u32 {
+        self.counts.borrow().iter().find(|count| count.name == name).map_or(0, |count| count.removes)
+    }
+
+    // sorted by most writes first
+    pub fn write_counts(&self) -> heapless::Vec<WriteCount, N> {
+        let mut counts = self.counts.borrow().clone();
+        counts.sort_unstable_by_key(|count| Reverse(count.writes));
+        counts
+    }
+
+    pub fn untracked_writes(&self) -> u32 {
+        self.untracked_writes.get()
+    }
+
+    pub fn reset_write_counts(&self) {
+        self.counts.borrow_mut().clear();
+        self.untracked_writes.set(0);
+    }
+
+    pub fn log_write_counts(&self) {
+        for count in self.write_counts() {
+            info!("Storage key {} written {} times, removed {} times", count.name, count.writes, count.removes);
+        }
+        let untracked_writes = self.untracked_writes();
+        if untracked_writes > 0 {
+            info!("Storage untracked writes: {}", untracked_writes);
+        }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.storage
+    }
+
+    fn count<T>(&self, name: &str, result: Result<T, S::Error>) -> Result<T, S::Error> {
+        self.count_as(name, result, |count| &mut count.writes)
+    }
+
+    fn count_as<T>(
+        &self,
+        name: &str,
+        result: Result<T, S::Error>,
+        counter: impl Fn(&mut WriteCount) -> &mut u32,
+    ) -> Result<T, S::Error> {
+        if result.is_ok() {
+            let mut counts = self.counts.borrow_mut();
+            match counts.iter_mut().find(|count| count.name == name) {
+                Some(count) => {
+                    let counter = counter(count);
+                    *counter = counter.saturating_add(1);
+                }
+                None => {
+                    let tracked = name.len() <= MAX_KEY_BYTES && {
+                        let mut count = WriteCount {
+                            name: String::from(name),
+                            writes: 0,
+                            removes: 0,
+                        };
+                        *counter(&mut count) = 1;
+                        counts.push(count).is_ok()
+                    };
+                    if !tracked {
+                        self.untracked_writes.set(self.untracked_writes.get().saturating_add(1));
+                    }
+                }
+            }
+        }
+        result
+    }
+}
+
+impl<S: Storage, const N: usize> Storage for WriteCountingStorage<S, N> {
+    type Error = S::Error;
+
+    fn stats(&self) -> Result<StorageStats, Self::Error> {
+        self.storage.stats()
+    }
+
+    fn get_blob<'a>(&self, name: &str, buf: &'a mut [u8]) -> Result<Option<&'a [u8]>, Self::Error> {
+        self.storage.get_blob(name, buf)
+    }
+
+    fn set_blob(&mut self, name: &str, val: &[u8]) -> Result<(), Self::Error> {
+        let result = self.storage.set_blob(name, val);
+        self.count(name, result)
+    }
+
+    fn remove(&mut self, name: &str) -> Result<bool, Self::Error> {
+        let result = self.storage.remove(name);
+        self.count_as(name, result, |count| &mut count.removes)
+    }
+
+    fn get_u8(&self, name: &str) -> Result<Option<u8>, Self::Error> {
+        self.storage.get_u8(name)
+    }
+
+    fn set_u8(&self, name: &str, val: u8) -> Result<(), Self::Error> {
+        self.count(name, self.storage.set_u8(name, val))
+    }
+
+    fn get_i8(&self, name: &str) -> Result<Option<i8>, Self::Error> {
+        self.storage.get_i8(name)
+    }
+
+    fn set_i8(&self, name: &str, val: i8) -> Result<(), Self::Error> {
+        self.count(name, self.storage.set_i8(name, val))
+    }
+
+    fn get_u16(&self, name: &str) -> Result<Option<u16>, Self::Error> {
+        self.storage.get_u16(name)
+    }
+
+    fn set_u16(&self, name: &str, val: u16) -> Result<(), Self::Error> {
+        self.count(name, self.storage.set_u16(name, val))
+    }
+
+    fn get_i16(&self, name: &str) -> Result<Option<i16>, Self::Error> {
+        self.storage.get_i16(name)
+    }
+
+    fn set_i16(&self, name: &str, val: i16) -> Result<(), Self::Error> {
+        self.count(name, self.storage.set_i16(name, val))
+    }
+
+    fn get_u32(&self, name: &str) -> Result<Option<u32>, Self::Error> {
+        self.storage.get_u32(name)
+    }
+
+    fn set_u32(&self, name: &str, val: u32) -> Result<(), Self::Error> {
+        self.count(name, self.storage.set_u32(name, val))
+    }
+
+    fn get_i32(&self, name: &str) -> Result<Option<i32>, Self::Error> {
+        self.storage.get_i32(name)
+    }
+
+    fn set_i32(&self, name: &str, val: i32) -> Result<(), Self::Error> {
+        self.count(name, self.storage.set_i32(name, val))
+    }
+
+    fn get_u64(&self, name: &str) -> Result<Option<u64>, Self::Error> {
+        self.storage.get_u64(name)
+    }
+
+    fn set_u64(&self, name: &str, val: u64) -> Result<(), Self::Error> {
+        self.count(name, self.storage.set_u64(name, val))
+    }
+
+    fn get_i64(&self, name: &str) -> Result<Option<i64>, Self::Error> {
+        self.storage.get_i64(name)
+    }
+
+    fn set_i64(&self, name: &str, val: i64) -> Result<(), Self::Error> {
+        self.count(name, self.storage.set_i64(name, val))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mocks::faulty_storage::FaultyStorage;
+    use crate::mocks::mock_esp_nvs::MockEspNvs;
+    use crate::storage::write_counting_storage::WriteCountingStorage;
+    use crate::traits::storage::Storage;
+
+    #[test]
+    fn counts_successful_writes_per_key() {
+        let mut storage: WriteCountingStorage<_, 2> = WriteCountingStorage::new(FaultyStorage::new(MockEspNvs::from([])));
+        storage.set_blob("ssid", "one".as_bytes()).unwrap();
+        storage.set_u8("count", 1).unwrap();
+        storage.set_u8("count", 2).unwrap();
+        storage.set_u8("other", 1).unwrap();
+        storage.remove("count").unwrap();
+        storage.storage.set_full(true);
+        assert!(storage.set_blob("ssid", "two".as_bytes()).is_err());
+        assert_eq!(storage.write_count("ssid"), 1);
+        assert_eq!(storage.write_count("count"), 2);
+        assert_eq!(storage.remove_count("count"), 1);
+        assert_eq!(storage.write_count("other"), 0);
+        assert_eq!(storage.untracked_writes(), 1);
+        let counts = storage.write_counts();
+        assert_eq!(counts[0].name, "count");
+        assert_eq!(counts[1].name, "ssid");
+    }
+}
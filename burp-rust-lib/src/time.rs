@@ -0,0 +1,259 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use heapless::String;
+use log::*;
+
+use crate::events::{EventChannel, Subscriber};
+use crate::traits::clock::Clock;
+use crate::traits::time_sync::{TimeSample, TimeSync};
+use crate::traits::timer::Timer;
+
+pub mod sntp;
+pub mod timezone;
+
+pub const TIME_EVENTS_CAPACITY: usize = 4;
+const SERVER_MAX_BYTES: usize = 64;
+const FAILURE_MESSAGE_MAX_BYTES: usize = 64;
+
+pub type SharedClock = Arc<Mutex<dyn Clock + Send>>;
+
+// Counts on from the last time it was set
+#[derive(Debug, Default)]
+pub struct MonotonicClock {
+    set_at: Option<(Instant, Duration)>,
+}
+
+impl MonotonicClock {
+    pub fn new() -> MonotonicClock {
+        MonotonicClock::default()
+    }
+}
+
+impl Clock for MonotonicClock {
+    fn utc(&self) -> Option<Duration> {
+        self.set_at.map(|(instant, utc)| utc + instant.elapsed())
+    }
+
+    fn set_utc(&mut self, utc: Duration) {
+        self.set_at = Some((Instant::now(), utc));
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeSyncState {
+    Unsynced,
+    Synced,
+    Failed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TimeSyncEvent {
+    Synced {
+        server: String<SERVER_MAX_BYTES>,
+        // how far ahead the clock was, None when it had not been set
+        offset_micros: Option<i64>,
+        round_trip: Duration,
+    },
+    Failed {
+        message: String<FAILURE_MESSAGE_MAX_BYTES>,
+    },
+}
+
+impl TimeSyncEvent {
+    pub fn state(&self) -> TimeSyncState {
+        match self {
+            TimeSyncEvent::Synced { .. } => TimeSyncState::Synced,
+            TimeSyncEvent::Failed { .. } => TimeSyncState::Failed,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TimeSyncPolicy {
+    pub interval: Duration,
+    // sooner than the interval after a failure
+    pub retry_interval: Duration,
+}
+
+impl Default for TimeSyncPolicy {
+    fn default() -> Self {
+        TimeSyncPolicy {
+            interval: Duration::from_secs(3600),
+            retry_interval: Duration::from_secs(60),
+        }
+    }
+}
+
+pub struct TimeKeeper<C, T> {
+    clock: C,
+    time_sync: T,
+    policy: TimeSyncPolicy,
+    // timer time of the last attempt and whether it worked
+    last_attempt: Option<(Duration, bool)>,
+    last_sample: Option<TimeSample>,
+    drift_ppm: Option<i64>,
+    events: EventChannel<TimeSyncEvent, TIME_EVENTS_CAPACITY>,
+}
+
+impl<C: Clock, T: TimeSync> TimeKeeper<C, T> {
+    pub fn new(clock: C, time_sync: T) -> TimeKeeper<C, T> {
+        TimeKeeper {
+            clock,
+            time_sync,
+            policy: TimeSyncPolicy::default(),
+            last_attempt: None,
+            last_sample: None,
+            drift_ppm: None,
+            events: EventChannel::new(),
+        }
+    }
+
+    pub fn with_policy(mut self, policy: TimeSyncPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    pub fn state(&self) -> TimeSyncState {
+        self.events.latest().map_or(TimeSyncState::Unsynced, |event| event.state())
+    }
+
+    pub fn events(&self) -> EventChannel<TimeSyncEvent, TIME_EVENTS_CAPACITY> {
+        self.events.clone()
+    }
+
+    pub fn subscribe(&self) -> Subscriber<TimeSyncEvent, TIME_EVENTS_CAPACITY> {
+        self.events.subscribe()
+    }
+
+    pub fn last_sample(&self) -> Option<&TimeSample> {
+        self.last_sample.as_ref()
+    }
+
+    // How fast the clock ran between the last two syncs, positive when ahead
+    pub fn drift_ppm(&self) -> Option<i64> {
+        self.drift_ppm
+    }
+
+    pub async fn sync(&mut self, servers: &[&str]) -> Result<(), T::Error> {
+        let sample = match self.time_sync.query(servers).await {
+            Ok(sample) => sample,
+            Err(error) => {
+                let message = truncated(&error.to_string());
+                warn!("Time sync failed: {}", message);
+                self.events.publish(TimeSyncEvent::Failed { message });
+                return Err(error);
+            }
+        };
+        let offset_micros = self.clock.utc().map(|local| local.as_micros() as i64 - sample.utc.as_micros() as i64);
+        if let (Some(offset_micros), Some(last_sample)) = (offset_micros, &self.last_sample) {
+            let elapsed_micros = sample.utc.saturating_sub(last_sample.utc).as_micros() as i64;
+            if elapsed_micros > 0 {
+                self.drift_ppm = Some(offset_micros.saturating_mul(1_000_000) / elapsed_micros);
+            }
+        }
+        info!("Time synced from {} (offset {:?}us, round trip {:?})", sample.server, offset_micros, sample.round_trip);
+        self.clock.set_utc(sample.utc);
+        let server = truncated(&sample.server);
+        self.events.publish(TimeSyncEvent::Synced {
+            server,
+            offset_micros,
+            round_trip: sample.round_trip,
+        });
+        self.last_sample = Some(sample);
+        Ok(())
+    }
+
+    pub async fn sync_if_due<Ti: Timer>(&mut self, timer: &Ti, servers: &[&str]) {
+        let now = timer.now();
+        if let Some((at, synced)) = self.last_attempt {
+            let interval = if synced { self.policy.interval } else { self.policy.retry_interval };
+            if now.saturating_sub(at) < interval {
+                return;
+            }
+        }
+        let synced = self.sync(servers).await.is_ok();
+        self.last_attempt = Some((now, synced));
+    }
+}
+
+// Cut at a character boundary, whole characters that fit are kept
+fn truncated<const N: usize>(text: &str) -> String<N> {
+    let mut truncated = String::new();
+    for c in text.chars() {
+        if truncated.push(c).is_err() {
+            break;
+        }
+    }
+    truncated
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::mocks::block_on::block_on;
+    use crate::mocks::mock_time_sync::MockTimeSync;
+    use crate::mocks::mock_timer::MockTimer;
+    use crate::time::{MonotonicClock, TimeKeeper, TimeSyncEvent, TimeSyncPolicy, TimeSyncState};
+    use crate::time::sntp::SntpClient;
+    use crate::mocks::mock_sntp_server::{stand_in_server, SERVER_TIME};
+    use crate::traits::clock::Clock;
+    use crate::traits::timer::Timer;
+
+    #[test]
+    fn sets_the_clock_and_tracks_drift() {
+        // the server moves on 1000s between syncs while the clock barely does, so it runs slow
+        let address = stand_in_server(&[SERVER_TIME, SERVER_TIME + Duration::from_secs(1000)], 1).to_string();
+        let mut clock = MonotonicClock::new();
+        clock.set_utc(SERVER_TIME + Duration::from_secs(60));
+        let mut keeper = TimeKeeper::new(clock, SntpClient::new(Duration::from_secs(2)));
+        block_on(keeper.sync(&[&address])).unwrap();
+        assert_eq!(keeper.state(), TimeSyncState::Synced);
+        let utc = keeper.clock().utc().unwrap();
+        assert!(utc >= SERVER_TIME && utc < SERVER_TIME + Duration::from_secs(1));
+        match keeper.events().latest().unwrap() {
+            TimeSyncEvent::Synced { offset_micros: Some(offset_micros), .. } => assert!((60_000_000..61_000_000).contains(&offset_micros)),
+            event => panic!("Unexpected event: {:?}", event),
+        }
+        assert_eq!(keeper.drift_ppm(), None);
+        block_on(keeper.sync(&[&address])).unwrap();
+        assert!((-1_000_000..-999_000).contains(&keeper.drift_ppm().unwrap()));
+    }
+
+    #[test]
+    fn truncates_long_server_names_between_characters() {
+        // 63 bytes and then a two byte character that would end past SERVER_MAX_BYTES
+        let server = format!("{}é.example", "a".repeat(63));
+        let mut keeper = TimeKeeper::new(MonotonicClock::new(), MockTimeSync::new().answer(&server, SERVER_TIME));
+        block_on(keeper.sync(&[&server])).unwrap();
+        match keeper.events().latest().unwrap() {
+            TimeSyncEvent::Synced { server: reported, .. } => assert_eq!(reported.as_str(), "a".repeat(63)),
+            event => panic!("Unexpected event: {:?}", event),
+        }
+    }
+
+    #[test]
+    fn retries_sooner_after_failure() {
+        let mut keeper = TimeKeeper::new(MonotonicClock::new(), SntpClient::new(Duration::from_millis(100)))
+            .with_policy(TimeSyncPolicy {
+                interval: Duration::from_secs(600),
+                retry_interval: Duration::from_secs(10),
+            });
+        let mut timer = MockTimer::new();
+        block_on(keeper.sync_if_due(&timer, &[]));
+        assert_eq!(keeper.state(), TimeSyncState::Failed);
+        block_on(timer.after(Duration::from_secs(5)));
+        let address = stand_in_server(&[SERVER_TIME], 1).to_string();
+        block_on(keeper.sync_if_due(&timer, &[&address]));
+        assert_eq!(keeper.state(), TimeSyncState::Failed);
+        block_on(timer.after(Duration::from_secs(5)));
+        block_on(keeper.sync_if_due(&timer, &[&address]));
+        assert_eq!(keeper.state(), TimeSyncState::Synced);
+        assert!(keeper.clock().utc().is_some());
+    }
+}
@@ -0,0 +1,173 @@
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+use log::*;
+use thiserror::Error;
+
+use crate::resolver::{resolve, split_host_port};
+use crate::traits::time_sync::{TimeSample, TimeSync};
+use crate::workers;
+
+pub const SNTP_PORT: u16 = 123;
+const PACKET_BYTES: usize = 48;
+// version 4, client
+const CLIENT_HEADER: u8 = 0x23;
+const MODE_SERVER: u8 = 4;
+const MODE_BROADCAST: u8 = 5;
+// from 1900 to 1970
+pub(crate) const NTP_TO_UNIX_SECONDS: u64 = 2_208_988_800;
+
+#[derive(Error, Debug)]
+pub enum SntpError {
+    #[error("No time servers configured")]
+    NoServers,
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    #[error("Server answered with an invalid packet")]
+    BadResponse,
+    // stratum 0, eg. RATE when polled too often
+    #[error("Server refused with kiss code {0}")]
+    KissOfDeath(String),
+}
+
+pub struct SntpClient {
+    timeout: Duration,
+    // distinguishes the answer to this request from stale or spoofed ones
+    nonce: u64,
+}
+
+impl SntpClient {
+    pub fn new(timeout: Duration) -> SntpClient {
+        SntpClient {
+            timeout,
+            nonce: 0x9e37_79b9_7f4a_7c15,
+        }
+    }
+
+    // One deadline covers the lookup and the answer, unrelated packets don't extend it
+    async fn query_server(&mut self, server: &str) -> Result<TimeSample, SntpError> {
+        let started = Instant::now();
+        let (host, port) = split_host_port(server)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid server address"))?;
+        let address = resolve(host, port.unwrap_or(SNTP_PORT), self.timeout).await?.into_iter().next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "server did not resolve"))?;
+        self.nonce = self.nonce.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        let mut request = [0_u8; PACKET_BYTES];
+        request[0] = CLIENT_HEADER;
+        request[40..48].copy_from_slice(&self.nonce.to_be_bytes());
+        let deadline = started + self.timeout;
+        let (response, round_trip) = workers::sockets().run(move || exchange(address, &request, deadline)).await?;
+        let mode = response[0] & 0x7;
+        if mode != MODE_SERVER && mode != MODE_BROADCAST {
+            return Err(SntpError::BadResponse);
+        }
+        let stratum = response[1];
+        if stratum == 0 {
+            return Err(SntpError::KissOfDeath(String::from_utf8_lossy(&response[12..16]).into_owned()));
+        }
+        let received = timestamp(&response[32..40]).ok_or(SntpError::BadResponse)?;
+        let transmitted = timestamp(&response[40..48]).ok_or(SntpError::BadResponse)?;
+        // half of the time spent on the network, not counting what the server took to answer
+        let network_delay = round_trip.saturating_sub(transmitted.saturating_sub(received));
+        Ok(TimeSample {
+            server: server.to_string(),
+            utc: transmitted + network_delay / 2,
+            round_trip,
+            stratum,
+        })
+    }
+}
+
+impl TimeSync for SntpClient {
+    type Error = SntpError;
+
+    async fn query(&mut self, servers: &[&str]) -> Result<TimeSample, Self::Error> {
+        let mut last_error = SntpError::NoServers;
+        for server in servers {
+            match self.query_server(server).await {
+                Ok(sample) => return Ok(sample),
+                Err(error) => {
+                    warn!("Time server {} failed: {}", server, error);
+                    last_error = error;
+                }
+            }
+        }
+        Err(last_error)
+    }
+}
+
+// Blocks until the answer to request arrives, with the socket timing out at the deadline
+fn exchange(address: SocketAddr, request: &[u8; PACKET_BYTES], deadline: Instant) -> io::Result<([u8; PACKET_BYTES], Duration)> {
+    let socket = UdpSocket::bind(if address.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" })?;
+    let sent = Instant::now();
+    socket.send_to(request, address)?;
+    let mut response = [0_u8; PACKET_BYTES];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "server did not answer"));
+        }
+        socket.set_read_timeout(Some(remaining))?;
+        match socket.recv_from(&mut response) {
+            Ok((len, from)) if from == address && len == PACKET_BYTES && response[24..32] == request[40..48] => {
+                return Ok((response, sent.elapsed()));
+            }
+            Ok((_, from)) => debug!("Ignoring unexpected packet from {}", from),
+            // which of the two depends on the platform
+            Err(error) if matches!(error.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {}
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+// Seconds and fraction since 1900, None when unset or before 1970
+fn timestamp(bytes: &[u8]) -> Option<Duration> {
+    let seconds = u32::from_be_bytes(bytes[..4].try_into().unwrap()) as u64;
+    let fraction = u32::from_be_bytes(bytes[4..].try_into().unwrap()) as u64;
+    let seconds = seconds.checked_sub(NTP_TO_UNIX_SECONDS)?;
+    Some(Duration::from_secs(seconds) + Duration::from_nanos((fraction * 1_000_000_000) >> 32))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::net::UdpSocket;
+    use std::time::{Duration, Instant};
+
+    use crate::mocks::block_on::block_on;
+    use crate::mocks::mock_sntp_server::{stand_in_server, SERVER_TIME};
+    use crate::time::sntp::{SntpClient, SntpError};
+    use crate::traits::time_sync::TimeSync;
+
+    #[test]
+    fn queries_a_server() {
+        let address = stand_in_server(&[SERVER_TIME], 2).to_string();
+        let mut client = SntpClient::new(Duration::from_secs(2));
+        let sample = block_on(client.query(&[&address])).unwrap();
+        assert_eq!(sample.stratum, 2);
+        assert!(sample.utc >= SERVER_TIME && sample.utc < SERVER_TIME + Duration::from_secs(1));
+        assert_eq!(sample.server, address);
+    }
+
+    #[test]
+    fn moves_on_to_the_next_server() {
+        let refusing = stand_in_server(&[SERVER_TIME], 0).to_string();
+        let answering = stand_in_server(&[SERVER_TIME], 1).to_string();
+        let mut client = SntpClient::new(Duration::from_secs(2));
+        assert!(matches!(block_on(client.query(&[&refusing])), Err(SntpError::KissOfDeath(code)) if code == "RATE"));
+        let refusing = stand_in_server(&[SERVER_TIME], 0).to_string();
+        assert_eq!(block_on(client.query(&[&refusing, &answering])).unwrap().server, answering);
+        assert!(matches!(block_on(client.query(&[])), Err(SntpError::NoServers)));
+    }
+
+    #[test]
+    fn gives_up_on_a_silent_server() {
+        let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = silent.local_addr().unwrap().to_string();
+        let mut client = SntpClient::new(Duration::from_millis(100));
+        let started = Instant::now();
+        assert!(matches!(block_on(client.query(&[&address])), Err(SntpError::Io(error)) if error.kind() == io::ErrorKind::TimedOut));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+}
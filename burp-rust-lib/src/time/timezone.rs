@@ -0,0 +1,396 @@
+use std::time::Duration;
+
+use heapless::String;
+use thiserror::Error;
+
+use crate::traits::clock::Clock;
+
+pub const TZ_MAX_BYTES: usize = 64;
+const NAME_MAX_BYTES: usize = 16;
+const SECONDS_PER_DAY: i64 = 86_400;
+const SECONDS_PER_HOUR: i32 = 3_600;
+// when a rule leaves out the time of day
+const DEFAULT_RULE_TIME: i32 = 2 * SECONDS_PER_HOUR;
+// what glibc assumes for a DST name without rules, the current US ones
+const DEFAULT_RULES: &str = "M3.2.0,M11.1.0";
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum TimeZoneError {
+    #[error("Zone must fit in {TZ_MAX_BYTES} bytes")]
+    TooLong,
+    #[error("Zone name must be at least 3 letters, or quoted in <>")]
+    InvalidName,
+    #[error("Offset must be [+-]hh[:mm[:ss]]")]
+    InvalidOffset,
+    #[error("Rule must be Jn, n or Mm.w.d with an optional /time")]
+    InvalidRule,
+    #[error("Unexpected text after the zone")]
+    TrailingText,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RuleDate {
+    // 1 to 365, February 29th is never counted
+    Julian(u16),
+    // 0 to 365, counting February 29th
+    ZeroBasedJulian(u16),
+    // week 5 is the last of that weekday in the month, Sunday is day 0
+    MonthWeekDay { month: u8, week: u8, weekday: u8 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rule {
+    date: RuleDate,
+    // local time of day, may be negative or past midnight
+    time: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Dst {
+    name: String<NAME_MAX_BYTES>,
+    offset: i32,
+    start: Rule,
+    end: Rule,
+}
+
+// Parsed from a POSIX TZ string such as CET-1CEST,M3.5.0,M10.5.0/3
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeZone {
+    name: String<NAME_MAX_BYTES>,
+    // seconds east of UTC, the opposite sign to the TZ string
+    offset: i32,
+    dst: Option<Dst>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalTime {
+    pub year: i32,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub nanosecond: u32,
+    // Sunday is 0
+    pub weekday: u8,
+    // seconds east of UTC
+    pub offset: i32,
+    pub is_dst: bool,
+    pub abbreviation: String<NAME_MAX_BYTES>,
+}
+
+impl TimeZone {
+    pub fn utc() -> TimeZone {
+        TimeZone {
+            name: String::from("UTC"),
+            offset: 0,
+            dst: None,
+        }
+    }
+
+    pub fn parse(tz: &str) -> Result<TimeZone, TimeZoneError> {
+        if tz.len() > TZ_MAX_BYTES {
+            return Err(TimeZoneError::TooLong);
+        }
+        let mut parser = Parser { rest: tz };
+        let name = parser.name()?;
+        let offset = -parser.time(TimeZoneError::InvalidOffset)?;
+        let dst = if parser.rest.is_empty() {
+            None
+        } else {
+            let dst_name = parser.name()?;
+            let dst_offset = if parser.rest.is_empty() || parser.rest.starts_with(',') {
+                offset + SECONDS_PER_HOUR
+            } else {
+                -parser.time(TimeZoneError::InvalidOffset)?
+            };
+            if parser.rest.is_empty() {
+                parser.rest = DEFAULT_RULES;
+            } else if !parser.eat(',') {
+                return Err(TimeZoneError::TrailingText);
+            }
+            let start = parser.rule()?;
+            if !parser.eat(',') {
+                return Err(TimeZoneError::InvalidRule);
+            }
+            let end = parser.rule()?;
+            Some(Dst {
+                name: dst_name,
+                offset: dst_offset,
+                start,
+                end,
+            })
+        };
+        if !parser.rest.is_empty() {
+            return Err(TimeZoneError::TrailingText);
+        }
+        Ok(TimeZone { name, offset, dst })
+    }
+
+    // Seconds east of UTC in effect at that moment
+    pub fn offset_at(&self, utc: Duration) -> i32 {
+        match &self.dst {
+            Some(dst) if self.is_dst(utc) => dst.offset,
+            _ => self.offset,
+        }
+    }
+
+    pub fn is_dst(&self, utc: Duration) -> bool {
+        let Some(dst) = &self.dst else {
+            return false;
+        };
+        let utc = utc.as_secs() as i64;
+        let (year, _, _) = civil_from_days((utc + self.offset as i64).div_euclid(SECONDS_PER_DAY));
+        // rule times are given in the local time in effect before each transition
+        let start = rule_seconds(&dst.start, year) - self.offset as i64;
+        let end = rule_seconds(&dst.end, year) - dst.offset as i64;
+        if start < end {
+            start <= utc && utc < end
+        } else {
+            // southern hemisphere, DST spans the new year
+            utc < end || start <= utc
+        }
+    }
+
+    pub fn local_time(&self, utc: Duration) -> LocalTime {
+        let is_dst = self.is_dst(utc);
+        let (offset, abbreviation) = match &self.dst {
+            Some(dst) if is_dst => (dst.offset, &dst.name),
+            _ => (self.offset, &self.name),
+        };
+        let local = utc.as_secs() as i64 + offset as i64;
+        let days = local.div_euclid(SECONDS_PER_DAY);
+        let seconds = local.rem_euclid(SECONDS_PER_DAY);
+        let (year, month, day) = civil_from_days(days);
+        LocalTime {
+            year,
+            month,
+            day,
+            hour: (seconds / 3600) as u8,
+            minute: (seconds / 60 % 60) as u8,
+            second: (seconds % 60) as u8,
+            nanosecond: utc.subsec_nanos(),
+            // 1970-01-01 was a Thursday
+            weekday: (days + 4).rem_euclid(7) as u8,
+            offset,
+            is_dst,
+            abbreviation: abbreviation.clone(),
+        }
+    }
+
+    // None until the clock has been set
+    pub fn now<C: Clock>(&self, clock: &C) -> Option<LocalTime> {
+        clock.utc().map(|utc| self.local_time(utc))
+    }
+}
+
+struct Parser<'a> {
+    rest: &'a str,
+}
+
+impl Parser<'_> {
+    fn eat(&mut self, c: char) -> bool {
+        match self.rest.strip_prefix(c) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Either alphabetic or quoted, eg. <+0530>
+    fn name(&mut self) -> Result<String<NAME_MAX_BYTES>, TimeZoneError> {
+        let name = if self.eat('<') {
+            let end = self.rest.find('>').ok_or(TimeZoneError::InvalidName)?;
+            let name = &self.rest[..end];
+            self.rest = &self.rest[end + 1..];
+            name
+        } else {
+            let end = self.rest.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(self.rest.len());
+            let name = &self.rest[..end];
+            self.rest = &self.rest[end..];
+            name
+        };
+        if name.len() < 3 || name.len() > NAME_MAX_BYTES {
+            return Err(TimeZoneError::InvalidName);
+        }
+        Ok(String::from(name))
+    }
+
+    // [+-]hh[:mm[:ss]] in seconds
+    fn time(&mut self, error: TimeZoneError) -> Result<i32, TimeZoneError> {
+        let sign = if self.eat('-') {
+            -1
+        } else {
+            self.eat('+');
+            1
+        };
+        let mut seconds = 0;
+        for (index, scale) in [SECONDS_PER_HOUR, 60, 1].into_iter().enumerate() {
+            if index > 0 && !self.eat(':') {
+                break;
+            }
+            let max = if index == 0 { 167 } else { 59 };
+            seconds += self.number(max).ok_or(error.clone())? as i32 * scale;
+        }
+        Ok(sign * seconds)
+    }
+
+    fn number(&mut self, max: u16) -> Option<u16> {
+        let end = self.rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(self.rest.len());
+        let number = self.rest[..end].parse().ok().filter(|number| *number <= max)?;
+        self.rest = &self.rest[end..];
+        Some(number)
+    }
+
+    fn rule(&mut self) -> Result<Rule, TimeZoneError> {
+        let date = if self.eat('J') {
+            RuleDate::Julian(self.number(365).filter(|day| *day >= 1).ok_or(TimeZoneError::InvalidRule)?)
+        } else if self.eat('M') {
+            let month = self.number(12).filter(|month| *month >= 1).ok_or(TimeZoneError::InvalidRule)? as u8;
+            let week = self.eat('.').then(|| self.number(5)).flatten().filter(|week| *week >= 1).ok_or(TimeZoneError::InvalidRule)? as u8;
+            let weekday = self.eat('.').then(|| self.number(6)).flatten().ok_or(TimeZoneError::InvalidRule)? as u8;
+            RuleDate::MonthWeekDay { month, week, weekday }
+        } else {
+            RuleDate::ZeroBasedJulian(self.number(365).ok_or(TimeZoneError::InvalidRule)?)
+        };
+        let time = if self.eat('/') { self.time(TimeZoneError::InvalidRule)? } else { DEFAULT_RULE_TIME };
+        Ok(Rule { date, time })
+    }
+}
+
+// Seconds from the epoch to the rule's transition, in the local time it is given in
+fn rule_seconds(rule: &Rule, year: i32) -> i64 {
+    let year_start = days_from_civil(year, 1, 1);
+    let day = match rule.date {
+        RuleDate::Julian(day) => {
+            let leap_day = is_leap_year(year) && day >= 60;
+            year_start + day as i64 - 1 + leap_day as i64
+        }
+        RuleDate::ZeroBasedJulian(day) => year_start + day as i64,
+        RuleDate::MonthWeekDay { month, week, weekday } => {
+            let first = days_from_civil(year, month, 1);
+            let first_weekday = (first + 4).rem_euclid(7);
+            let mut day = first + (weekday as i64 - first_weekday).rem_euclid(7) + (week as i64 - 1) * 7;
+            while day >= first + days_in_month(year, month) as i64 {
+                day -= 7;
+            }
+            day
+        }
+    };
+    day * SECONDS_PER_DAY + rule.time as i64
+}
+
+fn is_leap_year(year: i32) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: i32, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Days since 1970-01-01 in the proleptic Gregorian calendar
+fn days_from_civil(year: i32, month: u8, day: u8) -> i64 {
+    let year = year as i64 - (month <= 2) as i64;
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i32, u8, u8) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u8;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 } as u8;
+    let year = (year_of_era + era * 400 + (month <= 2) as i64) as i32;
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::time::timezone::{civil_from_days, days_from_civil, TimeZone, TimeZoneError};
+
+    fn utc(year: i32, month: u8, day: u8, hour: u64, minute: u64) -> Duration {
+        Duration::from_secs(days_from_civil(year, month, day) as u64 * 86_400 + hour * 3600 + minute * 60)
+    }
+
+    fn local(tz: &TimeZone, utc: Duration) -> (u8, u8, u8, u8, bool, String) {
+        let local = tz.local_time(utc);
+        (local.month, local.day, local.hour, local.minute, local.is_dst, local.abbreviation.to_string())
+    }
+
+    #[test]
+    fn converts_calendar_days() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2024, 2, 29), 19_782);
+        assert_eq!(civil_from_days(19_782), (2024, 2, 29));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+    }
+
+    #[test]
+    fn follows_european_dst() {
+        let tz = TimeZone::parse("CET-1CEST,M3.5.0,M10.5.0/3").unwrap();
+        assert_eq!(local(&tz, utc(2024, 3, 31, 0, 59)), (3, 31, 1, 59, false, "CET".into()));
+        assert_eq!(local(&tz, utc(2024, 3, 31, 1, 0)), (3, 31, 3, 0, true, "CEST".into()));
+        assert_eq!(local(&tz, utc(2024, 10, 27, 0, 59)), (10, 27, 2, 59, true, "CEST".into()));
+        assert_eq!(local(&tz, utc(2024, 10, 27, 1, 0)), (10, 27, 2, 0, false, "CET".into()));
+        assert_eq!(tz.local_time(utc(2024, 7, 1, 12, 0)).offset, 7200);
+        assert_eq!(tz.local_time(utc(2024, 7, 1, 12, 0)).weekday, 1);
+    }
+
+    #[test]
+    fn follows_us_and_default_rules() {
+        for tz in ["EST5EDT,M3.2.0,M11.1.0", "EST5EDT"] {
+            let tz = TimeZone::parse(tz).unwrap();
+            assert_eq!(local(&tz, utc(2024, 3, 10, 6, 59)), (3, 10, 1, 59, false, "EST".into()));
+            assert_eq!(local(&tz, utc(2024, 3, 10, 7, 0)), (3, 10, 3, 0, true, "EDT".into()));
+            assert_eq!(local(&tz, utc(2024, 11, 3, 5, 59)), (11, 3, 1, 59, true, "EDT".into()));
+            assert_eq!(local(&tz, utc(2024, 11, 3, 6, 0)), (11, 3, 1, 0, false, "EST".into()));
+        }
+    }
+
+    #[test]
+    fn follows_southern_hemisphere_dst() {
+        let tz = TimeZone::parse("AEST-10AEDT,M10.1.0,M4.1.0/3").unwrap();
+        assert!(tz.is_dst(utc(2024, 1, 15, 0, 0)));
+        assert_eq!(local(&tz, utc(2024, 4, 6, 15, 59)), (4, 7, 2, 59, true, "AEDT".into()));
+        assert_eq!(local(&tz, utc(2024, 4, 6, 16, 0)), (4, 7, 2, 0, false, "AEST".into()));
+        assert_eq!(local(&tz, utc(2024, 10, 5, 16, 0)), (10, 6, 3, 0, true, "AEDT".into()));
+        assert!(tz.is_dst(utc(2024, 12, 31, 23, 0)));
+    }
+
+    #[test]
+    fn parses_fixed_offsets_and_julian_rules() {
+        let tz = TimeZone::parse("<+0530>-5:30").unwrap();
+        assert_eq!(local(&tz, utc(2024, 1, 1, 20, 0)), (1, 2, 1, 30, false, "+0530".into()));
+        assert_eq!(TimeZone::parse("UTC0").unwrap().offset_at(utc(2024, 6, 1, 0, 0)), 0);
+        let tz = TimeZone::parse("XST3XDT,J60/0,300").unwrap();
+        assert!(!tz.is_dst(utc(2024, 3, 1, 2, 59)));
+        assert!(tz.is_dst(utc(2024, 3, 1, 3, 0)));
+        assert_eq!(tz.offset_at(utc(2024, 3, 1, 3, 0)), -7200);
+    }
+
+    #[test]
+    fn rejects_invalid_zones() {
+        assert_eq!(TimeZone::parse("U0"), Err(TimeZoneError::InvalidName));
+        assert_eq!(TimeZone::parse("CET"), Err(TimeZoneError::InvalidOffset));
+        assert_eq!(TimeZone::parse("CET-1CEST,M13.5.0,M10.5.0"), Err(TimeZoneError::InvalidRule));
+        assert_eq!(TimeZone::parse("CET-1CEST,M3.5.0"), Err(TimeZoneError::InvalidRule));
+        assert_eq!(TimeZone::parse("CET-1CEST,M3.5.0,M10.5.0/3x"), Err(TimeZoneError::TrailingText));
+    }
+}
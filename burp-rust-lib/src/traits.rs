@@ -1,4 +1,12 @@
 pub mod storage;
+pub mod async_storage;
+pub mod net_if;
 pub mod wifi;
 pub mod mdns;
 pub mod read_write;
+pub mod timer;
+pub mod async_read_write;
+pub mod byte_stream;
+pub mod clock;
+pub mod time_sync;
+pub mod ping;
@@ -0,0 +1,7 @@
+use std::error::Error;
+
+pub trait AsyncReadWrite {
+    type Error: Error;
+    async fn read(&mut self) -> Result<(), Self::Error>;
+    async fn write(&mut self) -> Result<(), Self::Error>;
+}
@@ -0,0 +1,25 @@
+use std::error::Error;
+use std::io;
+
+// A serial style transport that never blocks on reads
+pub trait ByteStream {
+    type Error: Error;
+    // returns 0 when no bytes are available yet
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Self::Error>;
+    fn write_all(&mut self, data: &[u8]) -> Result<(), Self::Error>;
+}
+
+// Lets the network hold a port without being generic over its driver
+pub type SerialPort = Box<dyn ByteStream<Error = io::Error>>;
+
+impl<B: ByteStream + ?Sized> ByteStream for Box<B> {
+    type Error = B::Error;
+
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Self::Error> {
+        (**self).read(buffer)
+    }
+
+    fn write_all(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        (**self).write_all(data)
+    }
+}
@@ -0,0 +1,19 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+pub trait Clock {
+    // since the unix epoch, None until the clock has been set
+    fn utc(&self) -> Option<Duration>;
+    fn set_utc(&mut self, utc: Duration);
+}
+
+// Lets the clock be read elsewhere while it is being kept in sync
+impl<C: Clock + ?Sized> Clock for Arc<Mutex<C>> {
+    fn utc(&self) -> Option<Duration> {
+        self.lock().unwrap().utc()
+    }
+
+    fn set_utc(&mut self, utc: Duration) {
+        self.lock().unwrap().set_utc(utc)
+    }
+}
@@ -3,13 +3,14 @@ use std::time::Duration;
 
 use embedded_svc::ipv4::IpAddr;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub enum Protocol {
+    #[default]
     V4,
     V6,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Interface {
     STA,
     AP,
@@ -46,6 +47,14 @@ pub trait Mdns {
         port: u16,
         txt: &[(&str, &str)],
     ) -> Result<(), Self::Error>;
+    fn remove_service(
+        &mut self,
+        service_type: &str,
+        proto: &str,
+    ) -> Result<(), Self::Error>;
+    fn remove_services(&mut self) -> Result<(), Self::Error>;
+    // Shuts the responder down, the other calls fail afterwards
+    fn free(&mut self);
     fn query_ptr(
         &self,
         service_type: &str,
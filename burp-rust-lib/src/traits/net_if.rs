@@ -0,0 +1,16 @@
+use std::error::Error;
+
+use embedded_svc::ipv4::IpInfo;
+
+// A link that can carry traffic, such as wifi or ethernet
+pub trait NetIf {
+    type Error: Error;
+    async fn start(&mut self) -> Result<(), Self::Error>;
+    async fn stop(&mut self) -> Result<(), Self::Error>;
+    async fn wait_netif_up(&self) -> Result<(), Self::Error>;
+    // associated, or the cable plugged in
+    fn is_connected(&self) -> Result<bool, Self::Error>;
+    fn get_ip_info(&self) -> Result<IpInfo, Self::Error>;
+    // Used for traffic no other route matches
+    fn set_default_route(&mut self) -> Result<(), Self::Error>;
+}
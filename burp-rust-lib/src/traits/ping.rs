@@ -0,0 +1,43 @@
+use std::error::Error;
+use std::net::IpAddr;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PingStats {
+    pub sent: u16,
+    pub received: u16,
+    pub min: Option<Duration>,
+    pub max: Option<Duration>,
+    total: Duration,
+}
+
+impl PingStats {
+    // None when the reply did not arrive in time
+    pub fn add(&mut self, round_trip: Option<Duration>) {
+        self.sent += 1;
+        let Some(round_trip) = round_trip else {
+            return;
+        };
+        self.received += 1;
+        self.total += round_trip;
+        self.min = Some(self.min.map_or(round_trip, |min| min.min(round_trip)));
+        self.max = Some(self.max.map_or(round_trip, |max| max.max(round_trip)));
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        (self.received > 0).then(|| self.total / self.received as u32)
+    }
+
+    pub fn loss_percent(&self) -> u8 {
+        match self.sent {
+            0 => 0,
+            sent => (100 - self.received as u32 * 100 / sent as u32) as u8,
+        }
+    }
+}
+
+pub trait Ping {
+    type Error: Error;
+    // Waits up to the timeout for each reply before sending the next
+    async fn ping(&mut self, address: IpAddr, count: u16, timeout: Duration) -> Result<PingStats, Self::Error>;
+}
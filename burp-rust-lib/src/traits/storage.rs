@@ -1,9 +1,38 @@
 use std::error::Error;
 
-pub trait Storage {
-    type Error: Error;
+pub const NAMESPACE_NAME_MAX_BYTES: usize = 15;
+pub const MAX_NAMESPACES: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct NamespaceStats {
+    pub name: heapless::String<NAMESPACE_NAME_MAX_BYTES>,
+    pub used_entries: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct StorageStats {
+    pub used_entries: usize,
+    pub free_entries: usize,
+    pub total_entries: usize,
+    pub namespace_count: usize,
+    // of the namespace the storage was opened with
+    pub namespace_used_entries: usize,
+    // every namespace in the partition, only the first MAX_NAMESPACES are listed
+    pub namespaces: heapless::Vec<NamespaceStats, MAX_NAMESPACES>,
+}
+
+impl StorageStats {
+    pub fn used_percent(&self) -> u8 {
+        (self.used_entries * 100).checked_div(self.total_entries).map_or(100, |percent| percent as u8)
+    }
+}
+
+pub trait Storage: Send + 'static {
+    type Error: Error + Send + 'static;
+    fn stats(&self) -> Result<StorageStats, Self::Error>;
     fn get_blob<'a>(&self, name: &str, buf: &'a mut [u8]) -> Result<Option<&'a [u8]>, Self::Error>;
     fn set_blob(&mut self, name: &str, val: &[u8]) -> Result<(), Self::Error>;
+    fn remove(&mut self, name: &str) -> Result<bool, Self::Error>;
     fn get_u8(&self, name: &str) -> Result<Option<u8>, Self::Error>;
     fn set_u8(&self, name: &str, val: u8) -> Result<(), Self::Error>;
     fn get_i8(&self, name: &str) -> Result<Option<i8>, Self::Error>;
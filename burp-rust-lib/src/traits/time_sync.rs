@@ -0,0 +1,17 @@
+use std::error::Error;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeSample {
+    pub server: String,
+    // since the unix epoch, estimated for when the answer arrived
+    pub utc: Duration,
+    pub round_trip: Duration,
+    pub stratum: u8,
+}
+
+pub trait TimeSync {
+    type Error: Error;
+    // Asks each server in turn until one answers
+    async fn query(&mut self, servers: &[&str]) -> Result<TimeSample, Self::Error>;
+}
@@ -0,0 +1,7 @@
+use std::time::Duration;
+
+pub trait Timer {
+    // monotonic time since boot
+    fn now(&self) -> Duration;
+    async fn after(&mut self, duration: Duration);
+}
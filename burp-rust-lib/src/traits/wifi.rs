@@ -1,14 +1,22 @@
-use std::error::Error;
+use std::ops::RangeInclusive;
 
-use embedded_svc::ipv4::IpInfo;
+use embedded_svc::ipv4::Ipv6Addr;
 use embedded_svc::wifi::{AccessPointInfo, Configuration};
 
-pub trait Wifi {
-    type Error: Error;
+use crate::traits::net_if::NetIf;
+
+pub trait Wifi: NetIf {
     fn set_configuration(&mut self, conf: &Configuration) -> Result<(), Self::Error>;
-    async fn start(&mut self) -> Result<(), Self::Error>;
     async fn scan(&mut self) -> Result<Vec<AccessPointInfo>, Self::Error>;
     async fn connect(&mut self) -> Result<(), Self::Error>;
-    async fn wait_netif_up(&self) -> Result<(), Self::Error>;
-    fn get_ip_info(&self) -> Result<IpInfo, Self::Error>;
+    async fn disconnect(&mut self) -> Result<(), Self::Error>;
+    // Of the access point currently connected to
+    fn ap_info(&self) -> Result<AccessPointInfo, Self::Error>;
+    fn rssi(&self) -> Result<i8, Self::Error>;
+    // wifi_err_reason_t of the last time the station lost its access point, None once reported
+    fn disconnect_reason(&self) -> Option<u16>;
+    // link-local and SLAAC addresses of the station interface
+    fn ipv6_addresses(&self) -> Result<Vec<Ipv6Addr>, Self::Error>;
+    // allowed by the regulatory domain of the configured country
+    fn channels(&self) -> Result<RangeInclusive<u8>, Self::Error>;
 }
@@ -0,0 +1,28 @@
+use std::sync::OnceLock;
+
+use crate::futures::Worker;
+
+// NVS operations on the device use far less than this
+const STORAGE_STACK_BYTES: usize = 4096;
+// getaddrinfo goes through lwIP on the device and needs more than the default task stack
+const RESOLVER_STACK_BYTES: usize = 8 * 1024;
+// blocking socket calls only wait on lwIP
+const SOCKETS_STACK_BYTES: usize = 4096;
+
+// Flash access and waiting for the shared storage lock
+pub fn storage() -> &'static Worker {
+    static STORAGE: OnceLock<Worker> = OnceLock::new();
+    STORAGE.get_or_init(|| Worker::new("storage", STORAGE_STACK_BYTES))
+}
+
+// Host name lookups, one at a time so a stuck DNS server holds up a single thread
+pub fn resolver() -> &'static Worker {
+    static RESOLVER: OnceLock<Worker> = OnceLock::new();
+    RESOLVER.get_or_init(|| Worker::new("resolver", RESOLVER_STACK_BYTES))
+}
+
+// Connects and receives with a timeout set on the socket
+pub fn sockets() -> &'static Worker {
+    static SOCKETS: OnceLock<Worker> = OnceLock::new();
+    SOCKETS.get_or_init(|| Worker::new("sockets", SOCKETS_STACK_BYTES))
+}